@@ -0,0 +1,328 @@
+//! Prints the HIR back as source code, which parses to the same HIR.
+//!
+//! Parentheses are only added where the precedence of an operator needs them,
+//! and around the condition of an `if` that passes a scope to a function, since
+//! the parser takes the first scope after a condition as its branch.
+
+use crate::{ControlFlowType, Expr, Hir, If, Literal, Node, Operator, Pattern, SimpleExpr};
+
+use std::fmt::{self, Display, Write};
+
+const INDENT: &str = "    ";
+
+/// how tightly an expression binds, from the loosest to the tightest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Func,
+    Compare,
+    Sum,
+    Product,
+    Cast,
+    App,
+    Basic,
+}
+
+impl Prec {
+    fn of(expr: &Expr<'_, '_, '_>) -> Self {
+        match *expr {
+            Expr::Func { .. } => Self::Func,
+            Expr::BinOp(Operator::Symbol(sym), ..) => match sym {
+                core_tokens::sym!(+) | core_tokens::sym!(-) => Self::Sum,
+                core_tokens::sym!(*) | core_tokens::sym!(/) => Self::Product,
+                _ => Self::Compare,
+            },
+            Expr::BinOp(Operator::Keyword(_), ..) => Self::Compare,
+            Expr::Cast { .. } => Self::Cast,
+            Expr::FuncApp { .. } | Expr::PreOp(..) | Expr::PostOp(..) => Self::App,
+            Expr::Simple(_) | Expr::Tuple(_) | Expr::Scope(_) => Self::Basic,
+        }
+    }
+
+    /// the next tighter level, binary operators are left associative so
+    /// their right operand must bind tighter than the operator
+    fn tighter(self) -> Self {
+        match self {
+            Self::Func => Self::Compare,
+            Self::Compare => Self::Sum,
+            Self::Sum => Self::Product,
+            Self::Product => Self::Cast,
+            Self::Cast => Self::App,
+            Self::App | Self::Basic => Self::Basic,
+        }
+    }
+}
+
+/// whether `expr` passes a scope to a function outside of any parentheses
+fn has_scope_arg(expr: &Expr<'_, '_, '_>) -> bool {
+    match *expr {
+        Expr::FuncApp { ref name_args } => name_args.iter().skip(1).any(|arg| match arg.val {
+            Expr::Scope(_) => true,
+            _ => false,
+        }),
+        Expr::BinOp(_, ref left, ref right) => {
+            has_scope_arg(&left.val) || has_scope_arg(&right.val)
+        }
+        Expr::PreOp(_, ref value) | Expr::PostOp(_, ref value) => has_scope_arg(&value.val),
+        Expr::Cast { ref value, .. } => has_scope_arg(&value.val),
+        Expr::Func { ref body, .. } => has_scope_arg(&body.val),
+        Expr::Simple(_) | Expr::Tuple(_) | Expr::Scope(_) => false,
+    }
+}
+
+struct Printer<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    indent: usize,
+}
+
+impl Printer<'_, '_> {
+    fn newline(&mut self) -> fmt::Result {
+        self.f.write_char('\n')?;
+
+        for _ in 0..self.indent {
+            self.f.write_str(INDENT)?;
+        }
+
+        Ok(())
+    }
+
+    fn scope(&mut self, scope: &[Node<Hir<'_, '_, '_>>]) -> fmt::Result {
+        if scope.is_empty() {
+            return self.f.write_str("{}");
+        }
+
+        self.f.write_char('{')?;
+        self.indent += 1;
+
+        for node in scope {
+            self.newline()?;
+            self.hir(&node.val)?;
+        }
+
+        self.indent -= 1;
+        self.newline()?;
+        self.f.write_char('}')
+    }
+
+    fn branch(&mut self, branch: &If<'_, '_, '_>) -> fmt::Result {
+        if has_scope_arg(&branch.cond.val) {
+            self.f.write_char('(')?;
+            self.expr(&branch.cond.val, Prec::Func)?;
+            self.f.write_char(')')?;
+        } else {
+            self.expr(&branch.cond.val, Prec::Func)?;
+        }
+
+        self.f.write_char(' ')?;
+        self.scope(&branch.branch.val)
+    }
+
+    fn hir(&mut self, hir: &Hir<'_, '_, '_>) -> fmt::Result {
+        match *hir {
+            Hir::Let { ref pat, ref value } => {
+                write!(self.f, "let {} = ", pat)?;
+                self.expr(&value.val, Prec::Func)?;
+                self.f.write_char(';')
+            }
+            Hir::Mut { ref pat, ref value } => {
+                write!(self.f, "{} = ", pat)?;
+                self.expr(&value.val, Prec::Func)?;
+                self.f.write_char(';')
+            }
+            Hir::Print(name) => write!(self.f, "print {};", name.val),
+            Hir::Panic(Some(message)) => write!(self.f, "panic \"{}\";", message.as_str()),
+            Hir::Panic(None) => self.f.write_str("panic;"),
+            Hir::Scope(ref scope) => self.scope(scope),
+            Hir::Loop(ref scope) => {
+                self.f.write_str("loop ")?;
+                self.scope(scope)
+            }
+            Hir::If {
+                ref if_branch,
+                ref else_if_branches,
+                ref else_branch,
+            } => {
+                self.f.write_str("if ")?;
+                self.branch(if_branch)?;
+
+                for branch in else_if_branches {
+                    self.f.write_str(" else if ")?;
+                    self.branch(branch)?;
+                }
+
+                if let Some(else_branch) = else_branch {
+                    self.f.write_str(" else ")?;
+                    self.scope(&else_branch.val)?;
+                }
+
+                Ok(())
+            }
+            Hir::ControlFlow { ty, label, ref val } => {
+                // the parser doesn't expect a `;` after `break`
+                self.f.write_str(match ty {
+                    ControlFlowType::Break => "break",
+                    ControlFlowType::Continue => "continue",
+                })?;
+
+                if let Some(label) = label {
+                    write!(self.f, " '{}", label)?;
+                }
+
+                if let Some(val) = val {
+                    self.f.write_char(' ')?;
+                    self.expr(val, Prec::Func)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// print `expr`, in parentheses if it binds looser than `min`
+    fn expr(&mut self, expr: &Expr<'_, '_, '_>, min: Prec) -> fmt::Result {
+        let prec = Prec::of(expr);
+
+        if prec < min {
+            self.f.write_char('(')?;
+            self.expr(expr, Prec::Func)?;
+            return self.f.write_char(')');
+        }
+
+        match *expr {
+            Expr::Simple(ref simple) => write!(self.f, "{}", simple),
+            Expr::PreOp(op, ref value) => {
+                write!(self.f, "{}", op)?;
+                self.expr(&value.val, Prec::Basic)
+            }
+            Expr::PostOp(op, ref value) => {
+                self.expr(&value.val, Prec::Basic)?;
+                write!(self.f, "{}", op)
+            }
+            Expr::BinOp(op, ref left, ref right) => {
+                self.expr(&left.val, prec)?;
+                write!(self.f, " {} ", op)?;
+                self.expr(&right.val, prec.tighter())
+            }
+            Expr::Func {
+                ref parameter_list,
+                ref body,
+            } => {
+                // parameter types don't have a syntax yet
+                for param in parameter_list {
+                    write!(self.f, "{} -> ", param.name)?;
+                }
+
+                self.expr(&body.val, Prec::Compare)
+            }
+            Expr::FuncApp { ref name_args } => {
+                for (i, arg) in name_args.iter().enumerate() {
+                    if i != 0 {
+                        self.f.write_char(' ')?;
+                    }
+
+                    self.expr(&arg.val, Prec::Basic)?;
+                }
+
+                Ok(())
+            }
+            Expr::Tuple(ref items) => {
+                self.f.write_char('(')?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        self.f.write_str(", ")?;
+                    }
+
+                    write!(self.f, "{}", item)?;
+                }
+
+                self.f.write_char(')')
+            }
+            Expr::Scope(ref scope) => self.scope(scope),
+            Expr::Cast { ref value, ref ty } => {
+                self.expr(&value.val, Prec::Cast)?;
+                self.f.write_str(" as ")?;
+                self.expr(&ty.val, Prec::Basic)
+            }
+        }
+    }
+}
+
+impl<N: Display> Display for Node<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.val.fmt(f)
+    }
+}
+
+impl Display for Hir<'_, '_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer { f, indent: 0 }.hir(self)
+    }
+}
+
+impl Display for Expr<'_, '_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer { f, indent: 0 }.expr(self, Prec::Func)
+    }
+}
+
+impl Display for Pattern<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Pattern::Literal(literal) => literal.fmt(f),
+            Pattern::Ident(name, crate::BindingMode::Value) => name.fmt(f),
+            Pattern::Ident(name, crate::BindingMode::Reference) => write!(f, "ref {}", name),
+            Pattern::Tuple(ref items) => {
+                f.write_char('(')?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+
+                    item.fmt(f)?;
+                }
+
+                f.write_char(')')
+            }
+        }
+    }
+}
+
+impl Display for SimpleExpr<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SimpleExpr::Literal(literal) => literal.fmt(f),
+            SimpleExpr::Ident(name) => name.fmt(f),
+        }
+    }
+}
+
+impl Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            // strings are kept as they were written, escapes and all
+            Literal::Str(s) => write!(f, "\"{}\"", s.as_str()),
+            Literal::Int(x) => x.fmt(f),
+            Literal::Float(x) => {
+                let x = x.to_string();
+
+                // `3.0` prints as `3`, which would lex as an integer
+                if x.contains('.') {
+                    f.write_str(&x)
+                } else {
+                    write!(f, "{}.0", x)
+                }
+            }
+            Literal::Bool(x) => x.fmt(f),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Operator::Symbol(sym) => f.write_str(sym.to_str()),
+            Operator::Keyword(kw) => f.write_str(kw.to_str()),
+        }
+    }
+}
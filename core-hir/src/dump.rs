@@ -0,0 +1,414 @@
+//! Structured dumps of the HIR, with the span of every node, as S-expressions
+//! for golden tests and as JSON for external tools.
+//!
+//! ```text
+//! (let 0..10
+//!   :pat (ident 4..5 :name "a" :mode "value")
+//!   :value (int 8..9 :value 1))
+//! ```
+
+use crate::{
+    BindingMode, ControlFlowType, Expr, Hir, HirNode, If, Literal, Node, Pattern, Scope, SimpleExpr,
+};
+use core_tokens::Span;
+
+use std::fmt::Write;
+
+const INDENT: &str = "  ";
+
+/// the S-expression dump of `hir`, one top level form per statement
+pub fn to_sexpr(hir: &[HirNode<'_, '_, '_>]) -> String {
+    let mut output = String::new();
+
+    for node in hir {
+        Tree::hir(node).sexpr(&mut output, 0);
+        output.push('\n');
+    }
+
+    output
+}
+
+/// the JSON dump of `hir`, an array with an object per statement
+pub fn to_json(hir: &[HirNode<'_, '_, '_>]) -> String {
+    let trees = hir.iter().map(Tree::hir).collect::<Vec<_>>();
+    let mut output = String::new();
+
+    Field::List(trees).json(&mut output, 0);
+    output.push('\n');
+
+    output
+}
+
+/// a node of the HIR, with its fields in a form that is easy to print
+struct Tree {
+    kind: &'static str,
+    span: Option<Span>,
+    fields: Vec<(&'static str, Field)>,
+}
+
+enum Field {
+    Str(String),
+    Int(u128),
+    Float(f64),
+    Bool(bool),
+    Null,
+    Tree(Tree),
+    List(Vec<Tree>),
+}
+
+impl Tree {
+    fn new(kind: &'static str, span: Option<Span>) -> Self {
+        Self {
+            kind,
+            span,
+            fields: Vec::new(),
+        }
+    }
+
+    fn with(mut self, name: &'static str, field: Field) -> Self {
+        self.fields.push((name, field));
+        self
+    }
+
+    fn body(scope: &[HirNode<'_, '_, '_>]) -> Field {
+        Field::List(scope.iter().map(Self::hir).collect())
+    }
+
+    fn scope(node: &Node<Scope<'_, '_, '_>>) -> Self {
+        Self::new("scope", Some(node.span)).with("body", Self::body(&node.val))
+    }
+
+    fn branch(branch: &If<'_, '_, '_>) -> Self {
+        Self::new("branch", Some(branch.cond.span.to(branch.branch.span)))
+            .with(
+                "cond",
+                Field::Tree(Self::expr(&branch.cond.val, branch.cond.span)),
+            )
+            .with("body", Field::Tree(Self::scope(&branch.branch)))
+    }
+
+    fn hir(node: &HirNode<'_, '_, '_>) -> Self {
+        let span = Some(node.span);
+
+        match node.val {
+            Hir::Let { ref pat, ref value } => Self::new("let", span)
+                .with("pat", Field::Tree(Self::pattern(&pat.val, Some(pat.span))))
+                .with("value", Field::Tree(Self::expr(&value.val, value.span))),
+            Hir::Mut { ref pat, ref value } => Self::new("assign", span)
+                .with("pat", Field::Tree(Self::pattern(&pat.val, Some(pat.span))))
+                .with("value", Field::Tree(Self::expr(&value.val, value.span))),
+            Hir::Print(name) => Self::new("print", span).with(
+                "name",
+                Field::Tree(
+                    Self::new("name", Some(name.span))
+                        .with("name", Field::Str(name.val.to_string())),
+                ),
+            ),
+            Hir::Panic(message) => Self::new("panic", span).with(
+                "message",
+                message.map_or(Field::Null, |message| {
+                    Field::Str(message.as_str().to_string())
+                }),
+            ),
+            Hir::Scope(ref scope) => Self::new("scope", span).with("body", Self::body(scope)),
+            Hir::Loop(ref scope) => Self::new("loop", span).with("body", Self::body(scope)),
+            Hir::If {
+                ref if_branch,
+                ref else_if_branches,
+                ref else_branch,
+            } => Self::new("if", span)
+                .with(
+                    "branches",
+                    Field::List(
+                        std::iter::once(if_branch)
+                            .chain(else_if_branches)
+                            .map(Self::branch)
+                            .collect(),
+                    ),
+                )
+                .with(
+                    "else",
+                    else_branch
+                        .as_ref()
+                        .map_or(Field::Null, |scope| Field::Tree(Self::scope(scope))),
+                ),
+            Hir::ControlFlow { ty, label, ref val } => {
+                let kind = match ty {
+                    ControlFlowType::Break => "break",
+                    ControlFlowType::Continue => "continue",
+                };
+
+                // the value of a `break` doesn't have a span of its own
+                Self::new(kind, span)
+                    .with(
+                        "label",
+                        label.map_or(Field::Null, |label| Field::Str(label.to_string())),
+                    )
+                    .with(
+                        "value",
+                        val.as_ref()
+                            .map_or(Field::Null, |val| Field::Tree(Self::expr(val, node.span))),
+                    )
+            }
+        }
+    }
+
+    fn pattern(pat: &Pattern<'_, '_>, span: Option<Span>) -> Self {
+        match *pat {
+            Pattern::Literal(literal) => Self::literal(literal, span),
+            Pattern::Ident(name, mode) => Self::new("ident", span)
+                .with("name", Field::Str(name.to_string()))
+                .with(
+                    "mode",
+                    Field::Str(
+                        match mode {
+                            BindingMode::Value => "value",
+                            BindingMode::Reference => "ref",
+                        }
+                        .to_string(),
+                    ),
+                ),
+            // the items of a tuple pattern don't have spans
+            Pattern::Tuple(ref items) => Self::new("tuple", span).with(
+                "items",
+                Field::List(items.iter().map(|item| Self::pattern(item, None)).collect()),
+            ),
+        }
+    }
+
+    fn literal(literal: Literal<'_>, span: Option<Span>) -> Self {
+        match literal {
+            Literal::Str(s) => {
+                Self::new("str", span).with("value", Field::Str(s.as_str().to_string()))
+            }
+            Literal::Int(x) => Self::new("int", span).with("value", Field::Int(x)),
+            Literal::Float(x) => Self::new("float", span).with("value", Field::Float(x)),
+            Literal::Bool(x) => Self::new("bool", span).with("value", Field::Bool(x)),
+        }
+    }
+
+    fn expr(expr: &Expr<'_, '_, '_>, span: Span) -> Self {
+        let span = Some(span);
+
+        match *expr {
+            Expr::Simple(ref simple) => match simple.val {
+                SimpleExpr::Literal(literal) => Self::literal(literal, Some(simple.span)),
+                SimpleExpr::Ident(name) => {
+                    Self::new("name", Some(simple.span)).with("name", Field::Str(name.to_string()))
+                }
+            },
+            Expr::PreOp(op, ref value) => Self::new("prefix", span)
+                .with("op", Field::Str(op.to_string()))
+                .with("value", Field::Tree(Self::expr(&value.val, value.span))),
+            Expr::PostOp(op, ref value) => Self::new("postfix", span)
+                .with("op", Field::Str(op.to_string()))
+                .with("value", Field::Tree(Self::expr(&value.val, value.span))),
+            Expr::BinOp(op, ref left, ref right) => Self::new("binary", span)
+                .with("op", Field::Str(op.to_string()))
+                .with("left", Field::Tree(Self::expr(&left.val, left.span)))
+                .with("right", Field::Tree(Self::expr(&right.val, right.span))),
+            Expr::Func {
+                ref parameter_list,
+                ref body,
+            } => Self::new("func", span)
+                .with(
+                    "params",
+                    Field::List(
+                        parameter_list
+                            .iter()
+                            .map(|param| {
+                                Self::new("param", Some(param.span))
+                                    .with("name", Field::Str(param.name.to_string()))
+                                    .with(
+                                        "type",
+                                        param.ty.as_ref().map_or(Field::Null, |ty| {
+                                            Field::Tree(Self::expr(&ty.val, ty.span))
+                                        }),
+                                    )
+                            })
+                            .collect(),
+                    ),
+                )
+                .with("body", Field::Tree(Self::expr(&body.val, body.span))),
+            Expr::FuncApp { ref name_args } => Self::new("call", span).with(
+                "args",
+                Field::List(
+                    name_args
+                        .iter()
+                        .map(|arg| Self::expr(&arg.val, arg.span))
+                        .collect(),
+                ),
+            ),
+            Expr::Tuple(ref items) => Self::new("tuple", span).with(
+                "items",
+                Field::List(
+                    items
+                        .iter()
+                        .map(|item| Self::pattern(&item.val, Some(item.span)))
+                        .collect(),
+                ),
+            ),
+            Expr::Scope(ref scope) => Self::new("scope", span).with("body", Self::body(scope)),
+            Expr::Cast { ref value, ref ty } => Self::new("cast", span)
+                .with("value", Field::Tree(Self::expr(&value.val, value.span)))
+                .with("type", Field::Tree(Self::expr(&ty.val, ty.span))),
+        }
+    }
+
+    fn sexpr(&self, output: &mut String, indent: usize) {
+        output.push('(');
+        output.push_str(self.kind);
+
+        if let Some(span) = self.span {
+            let _ = write!(output, " {}..{}", span.start(), span.end());
+        }
+
+        // scalars go on the first line, nodes each get a line of their own
+        for (name, field) in self.fields.iter() {
+            match field {
+                Field::Tree(_) | Field::List(_) => continue,
+                _ => (),
+            }
+
+            let _ = write!(output, " :{} ", name);
+            field.sexpr(output, indent + 1);
+        }
+
+        for (name, field) in self.fields.iter() {
+            match field {
+                Field::Tree(_) | Field::List(_) => (),
+                _ => continue,
+            }
+
+            newline(output, indent + 1);
+            let _ = write!(output, ":{} ", name);
+            field.sexpr(output, indent + 1);
+        }
+
+        output.push(')');
+    }
+}
+
+impl Field {
+    fn sexpr(&self, output: &mut String, indent: usize) {
+        match self {
+            Self::Str(s) => write_str(output, s),
+            Self::Int(x) => {
+                let _ = write!(output, "{}", x);
+            }
+            Self::Float(x) => {
+                let _ = write!(output, "{:?}", x);
+            }
+            Self::Bool(x) => {
+                let _ = write!(output, "{}", x);
+            }
+            Self::Null => output.push_str("nil"),
+            Self::Tree(tree) => tree.sexpr(output, indent),
+            Self::List(items) if items.is_empty() => output.push_str("[]"),
+            Self::List(items) => {
+                output.push('[');
+
+                for item in items {
+                    newline(output, indent + 1);
+                    item.sexpr(output, indent + 1);
+                }
+
+                output.push(']');
+            }
+        }
+    }
+
+    fn json(&self, output: &mut String, indent: usize) {
+        match self {
+            Self::Str(s) => write_str(output, s),
+            Self::Int(x) => {
+                let _ = write!(output, "{}", x);
+            }
+            Self::Float(x) if x.is_finite() => {
+                let _ = write!(output, "{:?}", x);
+            }
+            // JSON has no infinities or NaN
+            Self::Float(_) | Self::Null => output.push_str("null"),
+            Self::Bool(x) => {
+                let _ = write!(output, "{}", x);
+            }
+            Self::Tree(tree) => tree.json(output, indent),
+            Self::List(items) if items.is_empty() => output.push_str("[]"),
+            Self::List(items) => {
+                output.push('[');
+
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        output.push(',');
+                    }
+
+                    newline(output, indent + 1);
+                    item.json(output, indent + 1);
+                }
+
+                newline(output, indent);
+                output.push(']');
+            }
+        }
+    }
+}
+
+impl Tree {
+    fn json(&self, output: &mut String, indent: usize) {
+        output.push('{');
+        newline(output, indent + 1);
+        output.push_str("\"kind\": ");
+        write_str(output, self.kind);
+
+        output.push(',');
+        newline(output, indent + 1);
+        output.push_str("\"span\": ");
+
+        match self.span {
+            Some(span) => {
+                let _ = write!(output, "[{}, {}]", span.start(), span.end());
+            }
+            None => output.push_str("null"),
+        }
+
+        for (name, field) in self.fields.iter() {
+            output.push(',');
+            newline(output, indent + 1);
+            write_str(output, name);
+            output.push_str(": ");
+            field.json(output, indent + 1);
+        }
+
+        newline(output, indent);
+        output.push('}');
+    }
+}
+
+fn newline(output: &mut String, indent: usize) {
+    output.push('\n');
+
+    for _ in 0..indent {
+        output.push_str(INDENT);
+    }
+}
+
+/// write `s` as a quoted string, which is valid in both JSON and S-expressions
+fn write_str(output: &mut String, s: &str) {
+    output.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+}
@@ -0,0 +1,246 @@
+//! Rebuilding the HIR into a new arena.
+//!
+//! `Fold` reads a HIR whose expressions live in the `'hir` arena, and builds a new
+//! HIR whose expressions are allocated with `Fold::alloc`, which lives for `'out`.
+//! Every method defaults to the `walk_*` function of the same node, which rebuilds
+//! the node out of its folded children, so a desugaring only has to override the
+//! nodes it changes.
+//!
+//! ```ignore
+//! struct Rebuild<'str, 'idt, 'out> {
+//!     exprs: &'out LocalUniqueArena<Node<Expr<'str, 'idt, 'out>>, 16>,
+//! }
+//!
+//! impl<'str, 'idt, 'hir, 'out> Fold<'str, 'idt, 'hir, 'out> for Rebuild<'str, 'idt, 'out> {
+//!     fn alloc(
+//!         &mut self,
+//!         expr: Node<Expr<'str, 'idt, 'out>>,
+//!     ) -> &'out mut Node<Expr<'str, 'idt, 'out>> {
+//!         self.exprs.alloc(expr)
+//!     }
+//! }
+//! ```
+
+use crate::{Expr, Hir, If, Node, Parameter, Pattern, Scope, SimpleExpr};
+use core_tokens::Span;
+
+pub trait Fold<'str, 'idt, 'hir, 'out> {
+    /// move `expr` into the output arena
+    fn alloc(
+        &mut self,
+        expr: Node<Expr<'str, 'idt, 'out>>,
+    ) -> &'out mut Node<Expr<'str, 'idt, 'out>>;
+
+    fn fold_hir(&mut self, node: &Node<Hir<'str, 'idt, 'hir>>) -> Node<Hir<'str, 'idt, 'out>> {
+        walk_hir(self, node)
+    }
+
+    fn fold_scope(&mut self, scope: &[Node<Hir<'str, 'idt, 'hir>>]) -> Scope<'str, 'idt, 'out> {
+        walk_scope(self, scope)
+    }
+
+    fn fold_if(&mut self, branch: &If<'str, 'idt, 'hir>) -> If<'str, 'idt, 'out> {
+        walk_if(self, branch)
+    }
+
+    /// `span` is the span of the node holding `expr`, the value of a
+    /// `break` doesn't have a node of its own so it gets the span of the `break`
+    fn fold_expr(&mut self, expr: &Expr<'str, 'idt, 'hir>, span: Span) -> Expr<'str, 'idt, 'out> {
+        walk_expr(self, expr, span)
+    }
+
+    fn fold_simple_expr(
+        &mut self,
+        expr: &Node<SimpleExpr<'str, 'idt>>,
+    ) -> Node<SimpleExpr<'str, 'idt>> {
+        *expr
+    }
+
+    /// the items of a tuple pattern don't have spans of their own, they get the span of the tuple
+    fn fold_pattern(&mut self, pat: &Pattern<'str, 'idt>, span: Span) -> Pattern<'str, 'idt> {
+        walk_pattern(self, pat, span)
+    }
+
+    fn fold_parameter(
+        &mut self,
+        param: &Parameter<'str, 'idt, 'hir>,
+    ) -> Parameter<'str, 'idt, 'out> {
+        walk_parameter(self, param)
+    }
+}
+
+/// fold an expression that keeps its span
+fn fold_node<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    node: &Node<Expr<'str, 'idt, 'hir>>,
+) -> Node<Expr<'str, 'idt, 'out>> {
+    Node {
+        val: folder.fold_expr(&node.val, node.span),
+        span: node.span,
+    }
+}
+
+/// fold an expression and move it into the output arena
+fn fold_child<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    node: &Node<Expr<'str, 'idt, 'hir>>,
+) -> &'out mut Node<Expr<'str, 'idt, 'out>> {
+    let node = fold_node(folder, node);
+    folder.alloc(node)
+}
+
+pub fn walk_hir<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    node: &Node<Hir<'str, 'idt, 'hir>>,
+) -> Node<Hir<'str, 'idt, 'out>> {
+    let val = match node.val {
+        Hir::Let { ref pat, ref value } => {
+            let value = fold_node(folder, value);
+
+            Hir::Let {
+                pat: Node {
+                    val: folder.fold_pattern(&pat.val, pat.span),
+                    span: pat.span,
+                },
+                value,
+            }
+        }
+        Hir::Mut { ref pat, ref value } => {
+            let value = fold_node(folder, value);
+
+            Hir::Mut {
+                pat: Node {
+                    val: folder.fold_pattern(&pat.val, pat.span),
+                    span: pat.span,
+                },
+                value,
+            }
+        }
+        Hir::If {
+            ref if_branch,
+            ref else_if_branches,
+            ref else_branch,
+        } => Hir::If {
+            if_branch: folder.fold_if(if_branch),
+            else_if_branches: else_if_branches
+                .iter()
+                .map(|branch| folder.fold_if(branch))
+                .collect(),
+            else_branch: else_branch.as_ref().map(|else_branch| {
+                Box::new(Node {
+                    val: folder.fold_scope(&else_branch.val),
+                    span: else_branch.span,
+                })
+            }),
+        },
+        Hir::Print(name) => Hir::Print(name),
+        Hir::Panic(message) => Hir::Panic(message),
+        Hir::Scope(ref scope) => Hir::Scope(folder.fold_scope(scope)),
+        Hir::Loop(ref scope) => Hir::Loop(folder.fold_scope(scope)),
+        Hir::ControlFlow { ty, label, ref val } => Hir::ControlFlow {
+            ty,
+            label,
+            val: val.as_ref().map(|val| folder.fold_expr(val, node.span)),
+        },
+    };
+
+    Node {
+        val,
+        span: node.span,
+    }
+}
+
+pub fn walk_scope<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    scope: &[Node<Hir<'str, 'idt, 'hir>>],
+) -> Scope<'str, 'idt, 'out> {
+    scope.iter().map(|node| folder.fold_hir(node)).collect()
+}
+
+pub fn walk_if<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    branch: &If<'str, 'idt, 'hir>,
+) -> If<'str, 'idt, 'out> {
+    If {
+        cond: fold_node(folder, &branch.cond),
+        branch: Node {
+            val: folder.fold_scope(&branch.branch.val),
+            span: branch.branch.span,
+        },
+    }
+}
+
+pub fn walk_expr<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    expr: &Expr<'str, 'idt, 'hir>,
+    _span: Span,
+) -> Expr<'str, 'idt, 'out> {
+    match *expr {
+        Expr::Simple(ref simple) => Expr::Simple(folder.fold_simple_expr(simple)),
+        Expr::PreOp(op, ref value) => Expr::PreOp(op, fold_child(folder, value)),
+        Expr::PostOp(op, ref value) => Expr::PostOp(op, fold_child(folder, value)),
+        Expr::BinOp(op, ref left, ref right) => {
+            let left = fold_child(folder, left);
+            let right = fold_child(folder, right);
+
+            Expr::BinOp(op, left, right)
+        }
+        Expr::Func {
+            ref parameter_list,
+            ref body,
+        } => Expr::Func {
+            parameter_list: parameter_list
+                .iter()
+                .map(|param| folder.fold_parameter(param))
+                .collect(),
+            body: fold_child(folder, body),
+        },
+        Expr::FuncApp { ref name_args } => Expr::FuncApp {
+            name_args: name_args.iter().map(|arg| fold_node(folder, arg)).collect(),
+        },
+        Expr::Tuple(ref items) => Expr::Tuple(
+            items
+                .iter()
+                .map(|item| Node {
+                    val: folder.fold_pattern(&item.val, item.span),
+                    span: item.span,
+                })
+                .collect(),
+        ),
+        Expr::Scope(ref scope) => Expr::Scope(folder.fold_scope(scope)),
+        Expr::Cast { ref value, ref ty } => {
+            let value = fold_child(folder, value);
+            let ty = fold_child(folder, ty);
+
+            Expr::Cast { value, ty }
+        }
+    }
+}
+
+pub fn walk_pattern<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    pat: &Pattern<'str, 'idt>,
+    span: Span,
+) -> Pattern<'str, 'idt> {
+    match *pat {
+        Pattern::Literal(literal) => Pattern::Literal(literal),
+        Pattern::Ident(name, mode) => Pattern::Ident(name, mode),
+        Pattern::Tuple(ref items) => Pattern::Tuple(
+            items
+                .iter()
+                .map(|item| folder.fold_pattern(item, span))
+                .collect(),
+        ),
+    }
+}
+
+pub fn walk_parameter<'str, 'idt, 'hir, 'out, F: Fold<'str, 'idt, 'hir, 'out> + ?Sized>(
+    folder: &mut F,
+    param: &Parameter<'str, 'idt, 'hir>,
+) -> Parameter<'str, 'idt, 'out> {
+    Parameter {
+        name: param.name,
+        span: param.span,
+        ty: param.ty.as_ref().map(|ty| fold_node(folder, ty)),
+    }
+}
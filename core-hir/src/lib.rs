@@ -1,3 +1,9 @@
+mod display;
+pub mod dump;
+pub mod fold;
+pub mod visit;
+pub mod visit_mut;
+
 use core_tokens::{Ident, Span, Str};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -33,7 +39,8 @@ pub enum Hir<'str, 'idt, 'hir> {
         pat: Node<Pattern<'str, 'idt>>,
         value: Node<Expr<'str, 'idt, 'hir>>,
     },
-    Print(Ident<'idt>),
+    Print(Node<Ident<'idt>>),
+    Panic(Option<Str<'str>>),
     Scope(Scope<'str, 'idt, 'hir>),
     Loop(Scope<'str, 'idt, 'hir>),
     ControlFlow {
@@ -71,7 +78,7 @@ pub enum Pattern<'str, 'idt> {
 #[derive(Debug, PartialEq)]
 pub enum Expr<'str, 'idt, 'hir> {
     Simple(Node<SimpleExpr<'str, 'idt>>),
-    PreOp(Operator, &'hir Node<Expr<'str, 'idt, 'hir>>),
+    PreOp(Operator, &'hir mut Node<Expr<'str, 'idt, 'hir>>),
     PostOp(Operator, &'hir mut Node<Expr<'str, 'idt, 'hir>>),
     BinOp(
         Operator,
@@ -87,11 +94,16 @@ pub enum Expr<'str, 'idt, 'hir> {
     },
     Tuple(Vec<Node<Pattern<'str, 'idt>>>),
     Scope(Scope<'str, 'idt, 'hir>),
+    Cast {
+        value: &'hir mut Node<Expr<'str, 'idt, 'hir>>,
+        ty: &'hir mut Node<Expr<'str, 'idt, 'hir>>,
+    },
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Parameter<'str, 'idt, 'hir> {
     pub name: Ident<'idt>,
+    pub span: Span,
     pub ty: Option<Node<Expr<'str, 'idt, 'hir>>>,
 }
 
@@ -0,0 +1,170 @@
+//! Read-only traversal of the HIR.
+//!
+//! Every method of `Visit` defaults to the `walk_*` function of the same node,
+//! which visits the node's children. Override the methods for the nodes you care
+//! about, and call the `walk_*` function from the override to keep going deeper.
+//!
+//! ```ignore
+//! struct CountPrints(usize);
+//!
+//! impl<'str, 'idt, 'hir> Visit<'str, 'idt, 'hir> for CountPrints {
+//!     fn visit_hir(&mut self, node: &Node<Hir<'str, 'idt, 'hir>>) {
+//!         if let Hir::Print(_) = node.val {
+//!             self.0 += 1;
+//!         }
+//!
+//!         walk_hir(self, node);
+//!     }
+//! }
+//! ```
+
+use crate::{Expr, Hir, If, Node, Parameter, Pattern, SimpleExpr};
+use core_tokens::Span;
+
+pub trait Visit<'str, 'idt, 'hir> {
+    fn visit_hir(&mut self, node: &Node<Hir<'str, 'idt, 'hir>>) {
+        walk_hir(self, node)
+    }
+
+    fn visit_scope(&mut self, scope: &[Node<Hir<'str, 'idt, 'hir>>]) {
+        walk_scope(self, scope)
+    }
+
+    fn visit_if(&mut self, branch: &If<'str, 'idt, 'hir>) {
+        walk_if(self, branch)
+    }
+
+    /// `span` is the span of the node holding `expr`, the value of a
+    /// `break` doesn't have a node of its own so it gets the span of the `break`
+    fn visit_expr(&mut self, expr: &Expr<'str, 'idt, 'hir>, span: Span) {
+        walk_expr(self, expr, span)
+    }
+
+    fn visit_simple_expr(&mut self, _expr: &Node<SimpleExpr<'str, 'idt>>) {}
+
+    /// the items of a tuple pattern don't have spans of their own, they get the span of the tuple
+    fn visit_pattern(&mut self, pat: &Pattern<'str, 'idt>, span: Span) {
+        walk_pattern(self, pat, span)
+    }
+
+    fn visit_parameter(&mut self, param: &Parameter<'str, 'idt, 'hir>) {
+        walk_parameter(self, param)
+    }
+}
+
+pub fn walk_hir<'str, 'idt, 'hir, V: Visit<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    node: &Node<Hir<'str, 'idt, 'hir>>,
+) {
+    match node.val {
+        Hir::Let { ref pat, ref value } | Hir::Mut { ref pat, ref value } => {
+            visitor.visit_expr(&value.val, value.span);
+            visitor.visit_pattern(&pat.val, pat.span);
+        }
+        Hir::If {
+            ref if_branch,
+            ref else_if_branches,
+            ref else_branch,
+        } => {
+            visitor.visit_if(if_branch);
+
+            for branch in else_if_branches {
+                visitor.visit_if(branch);
+            }
+
+            if let Some(else_branch) = else_branch {
+                visitor.visit_scope(&else_branch.val);
+            }
+        }
+        Hir::Print(_) | Hir::Panic(_) => (),
+        Hir::Scope(ref scope) | Hir::Loop(ref scope) => visitor.visit_scope(scope),
+        Hir::ControlFlow { ref val, .. } => {
+            if let Some(val) = val {
+                visitor.visit_expr(val, node.span);
+            }
+        }
+    }
+}
+
+pub fn walk_scope<'str, 'idt, 'hir, V: Visit<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    scope: &[Node<Hir<'str, 'idt, 'hir>>],
+) {
+    for node in scope {
+        visitor.visit_hir(node);
+    }
+}
+
+pub fn walk_if<'str, 'idt, 'hir, V: Visit<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    branch: &If<'str, 'idt, 'hir>,
+) {
+    visitor.visit_expr(&branch.cond.val, branch.cond.span);
+    visitor.visit_scope(&branch.branch.val);
+}
+
+pub fn walk_expr<'str, 'idt, 'hir, V: Visit<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    expr: &Expr<'str, 'idt, 'hir>,
+    _span: Span,
+) {
+    match *expr {
+        Expr::Simple(ref simple) => visitor.visit_simple_expr(simple),
+        Expr::PreOp(_, ref value) | Expr::PostOp(_, ref value) => {
+            visitor.visit_expr(&value.val, value.span)
+        }
+        Expr::BinOp(_, ref left, ref right) => {
+            visitor.visit_expr(&left.val, left.span);
+            visitor.visit_expr(&right.val, right.span);
+        }
+        Expr::Func {
+            ref parameter_list,
+            ref body,
+        } => {
+            for param in parameter_list {
+                visitor.visit_parameter(param);
+            }
+
+            visitor.visit_expr(&body.val, body.span);
+        }
+        Expr::FuncApp { ref name_args } => {
+            for arg in name_args {
+                visitor.visit_expr(&arg.val, arg.span);
+            }
+        }
+        Expr::Tuple(ref items) => {
+            for item in items {
+                visitor.visit_pattern(&item.val, item.span);
+            }
+        }
+        Expr::Scope(ref scope) => visitor.visit_scope(scope),
+        Expr::Cast { ref value, ref ty } => {
+            visitor.visit_expr(&value.val, value.span);
+            visitor.visit_expr(&ty.val, ty.span);
+        }
+    }
+}
+
+pub fn walk_pattern<'str, 'idt, 'hir, V: Visit<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    pat: &Pattern<'str, 'idt>,
+    span: Span,
+) {
+    match *pat {
+        Pattern::Literal(_) | Pattern::Ident(..) => (),
+        Pattern::Tuple(ref items) => {
+            for item in items {
+                visitor.visit_pattern(item, span);
+            }
+        }
+    }
+}
+
+pub fn walk_parameter<'str, 'idt, 'hir, V: Visit<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    param: &Parameter<'str, 'idt, 'hir>,
+) {
+    if let Some(ref ty) = param.ty {
+        visitor.visit_expr(&ty.val, ty.span);
+    }
+}
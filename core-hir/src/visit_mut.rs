@@ -0,0 +1,165 @@
+//! Mutable traversal of the HIR, for passes that rewrite it in place.
+//!
+//! This mirrors `visit`, every method of `VisitMut` defaults to the `walk_*_mut`
+//! function of the same node, which visits the node's children.
+
+use crate::{Expr, Hir, If, Node, Parameter, Pattern, SimpleExpr};
+use core_tokens::Span;
+
+pub trait VisitMut<'str, 'idt, 'hir> {
+    fn visit_hir_mut(&mut self, node: &mut Node<Hir<'str, 'idt, 'hir>>) {
+        walk_hir_mut(self, node)
+    }
+
+    fn visit_scope_mut(&mut self, scope: &mut [Node<Hir<'str, 'idt, 'hir>>]) {
+        walk_scope_mut(self, scope)
+    }
+
+    fn visit_if_mut(&mut self, branch: &mut If<'str, 'idt, 'hir>) {
+        walk_if_mut(self, branch)
+    }
+
+    /// `span` is the span of the node holding `expr`, the value of a
+    /// `break` doesn't have a node of its own so it gets the span of the `break`
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'str, 'idt, 'hir>, span: Span) {
+        walk_expr_mut(self, expr, span)
+    }
+
+    fn visit_simple_expr_mut(&mut self, _expr: &mut Node<SimpleExpr<'str, 'idt>>) {}
+
+    /// the items of a tuple pattern don't have spans of their own, they get the span of the tuple
+    fn visit_pattern_mut(&mut self, pat: &mut Pattern<'str, 'idt>, span: Span) {
+        walk_pattern_mut(self, pat, span)
+    }
+
+    fn visit_parameter_mut(&mut self, param: &mut Parameter<'str, 'idt, 'hir>) {
+        walk_parameter_mut(self, param)
+    }
+}
+
+pub fn walk_hir_mut<'str, 'idt, 'hir, V: VisitMut<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    node: &mut Node<Hir<'str, 'idt, 'hir>>,
+) {
+    match node.val {
+        Hir::Let {
+            ref mut pat,
+            ref mut value,
+        }
+        | Hir::Mut {
+            ref mut pat,
+            ref mut value,
+        } => {
+            visitor.visit_expr_mut(&mut value.val, value.span);
+            visitor.visit_pattern_mut(&mut pat.val, pat.span);
+        }
+        Hir::If {
+            ref mut if_branch,
+            ref mut else_if_branches,
+            ref mut else_branch,
+        } => {
+            visitor.visit_if_mut(if_branch);
+
+            for branch in else_if_branches {
+                visitor.visit_if_mut(branch);
+            }
+
+            if let Some(else_branch) = else_branch {
+                visitor.visit_scope_mut(&mut else_branch.val);
+            }
+        }
+        Hir::Print(_) | Hir::Panic(_) => (),
+        Hir::Scope(ref mut scope) | Hir::Loop(ref mut scope) => visitor.visit_scope_mut(scope),
+        Hir::ControlFlow { ref mut val, .. } => {
+            if let Some(val) = val {
+                visitor.visit_expr_mut(val, node.span);
+            }
+        }
+    }
+}
+
+pub fn walk_scope_mut<'str, 'idt, 'hir, V: VisitMut<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    scope: &mut [Node<Hir<'str, 'idt, 'hir>>],
+) {
+    for node in scope {
+        visitor.visit_hir_mut(node);
+    }
+}
+
+pub fn walk_if_mut<'str, 'idt, 'hir, V: VisitMut<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    branch: &mut If<'str, 'idt, 'hir>,
+) {
+    visitor.visit_expr_mut(&mut branch.cond.val, branch.cond.span);
+    visitor.visit_scope_mut(&mut branch.branch.val);
+}
+
+pub fn walk_expr_mut<'str, 'idt, 'hir, V: VisitMut<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut Expr<'str, 'idt, 'hir>,
+    _span: Span,
+) {
+    match *expr {
+        Expr::Simple(ref mut simple) => visitor.visit_simple_expr_mut(simple),
+        Expr::PreOp(_, ref mut value) | Expr::PostOp(_, ref mut value) => {
+            visitor.visit_expr_mut(&mut value.val, value.span)
+        }
+        Expr::BinOp(_, ref mut left, ref mut right) => {
+            visitor.visit_expr_mut(&mut left.val, left.span);
+            visitor.visit_expr_mut(&mut right.val, right.span);
+        }
+        Expr::Func {
+            ref mut parameter_list,
+            ref mut body,
+        } => {
+            for param in parameter_list {
+                visitor.visit_parameter_mut(param);
+            }
+
+            visitor.visit_expr_mut(&mut body.val, body.span);
+        }
+        Expr::FuncApp { ref mut name_args } => {
+            for arg in name_args {
+                visitor.visit_expr_mut(&mut arg.val, arg.span);
+            }
+        }
+        Expr::Tuple(ref mut items) => {
+            for item in items {
+                visitor.visit_pattern_mut(&mut item.val, item.span);
+            }
+        }
+        Expr::Scope(ref mut scope) => visitor.visit_scope_mut(scope),
+        Expr::Cast {
+            ref mut value,
+            ref mut ty,
+        } => {
+            visitor.visit_expr_mut(&mut value.val, value.span);
+            visitor.visit_expr_mut(&mut ty.val, ty.span);
+        }
+    }
+}
+
+pub fn walk_pattern_mut<'str, 'idt, 'hir, V: VisitMut<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    pat: &mut Pattern<'str, 'idt>,
+    span: Span,
+) {
+    match *pat {
+        Pattern::Literal(_) | Pattern::Ident(..) => (),
+        Pattern::Tuple(ref mut items) => {
+            for item in items {
+                visitor.visit_pattern_mut(item, span);
+            }
+        }
+    }
+}
+
+pub fn walk_parameter_mut<'str, 'idt, 'hir, V: VisitMut<'str, 'idt, 'hir> + ?Sized>(
+    visitor: &mut V,
+    param: &mut Parameter<'str, 'idt, 'hir>,
+) {
+    if let Some(ref mut ty) = param.ty {
+        visitor.visit_expr_mut(&mut ty.val, ty.span);
+    }
+}
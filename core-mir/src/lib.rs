@@ -1,3 +1,6 @@
+use core_tokens::Span;
+use core_types::Primitive;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Reg(pub usize);
 
@@ -21,11 +24,61 @@ pub enum PreOpType {
     Neg,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CastKind {
+    /// the kind of cast is not known until the type of the source is inferred
+    Infer,
+    Identity,
+    Truncate,
+    SignExtend,
+    ZeroExtend,
+    IntToFloat,
+    FloatToInt,
+    FloatToFloat,
+    BoolToInt,
+}
+
+impl CastKind {
+    /// the kind of cast needed to convert from `from` to `to`,
+    /// or `None` if there is no valid cast between them
+    pub fn new(from: Primitive, to: Primitive) -> Option<Self> {
+        if from == to {
+            return Some(Self::Identity);
+        }
+
+        let kind = match (from, to) {
+            (Primitive::Bool, to) if to.is_integer() => Self::BoolToInt,
+            (from, to) if from.is_integer() && to.is_integer() => {
+                if to.size() < from.size() {
+                    Self::Truncate
+                } else if to.size() == from.size() {
+                    // same size, only the interpretation of the bits changes
+                    Self::Identity
+                } else if from.is_signed() {
+                    Self::SignExtend
+                } else {
+                    Self::ZeroExtend
+                }
+            }
+            (from, to) if from.is_integer() && to.is_float() => Self::IntToFloat,
+            (from, to) if from.is_float() && to.is_integer() => Self::FloatToInt,
+            (from, to) if from.is_float() && to.is_float() => Self::FloatToFloat,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+}
+
 pub type InstructionList<BMeta, FMeta> = Vec<Mir<BMeta, FMeta>>;
 
+/// A straight line of instructions, followed by the terminator that decides which
+/// block runs next. The edges of the control flow graph are derived from the terminators,
+/// so they can't go out of sync with the instructions.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Block<BMeta, FMeta> {
     pub instructions: InstructionList<BMeta, FMeta>,
+    pub terminator: Terminator,
     pub meta: BMeta,
 }
 
@@ -35,37 +88,259 @@ pub struct StackFrame<BMeta, FMeta> {
     pub meta: FMeta,
 }
 
+/// a jump, landing pad or phi of `block` refers to `target`, which isn't a block of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds {
+    pub block: usize,
+    pub target: usize,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {} refers to block {}, which doesn't exist",
+            self.block, self.target
+        )
+    }
+}
+
 impl<BMeta, FMeta> StackFrame<BMeta, FMeta> {
-    pub fn new(blocks: Vec<Block<BMeta, FMeta>>, meta: FMeta) -> Option<Self> {
-        for block in &blocks {
-            for mir in &block.instructions {
-                match mir {
-                    &Mir::BranchTrue { target, .. } | &Mir::Jump(target) => {
-                        if target >= blocks.len() {
-                            eprintln!("target is out of bounds!");
-                            return None;
-                        }
-                    }
-                    _ => (),
-                }
+    /// a frame of `blocks`, which must only refer to each other
+    pub fn new(blocks: Vec<Block<BMeta, FMeta>>, meta: FMeta) -> Result<Self, OutOfBounds> {
+        for (index, block) in blocks.iter().enumerate() {
+            let mut targets = block.terminator.successors();
+
+            targets.extend(block.instructions.iter().flat_map(|mir| match *mir {
+                Mir::PushLandingPad { cleanup } => vec![cleanup],
+                Mir::Phi { ref from, .. } => from.iter().map(|&(block, _)| block).collect(),
+                _ => Vec::new(),
+            }));
+
+            if let Some(&target) = targets.iter().find(|&&target| target >= blocks.len()) {
+                return Err(OutOfBounds {
+                    block: index,
+                    target,
+                });
             }
         }
 
-        Some(Self { blocks, meta })
+        Ok(Self { blocks, meta })
     }
 
     pub fn blocks(&self) -> &[Block<BMeta, FMeta>] {
         &self.blocks
     }
+
+    pub fn blocks_mut(&mut self) -> &mut [Block<BMeta, FMeta>] {
+        &mut self.blocks
+    }
+
+    /// add `block` to the end of the frame and return its index, the blocks it
+    /// jumps to must be in the frame before it is used
+    pub fn push(&mut self, block: Block<BMeta, FMeta>) -> usize {
+        self.blocks.push(block);
+        self.blocks.len() - 1
+    }
+
+    /// remove the blocks `keep` is false for and renumber the rest, the sources of phis
+    /// from removed blocks are dropped, but nothing else may refer to a removed block
+    pub fn retain_blocks(&mut self, keep: &[bool]) {
+        let mut next = 0;
+        let index = keep
+            .iter()
+            .map(|&keep| {
+                if keep {
+                    next += 1;
+                    Some(next - 1)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let remap = |target: &mut usize| {
+            *target = index[*target].expect("a kept block refers to a removed block")
+        };
+
+        let blocks = std::mem::take(&mut self.blocks);
+
+        self.blocks = blocks
+            .into_iter()
+            .zip(keep)
+            .filter(|&(_, &keep)| keep)
+            .map(|(mut block, _)| {
+                for target in block.terminator.successors_mut() {
+                    remap(target);
+                }
+
+                for mir in block.instructions.iter_mut() {
+                    match *mir {
+                        Mir::PushLandingPad { ref mut cleanup } => remap(cleanup),
+                        Mir::Phi { ref mut from, .. } => {
+                            from.retain(|&(source, _)| index[source].is_some());
+
+                            for (source, _) in from.iter_mut() {
+                                remap(source);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+
+                block
+            })
+            .collect();
+    }
+
+    /// the blocks that can run right after `block`
+    pub fn successors(&self, block: usize) -> Vec<usize> {
+        self.blocks[block].terminator.successors()
+    }
+
+    /// the blocks that can run right before each block, indexed by block
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+
+        for (from, block) in self.blocks.iter().enumerate() {
+            for to in block.terminator.successors() {
+                if !predecessors[to].contains(&from) {
+                    predecessors[to].push(from);
+                }
+            }
+        }
+
+        predecessors
+    }
 }
 
+/// the last thing a block does, which transfers control to another block or out of the frame
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Mir<BMeta, FMeta> {
-    Jump(usize),
-    BranchTrue {
+pub enum Terminator {
+    Goto(usize),
+    /// go to `then` if `cond` is true, and to `otherwise` if it is false
+    Branch {
         cond: Reg,
-        target: usize,
+        then: usize,
+        otherwise: usize,
+    },
+    /// go to the target of the first case equal to `value`, or to `otherwise` if there is none,
+    /// the cases are truncated to the type of `value`
+    Switch {
+        value: Reg,
+        cases: Vec<(u128, usize)>,
+        otherwise: usize,
+    },
+    /// return from the frame, the value of a function is held in the
+    /// return register of its `CreateFunc`
+    Return,
+    /// control never reaches the end of this block
+    Unreachable,
+    /// call the function held by `func` with `args`, write its value to `ret`
+    /// and continue at `next`
+    Call {
+        func: Reg,
+        args: Vec<Reg>,
+        ret: Reg,
+        next: usize,
     },
+    /// continue unwinding, this must end every cleanup landing pad
+    Resume,
+}
+
+impl Terminator {
+    /// the blocks this terminator may go to, in the order they are written
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Self::Goto(target) | Self::Call { next: target, .. } => vec![target],
+            Self::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            Self::Switch {
+                ref cases,
+                otherwise,
+                ..
+            } => cases
+                .iter()
+                .map(|&(_, target)| target)
+                .chain(std::iter::once(otherwise))
+                .collect(),
+            Self::Return | Self::Unreachable | Self::Resume => Vec::new(),
+        }
+    }
+
+    /// the blocks this terminator may go to, so they can be redirected
+    pub fn successors_mut(&mut self) -> Vec<&mut usize> {
+        match *self {
+            Self::Goto(ref mut target)
+            | Self::Call {
+                next: ref mut target,
+                ..
+            } => vec![target],
+            Self::Branch {
+                ref mut then,
+                ref mut otherwise,
+                ..
+            } => vec![then, otherwise],
+            Self::Switch {
+                ref mut cases,
+                ref mut otherwise,
+                ..
+            } => cases
+                .iter_mut()
+                .map(|(_, target)| target)
+                .chain(std::iter::once(otherwise))
+                .collect(),
+            Self::Return | Self::Unreachable | Self::Resume => Vec::new(),
+        }
+    }
+
+    /// the register this terminator writes to
+    pub fn def(&self) -> Option<Reg> {
+        match *self {
+            Self::Call { ret, .. } => Some(ret),
+            _ => None,
+        }
+    }
+
+    /// the registers this terminator reads from
+    pub fn uses(&self) -> Vec<Reg> {
+        match *self {
+            Self::Branch { cond: reg, .. } | Self::Switch { value: reg, .. } => vec![reg],
+            Self::Call { func, ref args, .. } => {
+                std::iter::once(func).chain(args.iter().copied()).collect()
+            }
+            Self::Goto(_) | Self::Return | Self::Unreachable | Self::Resume => Vec::new(),
+        }
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Reg> {
+        match *self {
+            Self::Call { ref mut ret, .. } => Some(ret),
+            _ => None,
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Reg> {
+        match *self {
+            Self::Branch {
+                cond: ref mut reg, ..
+            }
+            | Self::Switch {
+                value: ref mut reg, ..
+            } => vec![reg],
+            Self::Call {
+                ref mut func,
+                ref mut args,
+                ..
+            } => std::iter::once(func).chain(args.iter_mut()).collect(),
+            Self::Goto(_) | Self::Return | Self::Unreachable | Self::Resume => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Mir<BMeta, FMeta> {
     Load {
         to: Reg,
         from: Load,
@@ -80,28 +355,135 @@ pub enum Mir<BMeta, FMeta> {
         out: Reg,
         left: Reg,
         right: Reg,
+        span: Span,
     },
     PreOp {
         op: PreOpType,
         out: Reg,
         arg: Reg,
+        span: Span,
+    },
+    Cast {
+        to: Reg,
+        from: Reg,
+        ty: Primitive,
+        kind: CastKind,
     },
     CreateFunc {
         binding: Reg,
         ret: Reg,
         stack_frame: StackFrame<BMeta, FMeta>,
     },
-    LoadFunction {
-        func: Reg,
-        ret: Reg,
-    },
     PopArgument {
         arg: Reg,
     },
-    PushArguement {
-        arg: Reg,
+    /// start unwinding, running every registered cleanup landing pad
+    Panic {
+        message: String,
+        span: Span,
     },
-    CallFunction,
+    /// register `cleanup` as the block to jump to if a panic unwinds through it
+    PushLandingPad {
+        cleanup: usize,
+    },
+    /// unregister the innermost cleanup landing pad
+    PopLandingPad,
+    /// write the register that belongs to the block control came from, phis are only
+    /// valid at the start of a block and have one source for each of its predecessors,
+    /// all phis of a block read their sources before any of them are written
+    Phi {
+        to: Reg,
+        from: Vec<(usize, Reg)>,
+    },
+}
+
+impl<BMeta, FMeta> Mir<BMeta, FMeta> {
+    /// the register this instruction writes to
+    pub fn def(&self) -> Option<Reg> {
+        match *self {
+            Self::Load { to, .. }
+            | Self::LoadReg { to, .. }
+            | Self::Cast { to, .. }
+            | Self::BinOp { out: to, .. }
+            | Self::PreOp { out: to, .. }
+            | Self::CreateFunc { binding: to, .. }
+            | Self::PopArgument { arg: to }
+            | Self::Phi { to, .. } => Some(to),
+            Self::Print(_)
+            | Self::Panic { .. }
+            | Self::PushLandingPad { .. }
+            | Self::PopLandingPad => None,
+        }
+    }
+
+    /// the registers this instruction reads from, not including the registers of nested frames
+    pub fn uses(&self) -> Vec<Reg> {
+        match *self {
+            Self::LoadReg { from: reg, .. }
+            | Self::Cast { from: reg, .. }
+            | Self::PreOp { arg: reg, .. }
+            | Self::Print(reg) => vec![reg],
+            Self::BinOp { left, right, .. } => vec![left, right],
+            Self::Phi { ref from, .. } => from.iter().map(|&(_, reg)| reg).collect(),
+            Self::Load { .. }
+            | Self::CreateFunc { .. }
+            | Self::PopArgument { .. }
+            | Self::Panic { .. }
+            | Self::PushLandingPad { .. }
+            | Self::PopLandingPad => Vec::new(),
+        }
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Reg> {
+        match *self {
+            Self::Load { ref mut to, .. }
+            | Self::LoadReg { ref mut to, .. }
+            | Self::Cast { ref mut to, .. }
+            | Self::BinOp {
+                out: ref mut to, ..
+            }
+            | Self::PreOp {
+                out: ref mut to, ..
+            }
+            | Self::CreateFunc {
+                binding: ref mut to,
+                ..
+            }
+            | Self::PopArgument { arg: ref mut to }
+            | Self::Phi { ref mut to, .. } => Some(to),
+            Self::Print(_)
+            | Self::Panic { .. }
+            | Self::PushLandingPad { .. }
+            | Self::PopLandingPad => None,
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Reg> {
+        match *self {
+            Self::LoadReg {
+                from: ref mut reg, ..
+            }
+            | Self::Cast {
+                from: ref mut reg, ..
+            }
+            | Self::PreOp {
+                arg: ref mut reg, ..
+            }
+            | Self::Print(ref mut reg) => vec![reg],
+            Self::BinOp {
+                ref mut left,
+                ref mut right,
+                ..
+            } => vec![left, right],
+            Self::Phi { ref mut from, .. } => from.iter_mut().map(|(_, reg)| reg).collect(),
+            Self::Load { .. }
+            | Self::CreateFunc { .. }
+            | Self::PopArgument { .. }
+            | Self::Panic { .. }
+            | Self::PushLandingPad { .. }
+            | Self::PopLandingPad => Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -112,6 +494,8 @@ pub enum Load {
     U32(u32),
     U64(u64),
     U128(u128),
+    /// any `i32`, the unsigned loads only hold the values of integer literals
+    I32(i32),
 }
 
 use std::{cell::Cell, fmt};
@@ -133,13 +517,21 @@ impl fmt::Display for Tabs {
     }
 }
 
+/// the location of an instruction in the source
+struct At(Span);
+impl fmt::Display for At {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@ {}..{}", self.0.start(), self.0.end())
+    }
+}
+
 impl fmt::Display for Reg {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "reg({})", self.0)
     }
 }
 
-impl<BMeta, FMeta> fmt::Display for StackFrame<BMeta, FMeta> {
+impl<BMeta, FMeta: fmt::Display> fmt::Display for StackFrame<BMeta, FMeta> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let blocks = self.blocks().iter().enumerate();
 
@@ -147,11 +539,14 @@ impl<BMeta, FMeta> fmt::Display for StackFrame<BMeta, FMeta> {
             depth.set(depth.get() + 1);
         });
 
+        writeln!(f, "{}FRAME {}", Tabs, self.meta)?;
+
         for (i, block) in blocks.clone() {
             writeln!(f, "{}BLOCK({})", Tabs, i)?;
             for (i, mir) in block.instructions.iter().enumerate() {
                 writeln!(f, "{}{:3}: {}", Tabs, i, mir)?;
             }
+            writeln!(f, "{}     {}", Tabs, block.terminator)?;
             writeln!(f, "{}ENDBLOCK({})", Tabs, i)?;
         }
 
@@ -163,11 +558,9 @@ impl<BMeta, FMeta> fmt::Display for StackFrame<BMeta, FMeta> {
     }
 }
 
-impl<BMeta, FMeta> fmt::Display for Mir<BMeta, FMeta> {
+impl<BMeta, FMeta: fmt::Display> fmt::Display for Mir<BMeta, FMeta> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Self::Jump(target) => write!(f, "jmp {}", target),
-            Self::BranchTrue { cond, target } => write!(f, "branch {} to {}", cond, target),
             Self::Load { to, from } => match from {
                 Load::Bool(from) => write!(f, "load(bool) {} {}", to, from),
                 Load::U8(from) => write!(f, "load(u8) {} {}", to, from),
@@ -175,16 +568,23 @@ impl<BMeta, FMeta> fmt::Display for Mir<BMeta, FMeta> {
                 Load::U32(from) => write!(f, "load(u32) {} {}", to, from),
                 Load::U64(from) => write!(f, "load(u64) {} {}", to, from),
                 Load::U128(from) => write!(f, "load(u128) {} {}", to, from),
+                Load::I32(from) => write!(f, "load(i32) {} {}", to, from),
             },
             Self::LoadReg { to, from } => write!(f, "load(reg) {} {}", to, from),
-            Self::Print(Reg(value)) => write!(f, "print {}", value),
+            Self::Print(value) => write!(f, "print {}", value),
             Self::BinOp {
                 op,
                 out,
                 left,
                 right,
-            } => write!(f, "bin({:?}) {}, {}, {}", op, out, left, right),
-            Self::PreOp { op, out, arg } => write!(f, "bin({:?}) {}, {}", op, out, arg),
+                span,
+            } => write!(f, "bin({:?}) {}, {}, {} {}", op, out, left, right, At(span)),
+            Self::PreOp { op, out, arg, span } => {
+                write!(f, "pre({:?}) {}, {} {}", op, out, arg, At(span))
+            }
+            Self::Cast { to, from, ty, kind } => {
+                write!(f, "cast({:?}) {} {} as {}", kind, to, from, ty.name())
+            }
             Self::CreateFunc {
                 binding,
                 ret,
@@ -194,10 +594,74 @@ impl<BMeta, FMeta> fmt::Display for Mir<BMeta, FMeta> {
                 write!(f, "{}", stack_frame)?;
                 write!(f, "{}     endfn {} -> {}", Tabs, binding, ret)
             }
-            Self::LoadFunction { func, ret } => write!(f, "load(fn) {} -> {}", func, ret),
             Self::PopArgument { arg } => write!(f, "pop(arg) {}", arg),
-            Self::PushArguement { arg } => write!(f, "push(arg) {}", arg),
-            Self::CallFunction => write!(f, "call"),
+            Self::Panic { ref message, span } => write!(f, "panic {:?} {}", message, At(span)),
+            Self::PushLandingPad { cleanup } => write!(f, "push(pad) {}", cleanup),
+            Self::PopLandingPad => write!(f, "pop(pad)"),
+            Self::Phi { to, ref from } => {
+                write!(f, "phi {} [", to)?;
+
+                for (i, &(block, reg)) in from.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{} -> {}", block, reg)?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Goto(target) => write!(f, "jmp {}", target),
+            Self::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "branch {} then {} else {}", cond, then, otherwise),
+            Self::Switch {
+                value,
+                ref cases,
+                otherwise,
+            } => {
+                write!(f, "switch {} [", value)?;
+
+                for (i, &(case, target)) in cases.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{} -> {}", case, target)?;
+                }
+
+                write!(f, "] else {}", otherwise)
+            }
+            Self::Return => write!(f, "return"),
+            Self::Unreachable => write!(f, "unreachable"),
+            Self::Call {
+                func,
+                ref args,
+                ret,
+                next,
+            } => {
+                write!(f, "call {}(", func)?;
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", arg)?;
+                }
+
+                write!(f, ") -> {} then {}", ret, next)
+            }
+            Self::Resume => write!(f, "resume"),
         }
     }
 }
@@ -21,7 +21,7 @@ pub trait Lexer<'str, 'idt> {
     ) -> Option<TokenValue<(GroupPos, Grouping)>>;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Span {
     start: usize,
     end: usize,
@@ -54,6 +54,67 @@ impl Span {
     pub fn to(self, other: Self) -> Self {
         Self::new(self.start, other.end)
     }
+
+    /// the 1-based line and column of the start of this span in `source`
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        let column = before[line_start..].chars().count() + 1;
+
+        (line, column)
+    }
+}
+
+/// the span of each comment in `whitespace`, a span of `source` that only holds
+/// whitespace and comments like the leading whitespace of a token, block comments
+/// nest and an unclosed one runs to the end of `whitespace`
+pub fn comments(source: &str, whitespace: Span) -> Vec<Span> {
+    let text = &source[whitespace.start..whitespace.end];
+    let bytes = text.as_bytes();
+    let mut comments = Vec::new();
+    let mut i = 0;
+
+    while i + 1 < bytes.len() {
+        let start = i;
+
+        match (bytes[i], bytes[i + 1]) {
+            (b'/', b'/') => i = text[i..].find('\n').map_or(text.len(), |pos| i + pos),
+            (b'/', b'*') => {
+                let mut depth = 0;
+
+                while i + 1 < bytes.len() {
+                    match (bytes[i], bytes[i + 1]) {
+                        (b'/', b'*') => {
+                            depth += 1;
+                            i += 2;
+                        }
+                        (b'*', b'/') => {
+                            depth -= 1;
+                            i += 2;
+
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => i += 1,
+                    }
+                }
+
+                if depth > 0 {
+                    i = text.len();
+                }
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+
+        comments.push(Span::new(whitespace.start + start, whitespace.start + i));
+    }
+
+    comments
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -84,10 +145,20 @@ impl fmt::Debug for Ident<'_> {
     }
 }
 
+impl fmt::Display for Ident<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
 impl<'idt> Ident<'idt> {
     pub const fn new(s: InternStr<'idt>) -> Self {
         Self(s)
     }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -165,6 +236,14 @@ macro_rules! kw_gen {
             }
         }
 
+        impl Keyword {
+            pub fn to_str(self) -> &'static str {
+                match self {
+                    $(Keyword::$kw_val => stringify!($kw),)*
+                }
+            }
+        }
+
         #[macro_export]
         macro_rules! kw {
         $(
@@ -241,10 +320,13 @@ kw_gen! {
     pub => Pub
     mod => Mod
 
+    as => As
+
     true => True
     false => False
 
     print => Print
+    panic => Panic
 
     ---
 
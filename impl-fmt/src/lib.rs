@@ -0,0 +1,200 @@
+//! Reprints a token stream in a canonical style.
+//!
+//! The lexer keeps comments in the `leading_whitespace` of each token, so the tokens
+//! together with the source are enough to rebuild the whole file. Only whitespace is
+//! changed: `{}` scopes are indented by four spaces, binary operators and `->` are
+//! surrounded by spaces, and every statement is put on its own line. Comments keep
+//! their place, either on their own line or after the code on the same line, and runs
+//! of blank lines are collapsed into one.
+//!
+//! Formatting the output again gives back the same output.
+
+use core_tokens::{kw, sym, GroupPos, Grouping, Span, Token, Type};
+
+const INDENT: &str = "    ";
+
+/// format `source`, `tokens` must be all of the tokens of `source`
+pub fn format(source: &str, tokens: &[Token<'_, '_>]) -> String {
+    let mut formatter = Formatter {
+        source,
+        output: String::new(),
+        depth: 0,
+        newline: false,
+        blank_line: false,
+        opened_scope: false,
+        after_comment: false,
+        glue_next: false,
+        prev_operand: false,
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        formatter.whitespace(token.leading_whitespace);
+        formatter.token(token, tokens.get(i + 1));
+    }
+
+    // comments after the last token are not part of any token
+    let end = tokens.last().map_or(0, |token| token.span.end());
+    formatter.whitespace(Span::new(end, source.len()));
+
+    if !formatter.output.is_empty() {
+        formatter.output.push('\n');
+    }
+
+    formatter.output
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    output: String,
+    /// the number of `{}` scopes that are open
+    depth: usize,
+    /// the next token or comment must start a new line
+    newline: bool,
+    /// the source had a blank line before the next token or comment
+    blank_line: bool,
+    /// the last thing written was a `{`
+    opened_scope: bool,
+    /// the last thing written was a block comment
+    after_comment: bool,
+    /// the next token comes right after the last one, without a space
+    glue_next: bool,
+    /// the last token ends an operand, so a `-` after it is a binary operator
+    prev_operand: bool,
+}
+
+impl Formatter<'_> {
+    /// write `text`, starting a new line if one is pending,
+    /// otherwise separated from the last item with a space if `space` is set
+    fn write(&mut self, text: &str, space: bool) {
+        if std::mem::replace(&mut self.newline, false) && !self.output.is_empty() {
+            self.output.push('\n');
+
+            // scopes don't start with a blank line
+            if self.blank_line && !self.opened_scope {
+                self.output.push('\n');
+            }
+
+            for _ in 0..self.depth {
+                self.output.push_str(INDENT);
+            }
+        } else if space && !self.output.is_empty() {
+            self.output.push(' ');
+        }
+
+        self.output.push_str(text);
+
+        self.blank_line = false;
+        self.opened_scope = false;
+        self.after_comment = false;
+    }
+
+    /// write the comments in the whitespace at `span`
+    fn whitespace(&mut self, span: Span) {
+        let text = &self.source[span.start()..span.end()];
+        let mut gap_start = 0;
+
+        for comment in core_tokens::comments(self.source, span) {
+            let start = comment.start() - span.start();
+            let end = comment.end() - span.start();
+            let gap = &text[gap_start..start];
+            let comment = text[start..end].trim_end();
+            let is_line_comment = comment.starts_with("//");
+
+            self.blank_line |= gap.matches('\n').count() > 1;
+
+            if gap.contains('\n') || self.output.is_empty() {
+                // a comment on its own line
+                self.newline = true;
+                self.write(comment, true);
+                self.newline = is_line_comment;
+            } else {
+                // a comment after some code stays on the same line as that code
+                let newline = std::mem::replace(&mut self.newline, false);
+                self.write(comment, true);
+                self.newline = newline || is_line_comment;
+            }
+
+            self.after_comment = !is_line_comment;
+            gap_start = end;
+        }
+
+        let gap = &text[gap_start..];
+
+        // code after a comment stays on the next line if it was there in the source
+        if gap_start != 0 && gap.contains('\n') {
+            self.newline = true;
+        }
+
+        self.blank_line |= gap.matches('\n').count() > 1;
+    }
+
+    fn token(&mut self, token: &Token<'_, '_>, next: Option<&Token<'_, '_>>) {
+        let text = &self.source[token.span.start()..token.span.end()];
+        let space = self.after_comment || !self.glue_next;
+
+        let is_prefix = match token.ty {
+            Type::Symbol(sym!(!)) => true,
+            Type::Symbol(sym!(-)) => !self.prev_operand,
+            _ => false,
+        };
+
+        match token.ty {
+            Type::Grouping(GroupPos::Start, Grouping::Curly) => {
+                self.write(text, space);
+                self.depth += 1;
+                self.opened_scope = true;
+
+                // an empty scope stays on one line
+                self.newline = match next.map(|next| next.ty) {
+                    Some(Type::Grouping(GroupPos::End, Grouping::Curly)) => false,
+                    _ => true,
+                };
+            }
+            Type::Grouping(GroupPos::End, Grouping::Curly) => {
+                self.depth = self.depth.saturating_sub(1);
+                self.newline |= !self.opened_scope;
+                self.blank_line = false;
+                self.write(text, false);
+
+                // `}` ends the statement, unless the expression continues after it
+                self.newline = match next.map(|next| next.ty) {
+                    Some(Type::Symbol(_))
+                    | Some(Type::Grouping(GroupPos::End, _))
+                    | Some(Type::Keyword(kw!(else)))
+                    | Some(Type::Keyword(kw!(as))) => false,
+                    _ => true,
+                };
+            }
+            Type::Symbol(sym!(;)) => {
+                self.write(text, false);
+                self.newline = true;
+            }
+            Type::Symbol(sym!(,))
+            | Type::Symbol(sym!(:))
+            | Type::Symbol(sym!(.))
+            | Type::Symbol(sym!(::))
+            | Type::Grouping(GroupPos::End, _) => self.write(text, self.after_comment),
+            _ => self.write(text, space),
+        }
+
+        self.glue_next = is_prefix
+            || match token.ty {
+                Type::Grouping(GroupPos::Start, Grouping::Paren)
+                | Type::Grouping(GroupPos::Start, Grouping::Square)
+                | Type::Symbol(sym!(.))
+                | Type::Symbol(sym!(::)) => true,
+                _ => false,
+            };
+
+        self.prev_operand = match token.ty {
+            Type::Ident(_)
+            | Type::Str(_)
+            | Type::Int(_)
+            | Type::Float(_)
+            | Type::Keyword(kw!(true))
+            | Type::Keyword(kw!(false))
+            | Type::Grouping(GroupPos::End, _) => true,
+            _ => false,
+        };
+    }
+}
@@ -229,7 +229,7 @@ impl<'input, 'str, 'idt> core_tokens::Lexer<'str, 'idt> for Lexer<'input, 'str,
                 // otherwise there was no end quote
 
                 self.input = "";
-                println!("ERROR: Detected an invalid quote");
+                eprintln!("ERROR: Detected an invalid quote");
                 return None;
             }
 
@@ -402,7 +402,7 @@ impl<'input, 'str, 'idt> core_tokens::Lexer<'str, 'idt> for Lexer<'input, 'str,
             // otherwise there was no end quote
 
             self.input = "";
-            println!("ERROR: Detected an invalid quote");
+            eprintln!("ERROR: Detected an invalid quote");
             return None;
         }
 
@@ -15,6 +15,9 @@ pub struct Context<'str, 'idt, 'hir> {
 pub struct HirParser<'str, 'idt, 'hir, L> {
     context: Context<'str, 'idt, 'hir>,
     lexer: PeekableLexer<'str, 'idt, L, 2>,
+    /// whether the expression being parsed is the condition of an `if`, which ends at
+    /// the scope of its branch, so that scope isn't taken as an argument
+    in_condition: bool,
 }
 
 type TNode<N> = <N as HasNode>::Node;
@@ -46,6 +49,7 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
         Self {
             context,
             lexer: PeekableLexer::new(lexer),
+            in_condition: false,
         }
     }
 
@@ -73,6 +77,7 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
 
         match token.ty {
             Type::Keyword(kw!(print)) => self.parse_print(),
+            Type::Keyword(kw!(panic)) => self.parse_panic(),
             Type::Keyword(kw!(let)) => self.parse_let(),
             Type::Keyword(kw!(loop)) => self.parse_loop(),
             Type::Keyword(kw!(break)) => self.parse_break(),
@@ -91,6 +96,14 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
     }
 
     pub fn parse_scope(&mut self) -> Option<Node<core_hir::Scope<'str, 'idt, 'hir>>> {
+        // the statements of a scope in a condition end at its `}`, not at a `{`
+        let in_condition = std::mem::replace(&mut self.in_condition, false);
+        let scope = self.parse_scope_inner();
+        self.in_condition = in_condition;
+        scope
+    }
+
+    fn parse_scope_inner(&mut self) -> Option<Node<core_hir::Scope<'str, 'idt, 'hir>>> {
         use core_tokens::{GroupPos, Grouping, Type};
 
         let mut inner = Vec::new();
@@ -125,7 +138,32 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
 
         Some(Node {
             span: start.span.to(end.span),
-            val: Hir::Print(ident.ty),
+            val: Hir::Print(Node {
+                val: ident.ty,
+                span: ident.span,
+            }),
+        })
+    }
+
+    pub fn parse_panic(&mut self) -> Option<TNode<Self>> {
+        use core_tokens::Type;
+
+        let start = self.lexer.parse_keyword(Some(kw!(panic)))?;
+
+        let message = match self.lexer.peek_token(1).next()?.ty {
+            Type::Str(message) => Some(message),
+            _ => None,
+        };
+
+        if message.is_some() {
+            self.lexer.parse_token();
+        }
+
+        let end = self.lexer.parse_sym(Some(sym!(;)))?;
+
+        Some(Node {
+            span: start.span.to(end.span),
+            val: Hir::Panic(message),
         })
     }
 
@@ -183,7 +221,7 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
         use core_tokens::{GroupPos, Grouping, Token, Type};
 
         let start = self.lexer.parse_keyword(Some(kw!(if)))?;
-        let cond = self.parse_expr()?;
+        let cond = self.parse_condition()?;
         let branch = self.parse_scope()?;
 
         let mut end_span = branch.span;
@@ -211,7 +249,7 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
             match peek.ty {
                 Type::Keyword(kw!(if)) => {
                     self.lexer.parse_keyword(Some(kw!(if)));
-                    let cond = self.parse_expr()?;
+                    let cond = self.parse_condition()?;
                     let branch = self.parse_scope()?;
                     end_span = branch.span;
 
@@ -239,6 +277,15 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
         })
     }
 
+    /// parse the condition of an `if` or `else if`, a scope after it is the branch,
+    /// not an argument of the condition
+    pub fn parse_condition(&mut self) -> Option<TExpr<Self>> {
+        let in_condition = std::mem::replace(&mut self.in_condition, true);
+        let cond = self.parse_expr();
+        self.in_condition = in_condition;
+        cond
+    }
+
     pub fn parse_loop(&mut self) -> Option<TNode<Self>> {
         let start = self.lexer.parse_keyword(Some(kw!(loop)))?;
         let block = self.parse_scope()?;
@@ -332,6 +379,7 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
 
             parameter_list.push(Parameter {
                 name: name.ty,
+                span: name.span,
                 ty: None,
             });
         }
@@ -376,7 +424,7 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
 
                         first = Node {
                             span: left.span.to(right.span),
-                            val: Expr::BinOp(core_hir::Operator::Symbol(sym), right, left),
+                            val: Expr::BinOp(core_hir::Operator::Symbol(sym), left, right),
                         };
 
                         continue;
@@ -411,20 +459,58 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
     pub fn parse_bin_prod(&mut self) -> Option<TExpr<Self>> {
         self.parse_bin_op(
             |sym| match sym {
-                sym!(+) | sym!(-) => true,
+                sym!(*) | sym!(/) => true,
                 _ => false,
             },
-            Self::parse_func_app,
+            Self::parse_cast,
         )
     }
 
+    pub fn parse_cast(&mut self) -> Option<TExpr<Self>> {
+        use core_tokens::{TokenValue, Type};
+
+        let mut value = self.parse_func_app()?;
+
+        while let Some(TokenValue {
+            ty: Type::Keyword(kw!(as)),
+            ..
+        }) = self.peek()
+        {
+            self.lexer.parse_keyword(Some(kw!(as)))?;
+
+            let ty = self.parse_simple_expr()?;
+            let ty = Node {
+                span: ty.span,
+                val: Expr::Simple(ty),
+            };
+
+            let value_node = self.context.exprs.alloc(value);
+            let ty = self.context.exprs.alloc(ty);
+
+            value = Node {
+                span: value_node.span.to(ty.span),
+                val: Expr::Cast {
+                    value: value_node,
+                    ty,
+                },
+            };
+        }
+
+        Some(value)
+    }
+
     pub fn parse_func_app(&mut self) -> Option<TExpr<Self>> {
         use smallvec::SmallVec;
         let mut name_args = SmallVec::<[_; 1]>::new();
         name_args.push(self.parse_basic_expr()?);
         let mut span = name_args[0].span;
 
-        while let Some(arg) = self.parse_basic_expr() {
+        while !(self.in_condition && self.peeks_scope()) {
+            let arg = match self.parse_basic_expr() {
+                Some(arg) => arg,
+                None => break,
+            };
+
             span = span.to(arg.span);
             name_args.push(arg)
         }
@@ -464,7 +550,13 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
                     Type::Grouping(GroupPos::Start, Grouping::Paren) => {
                         self.lexer
                             .parse_grouping(Some((GroupPos::Start, Grouping::Paren)));
-                        let expr = self.parse_expr()?;
+
+                        // a scope in parentheses can't be mistaken for a branch
+                        let in_condition = std::mem::replace(&mut self.in_condition, false);
+                        let expr = self.parse_expr();
+                        self.in_condition = in_condition;
+
+                        let expr = expr?;
                         self.lexer
                             .parse_grouping(Some((GroupPos::End, Grouping::Paren)));
 
@@ -476,6 +568,18 @@ impl<'str, 'idt, 'hir, L: Lexer<'str, 'idt>> HirParser<'str, 'idt, 'hir, L> {
         }
     }
 
+    fn peeks_scope(&mut self) -> bool {
+        use core_tokens::{GroupPos, Grouping, Type};
+
+        match self.peek() {
+            Some(token) => match token.ty {
+                Type::Grouping(GroupPos::Start, Grouping::Curly) => true,
+                _ => false,
+            },
+            None => false,
+        }
+    }
+
     pub fn parse_simple_expr(&mut self) -> Option<TSimpleExpr<Self>> {
         use core_tokens::Type;
 
@@ -0,0 +1,493 @@
+//! Analyses of the control flow graph of a `StackFrame`, which optimisation passes
+//! can compute once and share
+//!
+//! * `Cfg` - the edges between blocks, which blocks are reachable, and their reverse postorder
+//! * `Dominators` - the dominator tree and dominance frontiers
+//! * `Loops` - the natural loops and how deeply they are nested
+//!
+//! Unwinding is treated as an edge, so a cleanup landing pad is a successor of every
+//! block that pushes it.
+//!
+//! The results describe the frame they were computed from, and must be recomputed
+//! after a pass changes its control flow.
+
+use core_mir::Terminator;
+use core_tokens::Span;
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    /// the reachable blocks in reverse postorder
+    rpo: Vec<usize>,
+    /// the position of each block in `rpo`, `None` for unreachable blocks
+    rpo_index: Vec<Option<usize>>,
+}
+
+impl Cfg {
+    pub fn new(frame: &StackFrame) -> Self {
+        let blocks = frame.blocks();
+
+        let mut successors = blocks
+            .iter()
+            .map(|block| block.terminator.successors())
+            .collect::<Vec<_>>();
+
+        for (b, block) in blocks.iter().enumerate() {
+            for mir in block.instructions.iter() {
+                if let Mir::PushLandingPad { cleanup } = *mir {
+                    successors[b].push(cleanup);
+                }
+            }
+
+            let mut seen = Vec::with_capacity(successors[b].len());
+            successors[b].retain(|&target| {
+                let is_new = !seen.contains(&target);
+                seen.push(target);
+                is_new
+            });
+        }
+
+        let mut predecessors = vec![Vec::new(); blocks.len()];
+
+        for (from, targets) in successors.iter().enumerate() {
+            for &to in targets {
+                predecessors[to].push(from);
+            }
+        }
+
+        let mut postorder = Vec::with_capacity(blocks.len());
+
+        if !blocks.is_empty() {
+            let mut visited = vec![false; blocks.len()];
+            // each block on the path from the entry, with the next successor to visit
+            let mut stack = vec![(0, 0)];
+            visited[0] = true;
+
+            while let Some(&mut (b, ref mut next)) = stack.last_mut() {
+                match successors[b].get(*next) {
+                    Some(&child) => {
+                        *next += 1;
+
+                        if !visited[child] {
+                            visited[child] = true;
+                            stack.push((child, 0));
+                        }
+                    }
+                    None => {
+                        postorder.push(b);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        let rpo = postorder.into_iter().rev().collect::<Vec<_>>();
+
+        let mut rpo_index = vec![None; blocks.len()];
+
+        for (i, &b) in rpo.iter().enumerate() {
+            rpo_index[b] = Some(i);
+        }
+
+        Self {
+            successors,
+            predecessors,
+            rpo,
+            rpo_index,
+        }
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.successors.len()
+    }
+
+    pub fn successors(&self, block: usize) -> &[usize] {
+        &self.successors[block]
+    }
+
+    pub fn predecessors(&self, block: usize) -> &[usize] {
+        &self.predecessors[block]
+    }
+
+    /// the reachable blocks, each one after all of its predecessors except along back edges
+    pub fn reverse_postorder(&self) -> &[usize] {
+        &self.rpo
+    }
+
+    /// the position of `block` in the reverse postorder
+    pub fn rpo_index(&self, block: usize) -> Option<usize> {
+        self.rpo_index[block]
+    }
+
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.rpo_index[block].is_some()
+    }
+
+    /// the unreachable blocks, grouped into regions which each start at a block that
+    /// nothing jumps to, followed by the blocks only reachable from it
+    pub fn dead_regions(&self) -> Vec<Vec<usize>> {
+        let mut seen = self
+            .rpo_index
+            .iter()
+            .map(Option::is_some)
+            .collect::<Vec<_>>();
+
+        // blocks without predecessors come first, so a region starts where the dead code
+        // starts, dead cycles that nothing jumps into are picked up afterwards
+        let starts = (0..self.block_count())
+            .filter(|&b| self.predecessors[b].is_empty())
+            .chain(0..self.block_count())
+            .collect::<Vec<_>>();
+
+        let mut regions = Vec::new();
+
+        for start in starts {
+            if seen[start] {
+                continue;
+            }
+
+            seen[start] = true;
+
+            let mut region = Vec::new();
+            let mut stack = vec![start];
+
+            while let Some(b) = stack.pop() {
+                region.push(b);
+
+                for &child in self.successors[b].iter().rev() {
+                    if !seen[child] {
+                        seen[child] = true;
+                        stack.push(child);
+                    }
+                }
+            }
+
+            regions.push(region);
+        }
+
+        regions
+    }
+}
+
+/// the dominator tree of the reachable blocks, a block `a` dominates `b` if every path
+/// from the entry to `b` goes through `a`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    /// the immediate dominator of each block, the entry is its own immediate dominator
+    /// and unreachable blocks have none
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl Dominators {
+    /// compute the dominators with the algorithm from "A Simple, Fast Dominance Algorithm"
+    /// by Cooper, Harvey and Kennedy
+    pub fn new(cfg: &Cfg) -> Self {
+        let mut idom = vec![None; cfg.block_count()];
+        let rpo = cfg.reverse_postorder();
+
+        if let Some(&entry) = rpo.first() {
+            idom[entry] = Some(entry);
+        }
+
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            let index = |b: usize| cfg.rpo_index(b).unwrap();
+
+            while a != b {
+                while index(a) > index(b) {
+                    a = idom[a].unwrap();
+                }
+
+                while index(b) > index(a) {
+                    b = idom[b].unwrap();
+                }
+            }
+
+            a
+        };
+
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for &b in rpo.iter().skip(1) {
+                let new_idom = cfg
+                    .predecessors(b)
+                    .iter()
+                    .copied()
+                    .filter(|&pred| idom[pred].is_some())
+                    .fold(None, |new_idom, pred| match new_idom {
+                        None => Some(pred),
+                        Some(new_idom) => Some(intersect(&idom, pred, new_idom)),
+                    });
+
+                if idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![Vec::new(); cfg.block_count()];
+
+        for &b in rpo.iter().skip(1) {
+            children[idom[b].unwrap()].push(b);
+        }
+
+        Self { idom, children }
+    }
+
+    /// the closest block that strictly dominates `block`, `None` for the entry
+    /// and unreachable blocks
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        self.idom[block].filter(|&idom| idom != block)
+    }
+
+    /// the blocks `block` immediately dominates, its children in the dominator tree
+    pub fn children(&self, block: usize) -> &[usize] {
+        &self.children[block]
+    }
+
+    /// whether `a` dominates `b`, every block dominates itself
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        if self.idom[b].is_none() {
+            return false;
+        }
+
+        loop {
+            if a == b {
+                return true;
+            }
+
+            match self.idom(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// the dominance frontier of each block, the blocks where its dominance ends:
+    /// a block is in the frontier of `a` if `a` dominates one of its predecessors,
+    /// but doesn't strictly dominate the block itself
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<usize>> {
+        let mut frontiers = vec![Vec::new(); cfg.block_count()];
+
+        for &b in cfg.reverse_postorder() {
+            let preds = cfg.predecessors(b);
+
+            if preds.len() < 2 {
+                continue;
+            }
+
+            for &pred in preds.iter().filter(|&&pred| cfg.is_reachable(pred)) {
+                let mut runner = pred;
+
+                while Some(runner) != self.idom(b) {
+                    if !frontiers[runner].contains(&b) {
+                        frontiers[runner].push(b);
+                    }
+
+                    match self.idom(runner) {
+                        Some(idom) => runner = idom,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        frontiers
+    }
+}
+
+/// a natural loop, the blocks that can reach a back edge to `header` without going
+/// through `header`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    /// the blocks in the loop, including the header, in ascending order
+    pub blocks: Vec<usize>,
+    /// the blocks that jump back to the header
+    pub latches: Vec<usize>,
+    /// the index of the closest loop this one is nested in
+    pub parent: Option<usize>,
+    /// how many loops this one is nested in, including itself
+    pub depth: usize,
+}
+
+impl Loop {
+    pub fn contains(&self, block: usize) -> bool {
+        self.blocks.binary_search(&block).is_ok()
+    }
+}
+
+/// the natural loops of a frame, loops which share a header are merged into one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loops {
+    /// ordered so that every loop comes after the loops it is nested in
+    loops: Vec<Loop>,
+    /// the index of the innermost loop each block is in
+    innermost: Vec<Option<usize>>,
+}
+
+impl Loops {
+    pub fn new(cfg: &Cfg, doms: &Dominators) -> Self {
+        let mut loops = Vec::<Loop>::new();
+
+        // headers dominate their loops, so outer loops are found before inner loops
+        for &header in cfg.reverse_postorder() {
+            let latches = cfg
+                .predecessors(header)
+                .iter()
+                .copied()
+                .filter(|&pred| doms.dominates(header, pred))
+                .collect::<Vec<_>>();
+
+            if latches.is_empty() {
+                continue;
+            }
+
+            let mut in_loop = vec![false; cfg.block_count()];
+            in_loop[header] = true;
+
+            let mut stack = latches.clone();
+
+            while let Some(b) = stack.pop() {
+                if in_loop[b] {
+                    continue;
+                }
+
+                in_loop[b] = true;
+
+                stack.extend(
+                    cfg.predecessors(b)
+                        .iter()
+                        .copied()
+                        .filter(|&pred| cfg.is_reachable(pred) && !in_loop[pred]),
+                );
+            }
+
+            let blocks = (0..cfg.block_count())
+                .filter(|&b| in_loop[b])
+                .collect::<Vec<_>>();
+
+            let parent = loops.iter().rposition(|outer| outer.contains(header));
+            let depth = parent.map_or(0, |parent| loops[parent].depth) + 1;
+
+            loops.push(Loop {
+                header,
+                blocks,
+                latches,
+                parent,
+                depth,
+            });
+        }
+
+        let mut innermost = vec![None; cfg.block_count()];
+
+        for (i, l) in loops.iter().enumerate() {
+            for &b in l.blocks.iter() {
+                innermost[b] = Some(i);
+            }
+        }
+
+        Self { loops, innermost }
+    }
+
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// the index of the innermost loop `block` is in
+    pub fn innermost(&self, block: usize) -> Option<usize> {
+        self.innermost[block]
+    }
+
+    /// how many loops `block` is in, 0 if it isn't in a loop
+    pub fn depth(&self, block: usize) -> usize {
+        self.innermost[block].map_or(0, |l| self.loops[l].depth)
+    }
+
+    pub fn is_header(&self, block: usize) -> bool {
+        self.innermost[block].map_or(false, |l| self.loops[l].header == block)
+    }
+}
+
+/// remove the blocks of `frame` that can't be reached, and the sources of phis that no
+/// longer come from a predecessor of their block, returns how many blocks were removed
+///
+/// passes that change terminators call this afterwards, so the CFG stays free of dead blocks
+pub fn remove_unreachable(frame: &mut StackFrame) -> usize {
+    let cfg = Cfg::new(frame);
+    let keep = (0..cfg.block_count())
+        .map(|b| cfg.is_reachable(b))
+        .collect::<Vec<_>>();
+    let removed = keep.iter().filter(|&&keep| !keep).count();
+
+    if removed != 0 {
+        frame.retain_blocks(&keep);
+    }
+
+    let predecessors = frame.predecessors();
+
+    for (block, predecessors) in frame.blocks_mut().iter_mut().zip(predecessors) {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::Phi { ref mut from, .. } = *mir {
+                from.retain(|(source, _)| predecessors.contains(source));
+            }
+        }
+    }
+
+    removed
+}
+
+/// whether a landing pad of `frame` has a cleanup that does more than continue unwinding
+///
+/// a cleanup can be entered from the middle of a block, which a phi can't express, so passes
+/// that need SSA form leave these frames as they are, a cleanup that only resumes unwinding
+/// reads no registers and doesn't need any phis
+pub fn has_cleanups(frame: &StackFrame) -> bool {
+    let blocks = frame.blocks();
+
+    blocks.iter().any(|block| {
+        block.instructions.iter().any(|mir| match *mir {
+            Mir::PushLandingPad { cleanup } => {
+                !blocks[cleanup].instructions.is_empty()
+                    || blocks[cleanup].terminator != Terminator::Resume
+            }
+            _ => false,
+        })
+    })
+}
+
+/// the span of each region of unreachable code in `frame` and all of its nested frames,
+/// regions without any instructions are skipped, since they don't hold any code the user wrote
+pub fn unreachable_code(frame: &StackFrame) -> Vec<Option<Span>> {
+    let mut spans = Vec::new();
+    let cfg = Cfg::new(frame);
+    let blocks = frame.blocks();
+
+    for region in cfg.dead_regions() {
+        if region.iter().all(|&b| blocks[b].instructions.is_empty()) {
+            continue;
+        }
+
+        spans.push(region.iter().find_map(|&b| blocks[b].meta.span));
+    }
+
+    for block in blocks {
+        for mir in block.instructions.iter() {
+            if let Mir::CreateFunc {
+                ref stack_frame, ..
+            } = *mir
+            {
+                spans.extend(unreachable_code(stack_frame));
+            }
+        }
+    }
+
+    spans
+}
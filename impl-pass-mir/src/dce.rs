@@ -0,0 +1,223 @@
+//! Removes the instructions whose results are never read, and the blocks that can't be reached
+//!
+//! An instruction is dead if the register it writes isn't live right after it, either
+//! because nothing reads the register at all, or because every path writes it again
+//! before reading it, a dead store. Removing an instruction can make the instructions
+//! that computed its operands dead, so this is repeated until nothing changes.
+//!
+//! Instructions with other effects are always kept, even if their result is dead: prints,
+//! panics, landing pads, popping arguments, and arithmetic, which may overflow or divide
+//! by zero. Calls are terminators, so they are never removed either.
+//!
+//! Works on frames both in and out of SSA form.
+
+use core_mir::{BinOpType, PreOpType, Reg, Terminator};
+
+use std::fmt;
+use std::ops::AddAssign;
+
+use super::cfg::{self, Cfg};
+use super::liveness::Liveness;
+use super::*;
+
+/// how much dead code was removed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// instructions whose result is never read
+    pub unused: usize,
+    /// instructions whose result is written again, or no longer read, on every path
+    /// before it is read
+    pub dead_stores: usize,
+    /// blocks that can't be reached
+    pub blocks: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.unused += other.unused;
+        self.dead_stores += other.dead_stores;
+        self.blocks += other.blocks;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} unused instructions, {} dead stores and {} unreachable blocks",
+            self.unused, self.dead_stores, self.blocks
+        )
+    }
+}
+
+/// remove the dead code of `frame` and of all of its functions
+///
+/// the registers are renumbered if anything was removed, so the types of the frame
+/// must be inferred again
+pub fn eliminate(frame: &mut StackFrame) -> Stats {
+    eliminate_frame(frame, &mut None)
+}
+
+fn eliminate_frame(frame: &mut StackFrame, ret: &mut Option<Reg>) -> Stats {
+    let mut stats = Stats::default();
+
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ret: ref mut func_ret,
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                let mut ret = Some(*func_ret);
+                stats += eliminate_frame(stack_frame, &mut ret);
+                *func_ret = ret.unwrap();
+            }
+        }
+    }
+
+    let mut local = Stats {
+        blocks: cfg::remove_unreachable(frame),
+        ..Stats::default()
+    };
+
+    while remove_dead(frame, *ret, &mut local) {}
+
+    if local != Stats::default() {
+        let max_reg_count = frame.meta.max_reg_count;
+        ssa::compact(frame, max_reg_count, ret);
+    }
+
+    stats += local;
+    stats
+}
+
+/// remove the instructions that are dead according to the current liveness of `frame`,
+/// returns whether any were removed
+fn remove_dead(frame: &mut StackFrame, ret: Option<Reg>, stats: &mut Stats) -> bool {
+    let cfg = Cfg::new(frame);
+    let liveness = Liveness::new(frame, &cfg, ret);
+    let max_reg_count = frame.meta.max_reg_count;
+
+    let mut read = vec![false; max_reg_count];
+    // a cleanup can be entered from the middle of a block, so the registers it reads
+    // are never treated as dead
+    let mut pinned = vec![false; max_reg_count];
+
+    for block in frame.blocks() {
+        for mir in block.instructions.iter() {
+            for reg in mir.uses() {
+                read[reg.0] = true;
+            }
+
+            if let Mir::PushLandingPad { cleanup } = *mir {
+                for reg in liveness.live_in(cleanup) {
+                    pinned[reg.0] = true;
+                }
+            }
+        }
+
+        for reg in block.terminator.uses() {
+            read[reg.0] = true;
+        }
+    }
+
+    if let Some(ret) = ret {
+        read[ret.0] = true;
+    }
+
+    let mut changed = false;
+
+    for (b, block) in frame.blocks_mut().iter_mut().enumerate() {
+        let mut live = vec![false; max_reg_count];
+
+        for reg in liveness.live_out(b) {
+            live[reg.0] = true;
+        }
+
+        if let Some(def) = block.terminator.def() {
+            live[def.0] = false;
+        }
+
+        for reg in block.terminator.uses() {
+            live[reg.0] = true;
+        }
+
+        if let (Some(ret), Terminator::Return) = (ret, &block.terminator) {
+            live[ret.0] = true;
+        }
+
+        let mut keep = vec![true; block.instructions.len()];
+
+        for (i, mir) in block.instructions.iter().enumerate().rev() {
+            let def = match mir.def() {
+                Some(def) => def,
+                None => {
+                    mark_uses(mir, &mut live);
+                    continue;
+                }
+            };
+
+            if live[def.0] || pinned[def.0] || !is_pure(mir) {
+                live[def.0] = false;
+                mark_uses(mir, &mut live);
+                continue;
+            }
+
+            keep[i] = false;
+            changed = true;
+
+            if read[def.0] {
+                stats.dead_stores += 1;
+            } else {
+                stats.unused += 1;
+            }
+        }
+
+        let mut keep = keep.into_iter();
+        block.instructions.retain(|_| keep.next().unwrap());
+    }
+
+    changed
+}
+
+/// the sources of a phi are read at the end of the blocks they come from, not in its own block
+fn mark_uses(mir: &Mir, live: &mut [bool]) {
+    if let Mir::Phi { .. } = *mir {
+    } else {
+        for reg in mir.uses() {
+            live[reg.0] = true;
+        }
+    }
+}
+
+/// whether `mir` does nothing but write its result, so it can be removed if the result
+/// is never read
+pub(crate) fn is_pure(mir: &Mir) -> bool {
+    match *mir {
+        Mir::Load { .. }
+        | Mir::LoadReg { .. }
+        | Mir::Cast { .. }
+        | Mir::CreateFunc { .. }
+        | Mir::Phi { .. } => true,
+        // arithmetic panics on overflow and division by zero, comparisons never panic
+        Mir::BinOp { op, .. } => match op {
+            BinOpType::Add | BinOpType::Sub | BinOpType::Mul | BinOpType::Div => false,
+            BinOpType::Equal
+            | BinOpType::NotEqual
+            | BinOpType::LessThan
+            | BinOpType::LessThanOrEqual
+            | BinOpType::GreaterThan
+            | BinOpType::GreaterThanOrEqual => true,
+        },
+        Mir::PreOp { op, .. } => match op {
+            PreOpType::Not => true,
+            PreOpType::Neg => false,
+        },
+        Mir::Print(_)
+        | Mir::PopArgument { .. }
+        | Mir::Panic { .. }
+        | Mir::PushLandingPad { .. }
+        | Mir::PopLandingPad => false,
+    }
+}
@@ -1,8 +1,10 @@
 use core_hir::{BindingMode, Expr, Hir, Literal, Node, Pattern, SimpleExpr};
-use core_mir::{Load, Reg};
-use core_tokens::Ident;
+use core_mir::{Load, Reg, Terminator};
+use core_tokens::{Ident, Span};
+use impl_pass_resolve::{DefId, DefKind, Resolution};
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
 
 use super::*;
 
@@ -10,6 +12,35 @@ pub struct Context {
     // pub types: &'tcx Cache<Type>,
 }
 
+/// a part of `hir` that can't be encoded, either because the language doesn't
+/// allow it where it is or because it can't be lowered to MIR yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub message: String,
+    /// the location of the expression or statement that couldn't be encoded
+    pub span: Option<Span>,
+}
+
+impl Error {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    /// `what` parses, but the encoder doesn't support it yet
+    fn unsupported(what: &str, span: Span) -> Self {
+        Self::new(format!("{} can't be lowered to MIR yet", what), span)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
 struct Loop<'idt> {
     label: Option<Ident<'idt>>,
     start: usize,
@@ -17,23 +48,42 @@ struct Loop<'idt> {
     exit: usize,
 }
 
-#[derive(Default)]
-struct Encoder<'idt> {
+struct Encoder<'r, 'idt> {
+    resolution: &'r Resolution<'idt>,
+    /// the register of each binding of this frame
+    bindings: HashMap<DefId, Reg>,
     blocks: Vec<Block>,
-    scopes: Vec<Scope<'idt>>,
     loop_stack: Vec<Loop<'idt>>,
     max_reg_count: usize,
-    current_scope: usize,
     current_block: usize,
 }
 
-#[derive(Default)]
-pub struct Scope<'idt> {
-    parent: usize,
-    locals: HashMap<Ident<'idt>, Reg>,
+/// encode `hir`, every name in it must be resolved by `resolution`
+pub fn write<
+    'tcx,
+    'str: 'hir,
+    'idt: 'hir,
+    'hir,
+    H: IntoIterator<Item = Node<Hir<'str, 'idt, 'hir>>>,
+>(
+    hir: H,
+    resolution: &Resolution<'idt>,
+) -> Result<StackFrame, Error> {
+    write_with_bindings(hir, resolution).map(|(frame, _)| frame)
 }
 
-pub fn write<
+/// the top level bindings of the frames encoded so far, a frame encoded with
+/// `write_continued` can read and write them, like each input of the repl
+#[derive(Debug, Default, Clone)]
+pub struct Globals<'idt> {
+    pub bindings: HashMap<Ident<'idt>, Reg>,
+    /// the number of registers used by the earlier frames
+    pub reg_count: usize,
+}
+
+/// encode `hir`, and return the register of each binding of the top level frame,
+/// the bindings of functions are in the frames of those functions
+pub fn write_with_bindings<
     'tcx,
     'str: 'hir,
     'idt: 'hir,
@@ -41,27 +91,74 @@ pub fn write<
     H: IntoIterator<Item = Node<Hir<'str, 'idt, 'hir>>>,
 >(
     hir: H,
-) -> Option<StackFrame> {
-    let mut encoder = Encoder::default();
-
-    encoder.blocks.push(Block {
-        instructions: Vec::new(),
-        meta: BlockMeta {
-            parents: HashSet::new(),
-            children: HashSet::new(),
-        },
-    });
+    resolution: &Resolution<'idt>,
+) -> Result<(StackFrame, HashMap<DefId, Reg>), Error> {
+    encode_frame(Encoder::new(resolution), hir)
+}
 
-    encoder.scopes.push(Scope::default());
+/// encode `hir` after the frames that defined `globals`, and return the globals after it,
+/// `resolution` must come from `resolve_continued` with the names of `globals`
+///
+/// the registers of `globals` keep their numbers, so the frame must be run with
+/// their values already assigned
+pub fn write_continued<
+    'tcx,
+    'str: 'hir,
+    'idt: 'hir,
+    'hir,
+    H: IntoIterator<Item = Node<Hir<'str, 'idt, 'hir>>>,
+>(
+    hir: H,
+    resolution: &Resolution<'idt>,
+    globals: &Globals<'idt>,
+) -> Result<(StackFrame, Globals<'idt>), Error> {
+    let mut encoder = Encoder::new(resolution);
+    encoder.max_reg_count = globals.reg_count;
+
+    for (i, def) in resolution.defs.iter().enumerate() {
+        if def.kind == DefKind::Global {
+            encoder
+                .bindings
+                .insert(DefId(i), globals.bindings[&def.name]);
+        }
+    }
+
+    let (frame, bindings) = encode_frame(encoder, hir)?;
+
+    let globals = Globals {
+        bindings: resolution
+            .top_level
+            .iter()
+            .map(|(&name, def)| (name, bindings[def]))
+            .collect(),
+        reg_count: frame.meta.max_reg_count,
+    };
+
+    Ok((frame, globals))
+}
 
+fn encode_frame<
+    'tcx,
+    'str: 'hir,
+    'idt: 'hir,
+    'hir,
+    H: IntoIterator<Item = Node<Hir<'str, 'idt, 'hir>>>,
+>(
+    mut encoder: Encoder<'_, 'idt>,
+    hir: H,
+) -> Result<(StackFrame, HashMap<DefId, Reg>), Error> {
+    encoder.new_block();
     encode_iter(&mut encoder, hir)?;
 
-    StackFrame::new(
+    let frame = StackFrame::new(
         encoder.blocks,
         FrameMeta {
             max_reg_count: encoder.max_reg_count,
         },
     )
+    .map_err(out_of_bounds)?;
+
+    Ok((frame, encoder.bindings))
 }
 
 fn encode_iter<
@@ -71,49 +168,55 @@ fn encode_iter<
     'hir,
     H: IntoIterator<Item = Node<Hir<'str, 'idt, 'hir>>>,
 >(
-    encoder: &mut Encoder<'idt>,
+    encoder: &mut Encoder<'_, 'idt>,
     hir: H,
-) -> Option<()> {
+) -> Result<(), Error> {
     hir.into_iter().try_for_each(move |hir| encoder.encode(hir))
 }
 
+/// a block refers to a block that was never created, which is a bug in the encoder
+fn out_of_bounds(error: core_mir::OutOfBounds) -> Error {
+    Error {
+        message: format!("the encoded MIR is invalid, {}", error),
+        span: None,
+    }
+}
+
 trait Encode<T> {
     type Output;
 
-    #[must_use]
-    fn encode(&mut self, value: T) -> Option<Self::Output>;
+    fn encode(&mut self, value: T) -> Result<Self::Output, Error>;
 }
 
-impl<'tcx, 'idt, 'str, 'hir> Encoder<'idt> {
-    fn scopes(&self) -> impl Iterator<Item = &Scope<'idt>> {
-        std::iter::successors(Some(self.current_scope), move |&scope| {
-            if scope == 0 {
-                None
-            } else {
-                Some(self.scopes[scope].parent)
-            }
-        })
-        .map(move |scope| &self.scopes[scope])
-    }
-
-    fn get(&self, id: Ident<'idt>) -> Option<Reg> {
-        self.scopes()
-            .find_map(|scope| scope.locals.get(&id))
-            .copied()
+impl<'r, 'idt> Encoder<'r, 'idt> {
+    fn new(resolution: &'r Resolution<'idt>) -> Self {
+        Self {
+            resolution,
+            bindings: HashMap::new(),
+            blocks: Vec::new(),
+            loop_stack: Vec::new(),
+            max_reg_count: 0,
+            current_block: 0,
+        }
     }
 
-    fn get_or_insert(&mut self, id: Ident<'idt>) -> Reg {
-        if let Some(reg) = self.get(id) {
-            reg
-        } else {
-            self.insert(id)
-        }
+    /// the register of the binding used by the name at `span`, an error if the
+    /// name is unresolved, which the resolver reports first
+    fn get(&self, span: Span) -> Result<Reg, Error> {
+        self.resolution
+            .use_at_span(span)
+            .and_then(|def| self.bindings.get(&def).copied())
+            .ok_or_else(|| Error::new("this name isn't bound in this frame", span))
     }
 
-    fn insert(&mut self, id: Ident<'idt>) -> Reg {
-        let scope = &mut self.scopes[self.current_scope];
+    /// a new register for the binding whose name is at `span`
+    fn insert(&mut self, span: Span) -> Reg {
+        let def = self
+            .resolution
+            .def_at_span(span)
+            .expect("every binding is defined by the resolver");
         let reg = Reg(self.max_reg_count);
-        scope.locals.insert(id, reg);
+        self.bindings.insert(def, reg);
         self.max_reg_count += 1;
         reg
     }
@@ -124,50 +227,33 @@ impl<'tcx, 'idt, 'str, 'hir> Encoder<'idt> {
         reg
     }
 
+    /// a new block, which returns until it is given another terminator
     fn new_block(&mut self) -> usize {
         let target = self.blocks.len();
 
         self.blocks.push(Block {
             instructions: Vec::new(),
-            meta: BlockMeta {
-                parents: HashSet::new(),
-                children: HashSet::new(),
-            },
+            terminator: Terminator::Return,
+            meta: BlockMeta { span: None },
         });
 
         target
     }
 
-    fn open_scope(&mut self) {
-        let parent = self.current_scope;
-        self.current_scope = self.scopes.len();
-
-        self.scopes.push(Scope {
-            parent,
-            ..Scope::default()
-        })
-    }
-
-    fn close_scope(&mut self) {
-        self.current_scope = self.scopes[self.current_scope].parent;
-    }
-
     fn jump(&mut self, from: usize, to: usize) {
-        self.blocks[from].instructions.push(Mir::Jump(to));
-        self.blocks[to].meta.parents.insert(from);
-        self.blocks[from].meta.children.insert(to);
+        self.blocks[from].terminator = Terminator::Goto(to);
     }
 
-    fn branch(&mut self, cond: Reg, from: usize, to: usize) {
-        self.blocks[from]
-            .instructions
-            .push(Mir::BranchTrue { cond, target: to });
-        self.blocks[to].meta.parents.insert(from);
-        self.blocks[from].meta.children.insert(to);
+    fn branch(&mut self, cond: Reg, from: usize, then: usize, otherwise: usize) {
+        self.blocks[from].terminator = Terminator::Branch {
+            cond,
+            then,
+            otherwise,
+        };
     }
 }
 
-impl<'tcx, 'idt, 'str, 'hir, F> Encode<(Node<Expr<'str, 'idt, 'hir>>, F)> for Encoder<'idt>
+impl<'tcx, 'idt, 'str, 'hir, F> Encode<(Node<Expr<'str, 'idt, 'hir>>, F)> for Encoder<'_, 'idt>
 where
     F: FnOnce(&mut Self) -> Reg,
 {
@@ -176,12 +262,12 @@ where
     fn encode(
         &mut self,
         (mut value, to): (Node<Expr<'str, 'idt, 'hir>>, F),
-    ) -> Option<Self::Output> {
+    ) -> Result<Self::Output, Error> {
         self.encode((&mut value, to))
     }
 }
 
-impl<'tcx, 'idt, 'str, 'hir, F> Encode<(&mut Node<Expr<'str, 'idt, 'hir>>, F)> for Encoder<'idt>
+impl<'tcx, 'idt, 'str, 'hir, F> Encode<(&mut Node<Expr<'str, 'idt, 'hir>>, F)> for Encoder<'_, 'idt>
 where
     F: FnOnce(&mut Self) -> Reg,
 {
@@ -190,13 +276,14 @@ where
     fn encode(
         &mut self,
         (value, to): (&mut Node<Expr<'str, 'idt, 'hir>>, F),
-    ) -> Option<Self::Output> {
+    ) -> Result<Self::Output, Error> {
         let reg;
+        let span = value.span;
 
         match value.val {
-            Expr::PreOp(op, ref right) => todo!("preop"),
-            Expr::PostOp(op, ref left) => todo!("postop"),
-            Expr::Tuple(ref lit) => todo!("tuple"),
+            Expr::PreOp(..) => Err(Error::unsupported("prefix operators", span)),
+            Expr::PostOp(..) => Err(Error::unsupported("postfix operators", span)),
+            Expr::Tuple(_) => Err(Error::unsupported("tuples", span)),
             Expr::Simple(simple) => {
                 let to = to(self);
                 self.encode((simple, to))
@@ -204,7 +291,7 @@ where
             Expr::Scope(ref mut scope) => {
                 self.encode(std::mem::take(scope))?;
                 let reg = to(self); // TODO: initialize the return register
-                Some(reg)
+                Ok(reg)
             }
             Expr::FuncApp { ref mut name_args } => {
                 let (name, args) = name_args.split_at_mut(1);
@@ -214,40 +301,32 @@ where
                 let mut reg_args = Vec::with_capacity(args.len());
 
                 for arg in args {
-                    reg_args.push(Mir::PushArguement {
-                        arg: self.encode((arg, Self::temp))?,
-                    });
+                    reg_args.push(self.encode((arg, Self::temp))?);
                 }
 
                 let ret = to(self);
-                let block = &mut self.blocks[self.current_block];
+                let next = self.new_block();
 
-                block.instructions.reserve(reg_args.len() + 2);
-                block
-                    .instructions
-                    .push(Mir::LoadFunction { func: name, ret });
-                block.instructions.extend(reg_args);
-                block.instructions.push(Mir::CallFunction);
+                self.blocks[self.current_block].terminator = Terminator::Call {
+                    func: name,
+                    args: reg_args,
+                    ret,
+                    next,
+                };
+
+                self.current_block = next;
 
-                Some(ret)
+                Ok(ret)
             }
             Expr::Func {
                 ref parameter_list,
                 ref mut body,
             } => {
-                let mut encoder = Encoder::default();
+                let mut encoder = Encoder::new(self.resolution);
 
-                encoder.blocks.push(Block {
-                    instructions: Vec::new(),
-                    meta: BlockMeta {
-                        parents: HashSet::new(),
-                        children: HashSet::new(),
-                    },
-                });
-
-                encoder.scopes.push(Scope::default());
+                encoder.new_block();
                 for param in parameter_list {
-                    let arg = encoder.insert(param.name);
+                    let arg = encoder.insert(param.span);
                     encoder.blocks[encoder.current_block]
                         .instructions
                         .push(Mir::PopArgument { arg })
@@ -260,7 +339,8 @@ where
                     FrameMeta {
                         max_reg_count: encoder.max_reg_count,
                     },
-                )?;
+                )
+                .map_err(out_of_bounds)?;
 
                 let binding = to(self);
 
@@ -272,7 +352,44 @@ where
                         ret,
                     });
 
-                Some(binding)
+                Ok(binding)
+            }
+            Expr::Cast {
+                ref mut value,
+                ref ty,
+            } => {
+                use core_mir::CastKind;
+                use core_types::Primitive;
+
+                let ty = match ty.val {
+                    Expr::Simple(Node {
+                        val: SimpleExpr::Ident(ident),
+                        ..
+                    }) => Primitive::from_name(ident.as_str()).ok_or_else(|| {
+                        Error::new(
+                            format!(
+                                "casts can only target primitive types, `{}` isn't one",
+                                ident
+                            ),
+                            ty.span,
+                        )
+                    })?,
+                    _ => return Err(Error::new("casts can only target primitive types", ty.span)),
+                };
+
+                let from = self.encode((value as &mut _, Self::temp))?;
+
+                reg = to(self);
+
+                self.blocks[self.current_block]
+                    .instructions
+                    .push(Mir::Cast {
+                        to: reg,
+                        from,
+                        ty,
+                        kind: CastKind::Infer,
+                    });
+                Ok(reg)
             }
             Expr::BinOp(op, ref mut left, ref mut right) => {
                 use core_hir::Operator;
@@ -282,8 +399,10 @@ where
                 let left = self.encode((left as &mut _, Self::temp))?;
                 let right = self.encode((right as &mut _, Self::temp))?;
 
+                let unsupported = || Err(Error::unsupported(&format!("`{}`", op), span));
+
                 let op = match op {
-                    Operator::Keyword(_) => return None,
+                    Operator::Keyword(_) => return unsupported(),
                     Operator::Symbol(op) => match op {
                         sym!(+) => BinOpType::Add,
                         sym!(-) => BinOpType::Sub,
@@ -297,7 +416,7 @@ where
                         sym!(>) => BinOpType::GreaterThan,
                         sym!(<) => BinOpType::LessThan,
 
-                        _ => return None,
+                        _ => return unsupported(),
                     },
                 };
 
@@ -310,28 +429,29 @@ where
                         out: reg,
                         left,
                         right,
+                        span,
                     });
-                Some(reg)
+                Ok(reg)
             }
         }
     }
 }
 
-impl<'tcx, 'idt, 'str, 'hir> Encode<Vec<Node<Hir<'str, 'idt, 'hir>>>> for Encoder<'idt> {
+impl<'tcx, 'idt, 'str, 'hir> Encode<Vec<Node<Hir<'str, 'idt, 'hir>>>> for Encoder<'_, 'idt> {
     type Output = ();
 
-    fn encode(&mut self, scope: Vec<Node<Hir<'str, 'idt, 'hir>>>) -> Option<Self::Output> {
-        self.open_scope();
-        encode_iter(self, scope)?;
-        self.close_scope();
-        Some(())
+    fn encode(&mut self, scope: Vec<Node<Hir<'str, 'idt, 'hir>>>) -> Result<Self::Output, Error> {
+        encode_iter(self, scope)
     }
 }
 
-impl<'tcx, 'idt, 'str, 'hir> Encode<Node<Hir<'str, 'idt, 'hir>>> for Encoder<'idt> {
+impl<'tcx, 'idt, 'str, 'hir> Encode<Node<Hir<'str, 'idt, 'hir>>> for Encoder<'_, 'idt> {
     type Output = ();
 
-    fn encode(&mut self, value: Node<Hir<'str, 'idt, 'hir>>) -> Option<Self::Output> {
+    fn encode(&mut self, value: Node<Hir<'str, 'idt, 'hir>>) -> Result<Self::Output, Error> {
+        let meta = &mut self.blocks[self.current_block].meta;
+        meta.span = meta.span.or(Some(value.span));
+
         match value.val {
             Hir::Scope(inner) => self.encode(inner)?,
             Hir::Loop(inner) => {
@@ -350,61 +470,68 @@ impl<'tcx, 'idt, 'str, 'hir> Encode<Node<Hir<'str, 'idt, 'hir>>> for Encoder<'id
                 self.encode(inner)?;
                 self.jump(self.current_block, end);
                 self.loop_stack.pop();
+                self.current_block = exit;
             }
             Hir::ControlFlow {
                 ty: core_hir::ControlFlowType::Break,
                 label,
                 val,
             } => {
-                assert!(label.is_none());
-                assert!(val.is_none());
+                if label.is_some() {
+                    return Err(Error::unsupported("labeled breaks", value.span));
+                }
+
+                if val.is_some() {
+                    return Err(Error::unsupported("breaks with a value", value.span));
+                }
 
+                let span = value.span;
                 let &Loop { exit, .. } = self
                     .loop_stack
                     .last()
-                    .expect("Break can only be used inside a loop");
+                    .ok_or_else(|| Error::new("`break` can only be used inside a loop", span))?;
 
                 self.jump(self.current_block, exit);
+
+                // anything after a `break` is unreachable, but it still needs a block
+                self.current_block = self.new_block();
             }
             Hir::ControlFlow {
                 ty: core_hir::ControlFlowType::Continue,
-                label,
-                val,
-            } => todo!("continue"),
-            Hir::Print(id) => {
-                let print = self.get(id).map(Mir::Print)?;
+                ..
+            } => return Err(Error::unsupported("`continue`", value.span)),
+            Hir::Panic(message) => {
+                let message = message
+                    .as_ref()
+                    .map_or("explicit panic", |message| message.as_str());
+
+                // the panic unwinds through a cleanup landing pad, there is nothing
+                // to drop yet, so all the cleanup does is continue unwinding
+                let cleanup = self.new_block();
+                self.blocks[cleanup].terminator = Terminator::Resume;
+
+                let instructions = &mut self.blocks[self.current_block].instructions;
+                instructions.push(Mir::PushLandingPad { cleanup });
+                instructions.push(Mir::Panic {
+                    message: message.to_string(),
+                    span: value.span,
+                });
+
+                // a panic never finishes, so anything after it is unreachable
+                self.blocks[self.current_block].terminator = Terminator::Unreachable;
+                self.current_block = self.new_block();
+            }
+            Hir::Print(name) => {
+                let print = Mir::Print(self.get(name.span)?);
                 self.blocks[self.current_block].instructions.push(print);
             }
             Hir::Let { pat, value } => {
-                let to = |this: &mut Self| match pat.val {
-                    Pattern::Literal(_) => {
-                        unreachable!(r#"invalid "let" pattern, cannot bind to literals"#)
-                    }
-                    Pattern::Ident(_, BindingMode::Reference) => unreachable!(
-                        r#"invalid "let" pattern, cannot bind to variables by reference"#
-                    ),
-                    Pattern::Tuple(_) => {
-                        unimplemented!(r#"invalid "let" pattern, tuples are not implemented"#)
-                    }
-                    Pattern::Ident(ident, BindingMode::Value) => this.insert(ident),
-                };
-
-                self.encode((value, to))?;
+                check_binding(&pat)?;
+                self.encode((value, |this: &mut Self| this.insert(pat.span)))?;
             }
             Hir::Mut { pat, value } => {
-                let to = match pat.val {
-                    Pattern::Literal(_) => {
-                        unreachable!(r#"invalid "let" pattern, cannot bind to literals"#)
-                    }
-                    Pattern::Ident(_, BindingMode::Reference) => unreachable!(
-                        r#"invalid "let" pattern, cannot bind to variables by reference"#
-                    ),
-                    Pattern::Tuple(_) => {
-                        unimplemented!(r#"invalid "let" pattern, tuples are not implemented"#)
-                    }
-                    Pattern::Ident(ident, BindingMode::Value) => self.get(ident)?,
-                };
-
+                check_binding(&pat)?;
+                let to = self.get(pat.span)?;
                 self.encode((value, |_this: &mut Self| to))?;
             }
             Hir::If {
@@ -412,40 +539,25 @@ impl<'tcx, 'idt, 'str, 'hir> Encode<Node<Hir<'str, 'idt, 'hir>>> for Encoder<'id
                 else_if_branches,
                 else_branch,
             } => {
-                let bb_start = self.new_block();
                 let trailing_block = self.new_block();
 
-                self.jump(self.current_block, bb_start);
-                self.current_block = bb_start;
+                // each condition is tested only if the conditions before it were false
+                for core_hir::If { cond, branch } in
+                    std::iter::once(if_branch).chain(else_if_branches)
+                {
+                    let cond = self.encode((cond, |this: &mut Self| this.temp()))?;
+                    let bb_if_branch = self.new_block();
+                    let bb_next_branch = self.new_block();
 
-                let init_block = bb_start;
+                    self.branch(cond, self.current_block, bb_if_branch, bb_next_branch);
 
-                let next_branch = std::iter::once(if_branch)
-                    .chain(else_if_branches)
-                    .try_fold(
-                        self.new_block(),
-                        |bb_if_branch, core_hir::If { cond, branch }| {
-                            let bb_next_branch = self.new_block();
-
-                            let current_block = self.current_block;
-
-                            self.current_block = init_block;
-                            let cond = self.encode((cond, |this: &mut Self| this.temp()))?;
-                            self.branch(cond, self.current_block, bb_if_branch);
-
-                            self.current_block = current_block;
-
-                            self.current_block = bb_if_branch;
-                            self.encode(branch.val)?;
-                            self.jump(self.current_block, trailing_block);
-
-                            Some(bb_next_branch)
-                        },
-                    )?;
+                    self.current_block = bb_if_branch;
+                    self.encode(branch.val)?;
+                    self.jump(self.current_block, trailing_block);
 
-                self.jump(init_block, next_branch);
+                    self.current_block = bb_next_branch;
+                }
 
-                self.current_block = next_branch;
                 if let Some(branch) = else_branch {
                     self.encode(branch.val)?;
                 }
@@ -455,65 +567,78 @@ impl<'tcx, 'idt, 'str, 'hir> Encode<Node<Hir<'str, 'idt, 'hir>>> for Encoder<'id
             }
         }
 
-        Some(())
+        Ok(())
     }
 }
 
-impl<'idt, 'str> Encode<Node<SimpleExpr<'str, 'idt>>> for Encoder<'idt> {
+/// an error unless `pat` binds a single name by value, the only pattern
+/// that `let` and assignments can encode
+fn check_binding(pat: &Node<Pattern<'_, '_>>) -> Result<(), Error> {
+    match pat.val {
+        Pattern::Ident(_, BindingMode::Value) => Ok(()),
+        Pattern::Ident(_, BindingMode::Reference) => Err(Error::new(
+            "cannot bind to variables by reference",
+            pat.span,
+        )),
+        Pattern::Literal(_) => Err(Error::new("cannot bind to literals", pat.span)),
+        Pattern::Tuple(_) => Err(Error::unsupported("tuple patterns", pat.span)),
+    }
+}
+
+impl<'idt, 'str> Encode<Node<SimpleExpr<'str, 'idt>>> for Encoder<'_, 'idt> {
     type Output = Reg;
 
-    fn encode(&mut self, value: Node<SimpleExpr<'str, 'idt>>) -> Option<Self::Output> {
+    fn encode(&mut self, value: Node<SimpleExpr<'str, 'idt>>) -> Result<Self::Output, Error> {
         match value.val {
             SimpleExpr::Literal(lit) => {
                 let to = self.temp();
-                self.encode((lit, to))
+                self.encode((
+                    Node {
+                        val: lit,
+                        span: value.span,
+                    },
+                    to,
+                ))
             }
-            SimpleExpr::Ident(ident) => match self.get(ident) {
-                Some(from) => Some(from),
-                None => {
-                    eprintln!(
-                        "ERROR: detected an uninitialized variable: {:?} at {:?}",
-                        ident, value.span
-                    );
-                    None
-                }
-            },
+            SimpleExpr::Ident(_) => self.get(value.span),
         }
     }
 }
 
-impl<'idt, 'str> Encode<(Node<SimpleExpr<'str, 'idt>>, Reg)> for Encoder<'idt> {
+impl<'idt, 'str> Encode<(Node<SimpleExpr<'str, 'idt>>, Reg)> for Encoder<'_, 'idt> {
     type Output = Reg;
 
-    fn encode(&mut self, (value, to): (Node<SimpleExpr<'str, 'idt>>, Reg)) -> Option<Self::Output> {
+    fn encode(
+        &mut self,
+        (value, to): (Node<SimpleExpr<'str, 'idt>>, Reg),
+    ) -> Result<Self::Output, Error> {
         match value.val {
-            SimpleExpr::Literal(lit) => self.encode((lit, to)),
-            SimpleExpr::Ident(ident) => match self.get(ident) {
-                Some(from) => {
-                    self.blocks[self.current_block]
-                        .instructions
-                        .push(Mir::LoadReg { to, from });
-                    Some(to)
-                }
-                None => {
-                    eprintln!(
-                        "ERROR: detected an uninitialized variable: {:?} at {:?}",
-                        ident, value.span
-                    );
-                    None
-                }
-            },
+            SimpleExpr::Literal(lit) => self.encode((
+                Node {
+                    val: lit,
+                    span: value.span,
+                },
+                to,
+            )),
+            SimpleExpr::Ident(_) => {
+                let from = self.get(value.span)?;
+
+                self.blocks[self.current_block]
+                    .instructions
+                    .push(Mir::LoadReg { to, from });
+                Ok(to)
+            }
         }
     }
 }
 
-impl<'idt, 'str> Encode<(Literal<'str>, Reg)> for Encoder<'idt> {
+impl<'idt, 'str> Encode<(Node<Literal<'str>>, Reg)> for Encoder<'_, 'idt> {
     type Output = Reg;
 
-    fn encode(&mut self, (value, to): (Literal<'str>, Reg)) -> Option<Self::Output> {
-        let from = match value {
-            Literal::Str(s) => todo!("str"),
-            Literal::Float(x) => todo!("float"),
+    fn encode(&mut self, (value, to): (Node<Literal<'str>>, Reg)) -> Result<Self::Output, Error> {
+        let from = match value.val {
+            Literal::Str(_) => return Err(Error::unsupported("string literals", value.span)),
+            Literal::Float(_) => return Err(Error::unsupported("float literals", value.span)),
             Literal::Bool(x) => Load::Bool(x),
             Literal::Int(x) => {
                 if x < (1 << 8) {
@@ -534,6 +659,6 @@ impl<'idt, 'str> Encode<(Literal<'str>, Reg)> for Encoder<'idt> {
             .instructions
             .push(Mir::Load { to, from });
 
-        Some(to)
+        Ok(to)
     }
 }
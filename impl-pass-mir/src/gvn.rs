@@ -0,0 +1,268 @@
+//! Copy propagation and global value numbering over a `StackFrame` in SSA form
+//!
+//! Every register is replaced with its leader, the first register known to hold the same
+//! value. A copy's leader is the leader of its source, and a phi whose sources all have
+//! the same leader is just a copy of it. Any other instruction that computes the same
+//! expression over the same leaders as an instruction in a block that dominates it
+//! has that instruction's result as its leader, so it doesn't have to be computed again.
+//!
+//! Instructions whose result has another leader are removed, and everything that read
+//! their result reads the leader instead. This is the dominator based value numbering
+//! from "Value Numbering" by Briggs, Cooper and Simpson, repeated until nothing changes,
+//! so the sources of phis that come from back edges are numbered too.
+//!
+//! An instruction that may panic is only removed if an equal instruction that dominates
+//! it already ran, so this never hides a panic.
+
+use core_mir::{BinOpType, CastKind, Load, PreOpType, Reg};
+use core_types::Primitive;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::AddAssign;
+
+use super::cfg::{self, Cfg, Dominators};
+use super::*;
+
+/// how many copies and computations were removed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// copies, and phis which only copy a single value
+    pub copies: usize,
+    /// instructions that computed a value which was already computed
+    pub redundant: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.copies += other.copies;
+        self.redundant += other.redundant;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "propagated {} copies and removed {} redundant computations",
+            self.copies, self.redundant
+        )
+    }
+}
+
+/// the value an instruction computes, in terms of the leaders of its operands
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expr {
+    Load(Load),
+    BinOp(BinOpType, Reg, Reg),
+    PreOp(PreOpType, Reg),
+    Cast(Reg, Primitive, CastKind),
+    /// phis in different blocks pick their source from different edges, so they are
+    /// only equal if they are in the same block
+    Phi(usize, Vec<(usize, Reg)>),
+}
+
+impl Expr {
+    fn new(mir: &Mir, block: usize) -> Option<Self> {
+        match *mir {
+            Mir::Load { from, .. } => Some(Self::Load(from)),
+            Mir::BinOp {
+                op, left, right, ..
+            } => {
+                // the order of the operands doesn't matter for these
+                let commutes = match op {
+                    BinOpType::Add | BinOpType::Mul | BinOpType::Equal | BinOpType::NotEqual => {
+                        true
+                    }
+                    _ => false,
+                };
+
+                if commutes && right.0 < left.0 {
+                    Some(Self::BinOp(op, right, left))
+                } else {
+                    Some(Self::BinOp(op, left, right))
+                }
+            }
+            Mir::PreOp { op, arg, .. } => Some(Self::PreOp(op, arg)),
+            Mir::Cast { from, ty, kind, .. } => Some(Self::Cast(from, ty, kind)),
+            Mir::Phi { ref from, .. } => {
+                let mut from = from.clone();
+                from.sort_unstable_by_key(|&(block, reg)| (block, reg.0));
+                Some(Self::Phi(block, from))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// replace the copies and redundant computations of `frame` and of all of its functions
+/// with the registers that already hold their value
+///
+/// the registers are renumbered if anything was removed, so the types of the frame
+/// must be inferred again, frames with cleanups aren't in SSA form, so they are left
+/// as they are
+pub fn number(frame: &mut StackFrame) -> Stats {
+    number_frame(frame, &mut None)
+}
+
+fn number_frame(frame: &mut StackFrame, ret: &mut Option<Reg>) -> Stats {
+    let mut stats = Stats::default();
+
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ret: ref mut func_ret,
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                let mut ret = Some(*func_ret);
+                stats += number_frame(stack_frame, &mut ret);
+                *func_ret = ret.unwrap();
+            }
+        }
+    }
+
+    if cfg::has_cleanups(frame) || frame.blocks().is_empty() {
+        return stats;
+    }
+
+    let mut local = Stats::default();
+
+    loop {
+        let cfg = Cfg::new(frame);
+        let doms = Dominators::new(&cfg);
+
+        let mut numbering = Numbering {
+            leaders: (0..frame.meta.max_reg_count).map(Reg).collect(),
+            table: HashMap::new(),
+            stats: Stats::default(),
+            doms: &doms,
+        };
+
+        numbering.number(frame.blocks_mut(), 0);
+
+        let Numbering {
+            leaders,
+            stats: found,
+            ..
+        } = numbering;
+
+        if found == Stats::default() {
+            break;
+        }
+
+        local += found;
+
+        // the sources of phis from back edges, and the return register, are only read
+        // after the whole frame was numbered
+        let leader = |reg: &mut Reg| *reg = find(&leaders, *reg);
+
+        for block in frame.blocks_mut() {
+            for mir in block.instructions.iter_mut() {
+                mir.uses_mut().into_iter().for_each(&leader);
+            }
+
+            block.terminator.uses_mut().into_iter().for_each(&leader);
+        }
+
+        if let Some(ret) = ret {
+            leader(ret);
+        }
+    }
+
+    if local != Stats::default() {
+        let max_reg_count = frame.meta.max_reg_count;
+        ssa::compact(frame, max_reg_count, ret);
+    }
+
+    stats += local;
+    stats
+}
+
+/// the leader of `reg`, a leader is its own leader
+fn find(leaders: &[Reg], mut reg: Reg) -> Reg {
+    while leaders[reg.0] != reg {
+        reg = leaders[reg.0];
+    }
+
+    reg
+}
+
+struct Numbering<'a> {
+    leaders: Vec<Reg>,
+    /// the expressions computed in the blocks that dominate the current block
+    table: HashMap<Expr, Reg>,
+    stats: Stats,
+    doms: &'a Dominators,
+}
+
+impl Numbering<'_> {
+    /// number the instructions of `b` and of the blocks it dominates
+    fn number(&mut self, blocks: &mut [Block], b: usize) {
+        let mut added = Vec::new();
+        let instructions = std::mem::take(&mut blocks[b].instructions);
+
+        for mut mir in instructions {
+            for reg in mir.uses_mut() {
+                *reg = find(&self.leaders, *reg);
+            }
+
+            let def = match mir.def() {
+                Some(def) => def,
+                None => {
+                    blocks[b].instructions.push(mir);
+                    continue;
+                }
+            };
+
+            let leader = match mir {
+                Mir::LoadReg { from, .. } => Some(from),
+                Mir::Phi { ref from, .. } => {
+                    let mut sources = from.iter().map(|&(_, reg)| reg).filter(|&reg| reg != def);
+
+                    match sources.next() {
+                        Some(first) if sources.all(|reg| reg == first) => Some(first),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some(leader) = leader {
+                self.leaders[def.0] = leader;
+                self.stats.copies += 1;
+                continue;
+            }
+
+            match Expr::new(&mir, b) {
+                Some(expr) => match self.table.get(&expr) {
+                    Some(&leader) => {
+                        self.leaders[def.0] = leader;
+                        self.stats.redundant += 1;
+                    }
+                    None => {
+                        self.table.insert(expr.clone(), def);
+                        added.push(expr);
+                        blocks[b].instructions.push(mir);
+                    }
+                },
+                None => blocks[b].instructions.push(mir),
+            }
+        }
+
+        for reg in blocks[b].terminator.uses_mut() {
+            *reg = find(&self.leaders, *reg);
+        }
+
+        let doms = self.doms;
+
+        for &child in doms.children(b) {
+            self.number(blocks, child);
+        }
+
+        for expr in added {
+            self.table.remove(&expr);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Inlines calls to small functions
+//!
+//! A call can be inlined if the function it calls is created by a `CreateFunc` in the same
+//! frame, either directly or through copies. The blocks of the function are appended to
+//! the caller with their registers and block indices moved past the caller's own, each
+//! `PopArgument` becomes a copy of the argument that was passed, and the block that
+//! returns copies the return value into the register the call wrote, and jumps to the
+//! block the call continued at.
+//!
+//! Only functions with at most `THRESHOLD` instructions are inlined, which keeps the
+//! caller from growing much, and the calls of the inlined function are inlined in turn.
+//!
+//! The function must return from a single block, which is the case once it is in SSA form.
+//! Frames with cleanups aren't in SSA form, so nothing is inlined into them, and functions
+//! with landing pads are never inlined, since their cleanups would have to be moved too.
+
+use core_mir::{Reg, Terminator};
+
+use std::fmt;
+use std::ops::AddAssign;
+
+use super::*;
+
+/// the most instructions and terminators a function may have to be inlined,
+/// including the instructions of the functions it creates
+pub const THRESHOLD: usize = 24;
+
+/// how many calls were inlined
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub calls: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.calls += other.calls;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "inlined {} calls", self.calls)
+    }
+}
+
+/// inline the calls to small functions in `frame` and in all of its functions
+///
+/// new registers are added for the inlined functions, so the types of the frame
+/// must be inferred again
+pub fn inline(frame: &mut StackFrame) -> Stats {
+    let mut stats = Stats::default();
+
+    // functions are inlined into first, so they are as small as they will get
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                stats += inline(stack_frame);
+            }
+        }
+    }
+
+    if cfg::has_cleanups(frame) {
+        return stats;
+    }
+
+    // the inlined blocks are appended, so their calls are visited too
+    let mut b = 0;
+
+    while b < frame.blocks().len() {
+        if let Some((callee, callee_ret)) = callee(frame, b) {
+            splice(frame, b, &callee, callee_ret);
+            stats.calls += 1;
+        }
+
+        b += 1;
+    }
+
+    stats
+}
+
+fn has_landing_pads(frame: &StackFrame) -> bool {
+    frame.blocks().iter().any(|block| {
+        block.instructions.iter().any(|mir| match *mir {
+            Mir::PushLandingPad { .. } => true,
+            _ => false,
+        })
+    })
+}
+
+/// how many instructions and terminators `frame` and its functions have
+fn size(frame: &StackFrame) -> usize {
+    frame
+        .blocks()
+        .iter()
+        .map(|block| {
+            let nested = block
+                .instructions
+                .iter()
+                .map(|mir| match *mir {
+                    Mir::CreateFunc {
+                        ref stack_frame, ..
+                    } => size(stack_frame),
+                    _ => 0,
+                })
+                .sum::<usize>();
+
+            block.instructions.len() + 1 + nested
+        })
+        .sum()
+}
+
+/// the instruction that writes `reg`, if it is written exactly once in `frame`
+fn definition(frame: &StackFrame, reg: Reg) -> Option<&Mir> {
+    let mut defs = frame
+        .blocks()
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter(|mir| mir.def() == Some(reg));
+
+    let def = defs.next()?;
+    let written_by_call = frame
+        .blocks()
+        .iter()
+        .any(|block| block.terminator.def() == Some(reg));
+
+    if defs.next().is_none() && !written_by_call {
+        Some(def)
+    } else {
+        None
+    }
+}
+
+/// the function called at the end of block `b` and its return register, if the call
+/// can be inlined
+fn callee(frame: &StackFrame, b: usize) -> Option<(StackFrame, Reg)> {
+    let (mut func, arg_count) = match frame.blocks()[b].terminator {
+        Terminator::Call { func, ref args, .. } => (func, args.len()),
+        _ => return None,
+    };
+
+    // copies of copies form a chain, which can't be longer than the frame
+    for _ in 0..=frame.meta.max_reg_count {
+        match *definition(frame, func)? {
+            Mir::LoadReg { from, .. } => func = from,
+            Mir::CreateFunc {
+                ref stack_frame,
+                ret,
+                ..
+            } => {
+                return if can_inline(stack_frame, arg_count) {
+                    Some((stack_frame.clone(), ret))
+                } else {
+                    None
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+/// whether `callee` is small enough to be inlined, and can be inlined into a call
+/// with `arg_count` arguments
+fn can_inline(callee: &StackFrame, arg_count: usize) -> bool {
+    let blocks = callee.blocks();
+
+    if blocks.is_empty() || size(callee) > THRESHOLD || has_landing_pads(callee) {
+        return false;
+    }
+
+    let returns = blocks
+        .iter()
+        .filter(|block| block.terminator == Terminator::Return)
+        .count();
+
+    // the entry of the function becomes the target of a jump, which would need new
+    // sources for any phis it has
+    let entry_has_predecessors = !callee.predecessors()[0].is_empty();
+
+    // the arguments are popped in the order they are written in the entry
+    let pops = |block: &Block| {
+        block
+            .instructions
+            .iter()
+            .filter(|mir| match **mir {
+                Mir::PopArgument { .. } => true,
+                _ => false,
+            })
+            .count()
+    };
+
+    returns == 1
+        && !entry_has_predecessors
+        && pops(&blocks[0]) == arg_count
+        && blocks.iter().skip(1).all(|block| pops(block) == 0)
+}
+
+/// replace the call at the end of block `b` with the blocks of `callee`
+fn splice(frame: &mut StackFrame, b: usize, callee: &StackFrame, callee_ret: Reg) {
+    let (args, ret, next) = match frame.blocks()[b].terminator {
+        Terminator::Call {
+            ref args,
+            ret,
+            next,
+            ..
+        } => (args.clone(), ret, next),
+        _ => unreachable!("only calls are inlined"),
+    };
+
+    let reg_offset = frame.meta.max_reg_count;
+    let block_offset = frame.blocks().len();
+    let mut returned_from = block_offset;
+
+    frame.meta.max_reg_count += callee.meta.max_reg_count;
+    frame.blocks_mut()[b].terminator = Terminator::Goto(block_offset);
+
+    let move_reg = |reg: &mut Reg| reg.0 += reg_offset;
+    let mut args = args.into_iter();
+
+    for (i, block) in callee.blocks().iter().enumerate() {
+        let mut block = block.clone();
+
+        for mir in block.instructions.iter_mut() {
+            if let Mir::PopArgument { arg } = *mir {
+                *mir = Mir::LoadReg {
+                    to: Reg(arg.0 + reg_offset),
+                    from: args.next().unwrap(),
+                };
+
+                continue;
+            }
+
+            if let Some(reg) = mir.def_mut() {
+                move_reg(reg);
+            }
+
+            mir.uses_mut().into_iter().for_each(&move_reg);
+
+            if let Mir::Phi { ref mut from, .. } = *mir {
+                for (source, _) in from.iter_mut() {
+                    *source += block_offset;
+                }
+            }
+        }
+
+        let terminator = &mut block.terminator;
+
+        if let Some(reg) = terminator.def_mut() {
+            move_reg(reg);
+        }
+
+        terminator.uses_mut().into_iter().for_each(&move_reg);
+
+        for target in terminator.successors_mut() {
+            *target += block_offset;
+        }
+
+        if block.terminator == Terminator::Return {
+            block.instructions.push(Mir::LoadReg {
+                to: ret,
+                from: Reg(callee_ret.0 + reg_offset),
+            });
+            block.terminator = Terminator::Goto(next);
+            returned_from = block_offset + i;
+        }
+
+        frame.push(block);
+    }
+
+    // control now reaches `next` from the block the function returned from
+    for mir in frame.blocks_mut()[next].instructions.iter_mut() {
+        if let Mir::Phi { ref mut from, .. } = *mir {
+            for (source, _) in from.iter_mut().filter(|(source, _)| *source == b) {
+                *source = returned_from;
+            }
+        }
+    }
+}
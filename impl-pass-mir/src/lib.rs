@@ -1,19 +1,41 @@
+pub mod cfg;
+pub mod dce;
 pub mod encode;
+pub mod gvn;
+pub mod inline;
+pub mod licm;
+pub mod liveness;
+pub mod parse;
+pub mod sccp;
+pub mod simplify;
+pub mod ssa;
 pub mod type_check;
+pub mod verify;
 
-use std::collections::HashSet;
+use core_tokens::Span;
+
+use std::fmt;
 
 pub type Mir = core_mir::Mir<BlockMeta, FrameMeta>;
 pub type Block = core_mir::Block<BlockMeta, FrameMeta>;
 pub type StackFrame = core_mir::StackFrame<BlockMeta, FrameMeta>;
 
+/// the edges between blocks are derived from their terminators, see `cfg` for the
+/// analyses built on them
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlockMeta {
-    pub parents: HashSet<usize>,
-    pub children: HashSet<usize>,
+    /// the span of the first statement encoded into the block, `None` for blocks
+    /// that don't start with a statement, or that weren't encoded from source
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FrameMeta {
     pub max_reg_count: usize,
 }
+
+impl fmt::Display for FrameMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "regs({})", self.max_reg_count)
+    }
+}
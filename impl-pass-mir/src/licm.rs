@@ -0,0 +1,488 @@
+//! Loop-invariant code motion and strength reduction over a `StackFrame` in SSA form
+//!
+//! Every loop gets a preheader, a block that is the only way into its header from outside
+//! the loop. An instruction in the loop whose operands are all computed outside of it
+//! computes the same value on every iteration, so it is moved into the preheader and only
+//! computed once. Loops are visited innermost first, so an instruction can be moved out of
+//! several loops, one preheader at a time.
+//!
+//! Moving an instruction that may panic would run it even if the loop never reached it, so
+//! arithmetic is only moved out of the header, and only if nothing before it in the header
+//! may panic or has an effect. Anything else that is moved never panics, so it doesn't
+//! matter whether the loop would have run it.
+//!
+//! An induction variable is a phi in the header that starts at some value and has an
+//! invariant step added to it on each iteration. A multiplication of an induction variable
+//! by an invariant factor is replaced with a new induction variable, which starts at the
+//! product and has the step times the factor added to it instead. Additions overflow at
+//! different points than the multiplication, so this is only done for integers, and only
+//! if overflow wraps around.
+
+use core_mir::{BinOpType, Reg, Terminator};
+use core_types::{Ty, Variant};
+
+use std::fmt;
+use std::ops::AddAssign;
+
+use super::cfg::{self, Cfg, Dominators, Loop, Loops};
+use super::*;
+
+/// how much the loops were optimised
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// preheaders that were added to loops
+    pub preheaders: usize,
+    /// instructions moved out of a loop, once for each loop they were moved out of
+    pub hoisted: usize,
+    /// multiplications replaced with a new induction variable
+    pub reduced: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.preheaders += other.preheaders;
+        self.hoisted += other.hoisted;
+        self.reduced += other.reduced;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "added {} preheaders, hoisted {} instructions and strength reduced {} multiplications",
+            self.preheaders, self.hoisted, self.reduced
+        )
+    }
+}
+
+/// move the invariant instructions of the loops in `frame` and in all of its functions into
+/// their preheaders, and strength reduce the multiplications by induction variables of
+/// `frame`, which must be in SSA form with `types` inferred for it
+///
+/// multiplications are only strength reduced if `wrapping` overflow is used, and not in
+/// functions, whose types depend on their arguments, frames with cleanups aren't in SSA
+/// form, so they are left as they are
+///
+/// new registers are added for the new induction variables, so the types of the frame must
+/// be inferred again
+pub fn optimise(frame: &mut StackFrame, types: &[Ty<'_, '_>], wrapping: bool) -> Stats {
+    let mut stats = Stats::default();
+
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                stats += optimise(stack_frame, &[], false);
+            }
+        }
+    }
+
+    if cfg::has_cleanups(frame) || frame.blocks().is_empty() {
+        return stats;
+    }
+
+    // the new induction variables are typed by the phi they are derived from, so this is
+    // taken before any registers are added
+    let integers = types
+        .iter()
+        .map(|ty| match ty.ty {
+            Variant::Primitive(prim) => prim.is_integer(),
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+
+    stats.preheaders += insert_preheaders(frame);
+
+    // moving instructions doesn't change the control flow graph, so the loops found here
+    // stay the same
+    let cfg = Cfg::new(frame);
+    let doms = Dominators::new(&cfg);
+    let loops = Loops::new(&cfg, &doms);
+    let mut def_blocks = def_blocks(frame);
+
+    for l in loops.loops().iter().rev() {
+        let preheader = match preheader(frame, &cfg, l) {
+            Some(preheader) => preheader,
+            None => continue,
+        };
+
+        let order = cfg
+            .reverse_postorder()
+            .iter()
+            .copied()
+            .filter(|&b| l.contains(b))
+            .collect::<Vec<_>>();
+
+        stats.hoisted += hoist(frame, l, &order, preheader, &mut def_blocks);
+
+        if wrapping {
+            stats.reduced += reduce(frame, l, preheader, &mut def_blocks, &integers);
+        }
+    }
+
+    stats
+}
+
+/// give every loop whose header isn't the entry a preheader, returns how many were added
+fn insert_preheaders(frame: &mut StackFrame) -> usize {
+    let cfg = Cfg::new(frame);
+    let doms = Dominators::new(&cfg);
+    let loops = Loops::new(&cfg, &doms);
+    let mut added = 0;
+
+    for l in loops.loops() {
+        let header = l.header;
+
+        let outside = cfg
+            .predecessors(header)
+            .iter()
+            .copied()
+            .filter(|&pred| !l.contains(pred))
+            .collect::<Vec<_>>();
+
+        if outside.is_empty() || preheader(frame, &cfg, l).is_some() {
+            continue;
+        }
+
+        let pre = frame.push(Block {
+            instructions: Vec::new(),
+            terminator: Terminator::Goto(header),
+            meta: BlockMeta { span: None },
+        });
+
+        for &pred in outside.iter() {
+            for target in frame.blocks_mut()[pred].terminator.successors_mut() {
+                if *target == header {
+                    *target = pre;
+                }
+            }
+        }
+
+        // the sources of the header's phis from outside the loop now come through the
+        // preheader, which picks between them if there is more than one
+        let mut phis = Vec::new();
+        let mut next_reg = frame.meta.max_reg_count;
+
+        for mir in frame.blocks_mut()[header].instructions.iter_mut() {
+            if let Mir::Phi { ref mut from, .. } = *mir {
+                let (sources, mut inside): (Vec<_>, Vec<_>) = from
+                    .drain(..)
+                    .partition(|&(source, _)| outside.contains(&source));
+
+                let reg = match *sources {
+                    [(_, reg)] => reg,
+                    _ => {
+                        let to = Reg(next_reg);
+                        next_reg += 1;
+                        phis.push(Mir::Phi { to, from: sources });
+                        to
+                    }
+                };
+
+                inside.push((pre, reg));
+                *from = inside;
+            }
+        }
+
+        frame.meta.max_reg_count = next_reg;
+        frame.blocks_mut()[pre].instructions = phis;
+        added += 1;
+    }
+
+    added
+}
+
+/// the block that only jumps to the header of `l`, if it is the only way into the loop
+fn preheader(frame: &StackFrame, cfg: &Cfg, l: &Loop) -> Option<usize> {
+    let mut outside = cfg
+        .predecessors(l.header)
+        .iter()
+        .copied()
+        .filter(|&pred| !l.contains(pred));
+
+    match (outside.next(), outside.next()) {
+        (Some(pred), None) if frame.blocks()[pred].terminator == Terminator::Goto(l.header) => {
+            Some(pred)
+        }
+        _ => None,
+    }
+}
+
+/// the block each register is written in
+fn def_blocks(frame: &StackFrame) -> Vec<Option<usize>> {
+    let mut def_blocks = vec![None; frame.meta.max_reg_count];
+
+    for (b, block) in frame.blocks().iter().enumerate() {
+        let defs = block
+            .instructions
+            .iter()
+            .filter_map(Mir::def)
+            .chain(block.terminator.def());
+
+        for reg in defs {
+            def_blocks[reg.0] = Some(b);
+        }
+    }
+
+    def_blocks
+}
+
+/// whether `reg` holds the same value on every iteration of `l`
+fn is_invariant(l: &Loop, def_blocks: &[Option<usize>], reg: Reg) -> bool {
+    match def_blocks.get(reg.0) {
+        Some(&Some(b)) => !l.contains(b),
+        _ => true,
+    }
+}
+
+/// move the invariant instructions of `l` into its preheader, the blocks of the loop are
+/// visited in `order`, so an instruction is visited after the instructions it reads,
+/// returns how many were moved
+fn hoist(
+    frame: &mut StackFrame,
+    l: &Loop,
+    order: &[usize],
+    preheader: usize,
+    def_blocks: &mut [Option<usize>],
+) -> usize {
+    let mut hoisted = 0;
+
+    loop {
+        let mut changed = false;
+
+        for &b in order {
+            // whether everything before the current instruction in the header can run
+            // after it instead
+            let mut unobserved = b == l.header;
+            let instructions = std::mem::take(&mut frame.blocks_mut()[b].instructions);
+
+            for mir in instructions {
+                let movable = match mir {
+                    Mir::Load { .. }
+                    | Mir::LoadReg { .. }
+                    | Mir::BinOp { .. }
+                    | Mir::PreOp { .. }
+                    | Mir::Cast { .. } => true,
+                    _ => false,
+                };
+
+                let pure = dce::is_pure(&mir);
+
+                if movable
+                    && (pure || unobserved)
+                    && mir
+                        .uses()
+                        .into_iter()
+                        .all(|reg| is_invariant(l, def_blocks, reg))
+                {
+                    if let Some(def) = mir.def() {
+                        def_blocks[def.0] = Some(preheader);
+                    }
+
+                    frame.blocks_mut()[preheader].instructions.push(mir);
+                    hoisted += 1;
+                    changed = true;
+                } else {
+                    unobserved &= pure;
+                    frame.blocks_mut()[b].instructions.push(mir);
+                }
+            }
+        }
+
+        if !changed {
+            return hoisted;
+        }
+    }
+}
+
+/// an induction variable of a loop, `reg` is `init` on entry and `reg op step` on every
+/// other iteration
+struct Induction {
+    reg: Reg,
+    init: Reg,
+    op: BinOpType,
+    step: Reg,
+}
+
+/// the induction variables in the header of `l`, which has a single latch
+fn induction_variables(
+    frame: &StackFrame,
+    l: &Loop,
+    preheader: usize,
+    latch: usize,
+    def_blocks: &[Option<usize>],
+) -> Vec<Induction> {
+    let definition = |reg: Reg| {
+        let block = &frame.blocks()[(*def_blocks.get(reg.0)?)?];
+        block.instructions.iter().find(|mir| mir.def() == Some(reg))
+    };
+
+    let mut inductions = Vec::new();
+
+    for mir in frame.blocks()[l.header].instructions.iter() {
+        let (reg, from) = match *mir {
+            Mir::Phi { to, ref from } => (to, from),
+            _ => break,
+        };
+
+        let source = |block: usize| from.iter().find(|&&(source, _)| source == block);
+
+        let (init, next) = match (from.len(), source(preheader), source(latch)) {
+            (2, Some(&(_, init)), Some(&(_, next))) => (init, next),
+            _ => continue,
+        };
+
+        let (op, step) = match definition(next) {
+            Some(&Mir::BinOp {
+                op: BinOpType::Add,
+                left,
+                right,
+                ..
+            }) if left == reg => (BinOpType::Add, right),
+            Some(&Mir::BinOp {
+                op: BinOpType::Add,
+                left,
+                right,
+                ..
+            }) if right == reg => (BinOpType::Add, left),
+            Some(&Mir::BinOp {
+                op: BinOpType::Sub,
+                left,
+                right,
+                ..
+            }) if left == reg => (BinOpType::Sub, right),
+            _ => continue,
+        };
+
+        if is_invariant(l, def_blocks, step) {
+            inductions.push(Induction {
+                reg,
+                init,
+                op,
+                step,
+            });
+        }
+    }
+
+    inductions
+}
+
+/// replace the multiplications of integer induction variables of `l` by invariant factors
+/// with new induction variables, returns how many were replaced
+fn reduce(
+    frame: &mut StackFrame,
+    l: &Loop,
+    preheader: usize,
+    def_blocks: &mut Vec<Option<usize>>,
+    integers: &[bool],
+) -> usize {
+    // with more than one latch, the step could be added on more than one edge
+    let latch = match *l.latches {
+        [latch] => latch,
+        _ => return 0,
+    };
+
+    if frame.predecessors()[l.header].len() != 2 {
+        return 0;
+    }
+
+    let inductions = induction_variables(frame, l, preheader, latch, def_blocks)
+        .into_iter()
+        .filter(|induction| integers.get(induction.reg.0).copied().unwrap_or(false))
+        .collect::<Vec<_>>();
+
+    if inductions.is_empty() {
+        return 0;
+    }
+
+    let mut phis = Vec::new();
+    let mut reduced = 0;
+
+    for &b in l.blocks.iter() {
+        for i in 0..frame.blocks()[b].instructions.len() {
+            let (out, left, right, span) = match frame.blocks()[b].instructions[i] {
+                Mir::BinOp {
+                    op: BinOpType::Mul,
+                    out,
+                    left,
+                    right,
+                    span,
+                } => (out, left, right, span),
+                _ => continue,
+            };
+
+            let found = inductions.iter().find_map(|induction| {
+                if induction.reg == left && is_invariant(l, def_blocks, right) {
+                    Some((induction, right))
+                } else if induction.reg == right && is_invariant(l, def_blocks, left) {
+                    Some((induction, left))
+                } else {
+                    None
+                }
+            });
+
+            let (induction, factor) = match found {
+                Some(found) => found,
+                None => continue,
+            };
+
+            let first = frame.meta.max_reg_count;
+            let (reg, init, step, next) =
+                (Reg(first), Reg(first + 1), Reg(first + 2), Reg(first + 3));
+            frame.meta.max_reg_count += 4;
+
+            // the loops this one is nested in must see that the new registers change
+            def_blocks.resize(frame.meta.max_reg_count, None);
+            def_blocks[reg.0] = Some(l.header);
+            def_blocks[init.0] = Some(preheader);
+            def_blocks[step.0] = Some(preheader);
+            def_blocks[next.0] = Some(latch);
+
+            let blocks = frame.blocks_mut();
+
+            blocks[preheader].instructions.push(Mir::BinOp {
+                op: BinOpType::Mul,
+                out: init,
+                left: induction.init,
+                right: factor,
+                span,
+            });
+
+            blocks[preheader].instructions.push(Mir::BinOp {
+                op: BinOpType::Mul,
+                out: step,
+                left: induction.step,
+                right: factor,
+                span,
+            });
+
+            blocks[latch].instructions.push(Mir::BinOp {
+                op: induction.op,
+                out: next,
+                left: reg,
+                right: step,
+                span,
+            });
+
+            blocks[b].instructions[i] = Mir::LoadReg { to: out, from: reg };
+
+            phis.push(Mir::Phi {
+                to: reg,
+                from: vec![(preheader, init), (latch, next)],
+            });
+
+            reduced += 1;
+        }
+    }
+
+    // the new phis go in front of the header's instructions once nothing indexes them
+    let header = &mut frame.blocks_mut()[l.header].instructions;
+    phis.extend(header.drain(..));
+    *header = phis;
+
+    reduced
+}
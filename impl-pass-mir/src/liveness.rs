@@ -0,0 +1,253 @@
+//! Which registers are live, still holding a value that may be read later, at each point
+//! of a `StackFrame`, and which registers interfere because they are live at the same time
+//!
+//! Registers that don't interfere can share the same storage, which is how the backends
+//! keep the locals of a frame small, even though the encoder uses a new register for
+//! every temporary.
+//!
+//! The sources of a phi are read at the end of the block they come from, so they are
+//! live out of that block, but not live into the block of the phi.
+
+use core_mir::{Reg, Terminator};
+
+use std::collections::HashSet;
+
+use super::cfg::Cfg;
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness {
+    live_in: Vec<Vec<bool>>,
+    live_out: Vec<Vec<bool>>,
+}
+
+impl Liveness {
+    /// `ret` is the return register if `frame` is the frame of a function, it is read
+    /// whenever the function returns
+    pub fn new(frame: &StackFrame, cfg: &Cfg, ret: Option<Reg>) -> Self {
+        let blocks = frame.blocks();
+        let max_reg_count = frame.meta.max_reg_count;
+
+        // the registers each block reads before writing them, and the registers it writes
+        let mut gen = vec![vec![false; max_reg_count]; blocks.len()];
+        let mut kill = vec![vec![false; max_reg_count]; blocks.len()];
+
+        for (b, block) in blocks.iter().enumerate() {
+            let (gen, kill) = (&mut gen[b], &mut kill[b]);
+
+            let mut read = |reg: Reg, kill: &[bool]| {
+                if !kill[reg.0] {
+                    gen[reg.0] = true;
+                }
+            };
+
+            for mir in block.instructions.iter() {
+                if let Mir::Phi { .. } = *mir {
+                } else {
+                    for reg in mir.uses() {
+                        read(reg, kill);
+                    }
+                }
+
+                if let Some(reg) = mir.def() {
+                    kill[reg.0] = true;
+                }
+            }
+
+            for reg in block.terminator.uses() {
+                read(reg, kill);
+            }
+
+            if let (Some(ret), Terminator::Return) = (ret, &block.terminator) {
+                read(ret, kill);
+            }
+
+            if let Some(reg) = block.terminator.def() {
+                kill[reg.0] = true;
+            }
+        }
+
+        let mut live_in = vec![vec![false; max_reg_count]; blocks.len()];
+        let mut live_out = vec![vec![false; max_reg_count]; blocks.len()];
+
+        let mut changed = true;
+
+        // liveness flows backwards, so the blocks are visited in postorder
+        while changed {
+            changed = false;
+
+            for &b in cfg.reverse_postorder().iter().rev() {
+                let mut out = vec![false; max_reg_count];
+
+                for &succ in cfg.successors(b) {
+                    for (out, &live) in out.iter_mut().zip(live_in[succ].iter()) {
+                        *out |= live;
+                    }
+
+                    for mir in blocks[succ].instructions.iter() {
+                        if let Mir::Phi { ref from, .. } = *mir {
+                            for &(source, reg) in from.iter() {
+                                if source == b {
+                                    out[reg.0] = true;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                let in_ = out
+                    .iter()
+                    .zip(gen[b].iter().zip(kill[b].iter()))
+                    .map(|(&out, (&gen, &kill))| gen || out && !kill)
+                    .collect::<Vec<_>>();
+
+                if in_ != live_in[b] || out != live_out[b] {
+                    live_in[b] = in_;
+                    live_out[b] = out;
+                    changed = true;
+                }
+            }
+        }
+
+        Self { live_in, live_out }
+    }
+
+    /// whether `reg` is live at the start of `block`, after its phis
+    pub fn is_live_in(&self, block: usize, reg: Reg) -> bool {
+        self.live_in[block][reg.0]
+    }
+
+    /// whether `reg` is live at the end of `block`
+    pub fn is_live_out(&self, block: usize, reg: Reg) -> bool {
+        self.live_out[block][reg.0]
+    }
+
+    pub fn live_in(&self, block: usize) -> impl Iterator<Item = Reg> + '_ {
+        live_regs(&self.live_in[block])
+    }
+
+    pub fn live_out(&self, block: usize) -> impl Iterator<Item = Reg> + '_ {
+        live_regs(&self.live_out[block])
+    }
+}
+
+fn live_regs(live: &[bool]) -> impl Iterator<Item = Reg> + '_ {
+    live.iter()
+        .enumerate()
+        .filter(|&(_, &live)| live)
+        .map(|(reg, _)| Reg(reg))
+}
+
+/// which registers can't share storage, because one of them is written while the
+/// other is still live
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interference {
+    edges: Vec<HashSet<usize>>,
+}
+
+impl Interference {
+    /// `ret` is the return register if `frame` is the frame of a function
+    pub fn new(frame: &StackFrame, ret: Option<Reg>) -> Self {
+        let cfg = Cfg::new(frame);
+        let liveness = Liveness::new(frame, &cfg, ret);
+        let blocks = frame.blocks();
+        let max_reg_count = frame.meta.max_reg_count;
+
+        let mut interference = Self {
+            edges: vec![HashSet::new(); max_reg_count],
+        };
+
+        for &b in cfg.reverse_postorder() {
+            let block = &blocks[b];
+            let mut live = liveness.live_out[b].clone();
+
+            if let Some(def) = block.terminator.def() {
+                interference.interfere_with_live(def, &live, None);
+                live[def.0] = false;
+            }
+
+            for reg in block.terminator.uses() {
+                live[reg.0] = true;
+            }
+
+            if let (Some(ret), Terminator::Return) = (ret, &block.terminator) {
+                live[ret.0] = true;
+            }
+
+            let mut phis = Vec::new();
+
+            for mir in block.instructions.iter().rev() {
+                if let Mir::Phi { to, .. } = *mir {
+                    phis.push(to);
+                    continue;
+                }
+
+                // a copy doesn't change the value, so it can share storage with its source
+                let copy = match *mir {
+                    Mir::LoadReg { from, .. } => Some(from),
+                    _ => None,
+                };
+
+                if let Some(def) = mir.def() {
+                    interference.interfere_with_live(def, &live, copy);
+                    live[def.0] = false;
+                }
+
+                for reg in mir.uses() {
+                    live[reg.0] = true;
+                }
+            }
+
+            // the phis of a block are all written at once
+            for &phi in phis.iter() {
+                live[phi.0] = true;
+            }
+
+            for &phi in phis.iter() {
+                live[phi.0] = false;
+                interference.interfere_with_live(phi, &live, None);
+                live[phi.0] = true;
+            }
+        }
+
+        // a cleanup can be entered from any instruction that panics while its landing pad
+        // is pushed, so the registers it reads are kept from sharing storage at all
+        for block in blocks.iter() {
+            for mir in block.instructions.iter() {
+                if let Mir::PushLandingPad { cleanup } = *mir {
+                    for reg in liveness.live_in(cleanup) {
+                        for other in 0..max_reg_count {
+                            interference.add(reg.0, other);
+                        }
+                    }
+                }
+            }
+        }
+
+        interference
+    }
+
+    fn add(&mut self, a: usize, b: usize) {
+        if a != b {
+            self.edges[a].insert(b);
+            self.edges[b].insert(a);
+        }
+    }
+
+    fn interfere_with_live(&mut self, def: Reg, live: &[bool], copy: Option<Reg>) {
+        for (reg, &live) in live.iter().enumerate() {
+            if live && Some(Reg(reg)) != copy {
+                self.add(def.0, reg);
+            }
+        }
+    }
+
+    pub fn interferes(&self, a: Reg, b: Reg) -> bool {
+        self.edges[a.0].contains(&b.0)
+    }
+
+    /// the registers that interfere with `reg`
+    pub fn neighbours(&self, reg: Reg) -> impl Iterator<Item = Reg> + '_ {
+        self.edges[reg.0].iter().map(|&reg| Reg(reg))
+    }
+}
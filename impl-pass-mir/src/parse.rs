@@ -0,0 +1,722 @@
+//! Parses the textual MIR printed by `Display` back into a `StackFrame`, so passes
+//! can be tested on hand-written MIR without going through the front end.
+//!
+//! ```text
+//! // comments start with `//`
+//! FRAME regs(3)
+//! BLOCK(0)
+//!   0: load(u8) reg(0) 6
+//!   1: load(u8) reg(1) 7
+//!   2: bin(Mul) reg(2), reg(0), reg(1) @ 8..13
+//!   3: print reg(2)
+//!      return
+//! ENDBLOCK(0)
+//! ```
+//!
+//! Every instruction is on a line of its own, and indentation doesn't matter. Each block
+//! ends with its terminator, which doesn't have an index. The index before each instruction,
+//! the `FRAME` line and the spans after instructions are optional, without a `FRAME` line
+//! a frame has one more register than the largest register it uses, and a missing span
+//! is `@ 0..0`.
+
+use core_mir::{BinOpType, CastKind, Load, PreOpType, Reg, Terminator};
+use core_tokens::Span;
+use core_types::Primitive;
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::*;
+
+const BIN_OPS: [BinOpType; 10] = [
+    BinOpType::Add,
+    BinOpType::Sub,
+    BinOpType::Mul,
+    BinOpType::Div,
+    BinOpType::Equal,
+    BinOpType::NotEqual,
+    BinOpType::LessThan,
+    BinOpType::LessThanOrEqual,
+    BinOpType::GreaterThan,
+    BinOpType::GreaterThanOrEqual,
+];
+
+const PRE_OPS: [PreOpType; 2] = [PreOpType::Not, PreOpType::Neg];
+
+const CAST_KINDS: [CastKind; 9] = [
+    CastKind::Infer,
+    CastKind::Identity,
+    CastKind::Truncate,
+    CastKind::SignExtend,
+    CastKind::ZeroExtend,
+    CastKind::IntToFloat,
+    CastKind::FloatToInt,
+    CastKind::FloatToFloat,
+    CastKind::BoolToInt,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub message: String,
+    /// the location of the error in the MIR source
+    pub span: Span,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// parse the textual MIR in `source`
+pub fn parse(source: &str) -> Result<StackFrame, Error> {
+    let mut parser = Parser {
+        lines: lines(source),
+        pos: 0,
+        end: source.len(),
+    };
+
+    parser.frame(false)
+}
+
+/// the lines of `source` without comments, skipping empty lines
+fn lines(source: &str) -> Vec<Cursor<'_>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for line in source.split('\n') {
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut end = line.len();
+
+        for (i, c) in line.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '/' if !in_string && line[i..].starts_with("//") => {
+                    end = i;
+                    break;
+                }
+                _ => (),
+            }
+        }
+
+        let text = line[..end].trim_end();
+
+        if !text.trim_start().is_empty() {
+            lines.push(Cursor {
+                text,
+                offset,
+                pos: 0,
+            });
+        }
+
+        offset += line.len() + 1;
+    }
+
+    lines
+}
+
+struct Parser<'a> {
+    lines: Vec<Cursor<'a>>,
+    pos: usize,
+    /// the length of the source
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Cursor<'a>> {
+        self.lines.get(self.pos).copied()
+    }
+
+    fn unexpected_end(&self, expected: &str) -> Error {
+        Error {
+            message: format!("expected {}, found the end of the MIR", expected),
+            span: Span::new(self.end, self.end),
+        }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Cursor<'a>, Error> {
+        let line = self.peek().ok_or_else(|| self.unexpected_end(expected))?;
+
+        self.pos += 1;
+
+        Ok(line)
+    }
+
+    /// parse a frame, a nested frame ends at its `endfn` line, which is not consumed
+    fn frame(&mut self, nested: bool) -> Result<StackFrame, Error> {
+        let mut max_reg_count = None;
+
+        if let Some(mut line) = self.peek() {
+            if line.keyword("FRAME") {
+                self.pos += 1;
+                line.expect("regs(")?;
+                max_reg_count = Some(line.number()?);
+                line.expect(")")?;
+                line.finish()?;
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let mut targets = Vec::new();
+
+        loop {
+            let mut line = match self.peek() {
+                Some(line) => line,
+                None if nested => return Err(self.unexpected_end("`endfn`")),
+                None => break,
+            };
+
+            if line.keyword("endfn") {
+                if nested {
+                    break;
+                }
+
+                return Err(line.error("`endfn` without a matching `fn`"));
+            }
+
+            self.pos += 1;
+
+            let index = blocks.len();
+            line.expect("BLOCK(")?;
+            line.index(index, "block")?;
+            line.expect(")")?;
+            line.finish()?;
+
+            let mut instructions = Vec::new();
+
+            let terminator = loop {
+                let mut line = self.next("an instruction or a terminator")?;
+
+                if line.eat("ENDBLOCK(") {
+                    return Err(line.error(format!("block {} doesn't have a terminator", index)));
+                }
+
+                if let Some(terminator) = line.terminator(&mut targets)? {
+                    break terminator;
+                }
+
+                let mir = self.instruction(line, instructions.len(), &mut targets)?;
+                instructions.push(mir);
+            };
+
+            let mut line = self.next(&format!("`ENDBLOCK({})`", index))?;
+            line.expect("ENDBLOCK(")?;
+            line.index(index, "block")?;
+            line.expect(")")?;
+            line.finish()?;
+
+            blocks.push(Block {
+                instructions,
+                terminator,
+                meta: BlockMeta { span: None },
+            });
+        }
+
+        for (target, span) in targets {
+            if target >= blocks.len() {
+                return Err(Error {
+                    message: format!("block {} doesn't exist", target),
+                    span,
+                });
+            }
+        }
+
+        let max_reg_count = max_reg_count.unwrap_or_else(|| {
+            blocks
+                .iter()
+                .flat_map(|block| {
+                    let terminator = &block.terminator;
+
+                    block
+                        .instructions
+                        .iter()
+                        .flat_map(|mir| mir.uses().into_iter().chain(mir.def()))
+                        .chain(terminator.uses().into_iter().chain(terminator.def()))
+                })
+                .map(|reg| reg.0 + 1)
+                .max()
+                .unwrap_or(0)
+        });
+
+        Ok(StackFrame::new(blocks, FrameMeta { max_reg_count })
+            .expect("the targets of all jumps were checked"))
+    }
+
+    fn instruction(
+        &mut self,
+        mut line: Cursor<'a>,
+        index: usize,
+        targets: &mut Vec<(usize, Span)>,
+    ) -> Result<Mir, Error> {
+        if line.has_index() {
+            line.index(index, "instruction")?;
+            line.expect(":")?;
+        }
+
+        let mir = if line.eat("load(") {
+            let kind = line.word()?;
+            line.expect(")")?;
+            let to = line.reg()?;
+
+            match kind {
+                "bool" => Mir::Load {
+                    to,
+                    from: Load::Bool(line.bool()?),
+                },
+                "u8" => Mir::Load {
+                    to,
+                    from: Load::U8(line.number()?),
+                },
+                "u16" => Mir::Load {
+                    to,
+                    from: Load::U16(line.number()?),
+                },
+                "u32" => Mir::Load {
+                    to,
+                    from: Load::U32(line.number()?),
+                },
+                "u64" => Mir::Load {
+                    to,
+                    from: Load::U64(line.number()?),
+                },
+                "u128" => Mir::Load {
+                    to,
+                    from: Load::U128(line.number()?),
+                },
+                "i32" => Mir::Load {
+                    to,
+                    from: Load::I32(line.number()?),
+                },
+                "reg" => Mir::LoadReg {
+                    to,
+                    from: line.reg()?,
+                },
+                _ => return Err(line.error(format!("unknown kind of load `{}`", kind))),
+            }
+        } else if line.keyword("print") {
+            Mir::Print(line.reg()?)
+        } else if line.eat("bin(") {
+            let op = line.named("binary operator", &BIN_OPS)?;
+            line.expect(")")?;
+            let out = line.reg()?;
+            line.expect(",")?;
+            let left = line.reg()?;
+            line.expect(",")?;
+            let right = line.reg()?;
+
+            Mir::BinOp {
+                op,
+                out,
+                left,
+                right,
+                span: line.span()?,
+            }
+        } else if line.eat("pre(") {
+            let op = line.named("prefix operator", &PRE_OPS)?;
+            line.expect(")")?;
+            let out = line.reg()?;
+            line.expect(",")?;
+            let arg = line.reg()?;
+
+            Mir::PreOp {
+                op,
+                out,
+                arg,
+                span: line.span()?,
+            }
+        } else if line.eat("cast(") {
+            let kind = line.named("kind of cast", &CAST_KINDS)?;
+            line.expect(")")?;
+            let to = line.reg()?;
+            let from = line.reg()?;
+            line.expect_keyword("as")?;
+
+            let name = line.word()?;
+            let ty = Primitive::from_name(name)
+                .ok_or_else(|| line.error(format!("`{}` is not a primitive type", name)))?;
+
+            Mir::Cast { to, from, ty, kind }
+        } else if line.keyword("fn") {
+            let binding = line.reg()?;
+            line.expect("->")?;
+            let ret = line.reg()?;
+            line.finish()?;
+
+            let stack_frame = self.frame(true)?;
+
+            let mut end = self.next("`endfn`")?;
+            end.expect_keyword("endfn")?;
+
+            if end.reg()? != binding || !end.eat("->") || end.reg()? != ret {
+                return Err(end.error(format!("expected `endfn {} -> {}`", binding, ret)));
+            }
+
+            end.finish()?;
+
+            return Ok(Mir::CreateFunc {
+                binding,
+                ret,
+                stack_frame,
+            });
+        } else if line.eat("pop(arg)") {
+            Mir::PopArgument { arg: line.reg()? }
+        } else if line.keyword("panic") {
+            Mir::Panic {
+                message: line.string()?,
+                span: line.span()?,
+            }
+        } else if line.eat("push(pad)") {
+            Mir::PushLandingPad {
+                cleanup: line.target(targets)?,
+            }
+        } else if line.eat("pop(pad)") {
+            Mir::PopLandingPad
+        } else if line.keyword("phi") {
+            let to = line.reg()?;
+            let mut from = Vec::new();
+
+            line.expect("[")?;
+
+            while !line.eat("]") {
+                if !from.is_empty() {
+                    line.expect(",")?;
+                }
+
+                let block = line.target(targets)?;
+                line.expect("->")?;
+                from.push((block, line.reg()?));
+            }
+
+            Mir::Phi { to, from }
+        } else {
+            return Err(line.error("expected an instruction"));
+        };
+
+        line.finish()?;
+
+        Ok(mir)
+    }
+}
+
+/// a line of MIR, and how much of it was parsed
+#[derive(Clone, Copy)]
+struct Cursor<'a> {
+    text: &'a str,
+    /// the offset of the line in the source
+    offset: usize,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// an error pointing at the rest of the line
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error {
+            message: message.into(),
+            span: Span::new(self.offset + self.pos, self.offset + self.text.len()),
+        }
+    }
+
+    /// there must be nothing left on the line
+    fn finish(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+
+        if self.rest().is_empty() {
+            Ok(())
+        } else {
+            Err(self.error(format!("unexpected `{}`", self.rest())))
+        }
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_whitespace();
+
+        if self.rest().starts_with(text) {
+            self.pos += text.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), Error> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", text)))
+        }
+    }
+
+    /// like `eat`, but `keyword` must not be followed by more of a word
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let pos = self.pos;
+
+        if self.eat(keyword) && !self.rest().starts_with(is_word_char) {
+            true
+        } else {
+            self.pos = pos;
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", keyword)))
+        }
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        self.skip_whitespace();
+
+        let rest = self.rest();
+        let len = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+
+        self.pos += len;
+
+        Ok(&rest[..len])
+    }
+
+    /// a word that is the `Debug` name of one of `values`
+    fn named<T: fmt::Debug + Copy>(&mut self, what: &str, values: &[T]) -> Result<T, Error> {
+        let name = self.word()?;
+
+        values
+            .iter()
+            .copied()
+            .find(|value| format!("{:?}", value) == name)
+            .ok_or_else(|| self.error(format!("unknown {} `{}`", what, name)))
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, Error> {
+        self.skip_whitespace();
+
+        let rest = self.rest();
+        // a sign is only valid if `T` is signed, which `parse` checks
+        let sign = if rest.starts_with('-') { 1 } else { 0 };
+        let len = rest[sign..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(rest.len(), |len| sign + len);
+
+        match rest[..len].parse() {
+            Ok(number) => {
+                self.pos += len;
+                Ok(number)
+            }
+            Err(_) => Err(self.error("expected a number")),
+        }
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        if self.keyword("true") {
+            Ok(true)
+        } else if self.keyword("false") {
+            Ok(false)
+        } else {
+            Err(self.error("expected `true` or `false`"))
+        }
+    }
+
+    fn reg(&mut self) -> Result<Reg, Error> {
+        self.expect("reg(")?;
+        let reg = Reg(self.number()?);
+        self.expect(")")?;
+
+        Ok(reg)
+    }
+
+    /// a block that is jumped to, which is checked once all blocks are known
+    fn target(&mut self, targets: &mut Vec<(usize, Span)>) -> Result<usize, Error> {
+        self.skip_whitespace();
+
+        let start = self.offset + self.pos;
+        let target = self.number()?;
+
+        targets.push((target, Span::new(start, self.offset + self.pos)));
+
+        Ok(target)
+    }
+
+    /// the terminator on this line, or `None` if the line doesn't start with one
+    fn terminator(
+        &mut self,
+        targets: &mut Vec<(usize, Span)>,
+    ) -> Result<Option<Terminator>, Error> {
+        let terminator = if self.keyword("jmp") {
+            Terminator::Goto(self.target(targets)?)
+        } else if self.keyword("branch") {
+            let cond = self.reg()?;
+            self.expect_keyword("then")?;
+            let then = self.target(targets)?;
+            self.expect_keyword("else")?;
+
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise: self.target(targets)?,
+            }
+        } else if self.keyword("switch") {
+            let value = self.reg()?;
+            let mut cases = Vec::new();
+
+            self.expect("[")?;
+
+            while !self.eat("]") {
+                if !cases.is_empty() {
+                    self.expect(",")?;
+                }
+
+                let case = self.number()?;
+                self.expect("->")?;
+                cases.push((case, self.target(targets)?));
+            }
+
+            self.expect_keyword("else")?;
+
+            Terminator::Switch {
+                value,
+                cases,
+                otherwise: self.target(targets)?,
+            }
+        } else if self.keyword("return") {
+            Terminator::Return
+        } else if self.keyword("unreachable") {
+            Terminator::Unreachable
+        } else if self.keyword("call") {
+            let func = self.reg()?;
+            let mut args = Vec::new();
+
+            self.expect("(")?;
+
+            while !self.eat(")") {
+                if !args.is_empty() {
+                    self.expect(",")?;
+                }
+
+                args.push(self.reg()?);
+            }
+
+            self.expect("->")?;
+            let ret = self.reg()?;
+            self.expect_keyword("then")?;
+
+            Terminator::Call {
+                func,
+                args,
+                ret,
+                next: self.target(targets)?,
+            }
+        } else if self.keyword("resume") {
+            Terminator::Resume
+        } else {
+            return Ok(None);
+        };
+
+        self.finish()?;
+
+        Ok(Some(terminator))
+    }
+
+    /// the line starts with the index of the instruction
+    fn has_index(&self) -> bool {
+        let rest = self.rest().trim_start();
+        let digits = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+
+        digits.len() != rest.len() && digits.trim_start().starts_with(':')
+    }
+
+    fn index(&mut self, expected: usize, what: &str) -> Result<(), Error> {
+        let index = self.number::<usize>()?;
+
+        if index == expected {
+            Ok(())
+        } else {
+            Err(self.error(format!(
+                "expected {} {}, found {} {}",
+                what, expected, what, index
+            )))
+        }
+    }
+
+    /// the span of an instruction in the program's source, `@ start..end`
+    fn span(&mut self) -> Result<Span, Error> {
+        if !self.eat("@") {
+            return Ok(Span::new(0, 0));
+        }
+
+        let start = self.number()?;
+        self.expect("..")?;
+        let end = self.number()?;
+
+        Ok(Span::new(start, end))
+    }
+
+    /// a string literal, with the escapes of Rust's `Debug` output for strings
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect("\"")?;
+
+        let mut output = String::new();
+
+        loop {
+            let c = match self.rest().chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+
+            self.pos += c.len_utf8();
+
+            match c {
+                '"' => return Ok(output),
+                '\\' => match self.escape() {
+                    Some(c) => output.push(c),
+                    None => return Err(self.error("invalid escape in string")),
+                },
+                c => output.push(c),
+            }
+        }
+    }
+
+    /// the character of an escape in a string, after the `\`
+    fn escape(&mut self) -> Option<char> {
+        let c = self.rest().chars().next()?;
+        self.pos += c.len_utf8();
+
+        match c {
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            '0' => Some('\0'),
+            '\\' | '"' | '\'' => Some(c),
+            'u' => {
+                let hex = self.rest().strip_prefix('{')?;
+                let len = hex.find('}')?;
+                let c = u32::from_str_radix(&hex[..len], 16).ok()?;
+
+                self.pos += len + 2;
+
+                std::char::from_u32(c)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -0,0 +1,483 @@
+//! Sparse conditional constant propagation over a `StackFrame` in SSA form
+//!
+//! Every register starts out unknown and is only lowered to a constant or to varying,
+//! and a block is only visited once an edge into it is found to be executable, so a
+//! constant that decides a branch keeps the other side from weakening the values of
+//! the registers it writes. This is the algorithm from "Constant Propagation with
+//! Conditional Branches" by Wegman and Zadeck.
+//!
+//! Afterwards every instruction that computes a constant is replaced with a load,
+//! branches and switches on constants become jumps, and the blocks that can no longer
+//! be reached are removed, along with the registers only they wrote.
+//!
+//! Instructions that overflow or divide by zero are never folded, so they still panic
+//! when the program runs. Floats are never folded either.
+
+use core_mir::{BinOpType, Load, PreOpType, Reg, Terminator};
+use core_types::{Primitive, Ty, Variant};
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+
+use super::*;
+
+/// how many constants were folded
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// instructions replaced with the load of their constant
+    pub constants: usize,
+    /// branches and switches on constants replaced with jumps
+    pub branches: usize,
+    /// blocks that can no longer be reached
+    pub blocks: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "folded {} constants and {} branches, and removed {} unreachable blocks",
+            self.constants, self.branches, self.blocks
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    /// not computed yet, this may still become anything
+    Unknown,
+    Const(Const),
+    /// may hold different values when the program runs
+    Varying,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Const {
+    Bool(bool),
+    Int(i128),
+}
+
+impl Value {
+    /// the value that is compatible with both `self` and `other`
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Unknown, value) | (value, Self::Unknown) => value,
+            (Self::Const(a), Self::Const(b)) if a == b => self,
+            _ => Self::Varying,
+        }
+    }
+}
+
+/// fold the constants of `frame`, which must be in SSA form with `types` inferred for it
+///
+/// the types of functions depend on their arguments, so the frames of functions are
+/// left as they are, and so are frames with cleanups, which aren't in SSA form
+pub fn propagate(frame: &mut StackFrame, types: &[Ty<'_, '_>]) -> Stats {
+    let mut stats = Stats::default();
+
+    if cfg::has_cleanups(frame) || frame.blocks().is_empty() {
+        return stats;
+    }
+
+    let prims = types
+        .iter()
+        .map(|ty| match ty.ty {
+            Variant::Primitive(prim) => Some(prim),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut propagation = Propagation::new(frame.blocks(), &prims);
+    propagation.run();
+
+    let Propagation {
+        values, executable, ..
+    } = propagation;
+
+    for (block, _) in frame
+        .blocks_mut()
+        .iter_mut()
+        .zip(executable)
+        .filter(|&(_, executable)| executable)
+    {
+        stats.constants += fold(block, &values, &prims);
+
+        let target = match block.terminator {
+            Terminator::Branch { .. } | Terminator::Switch { .. } => {
+                constant_target(&block.terminator, &values, &prims)
+            }
+            _ => None,
+        };
+
+        if let Some(target) = target {
+            block.terminator = Terminator::Goto(target);
+            stats.branches += 1;
+        }
+    }
+
+    stats.blocks = cfg::remove_unreachable(frame);
+
+    if stats.blocks != 0 {
+        let max_reg_count = frame.meta.max_reg_count;
+        ssa::compact(frame, max_reg_count, &mut None);
+    }
+
+    stats
+}
+
+/// replace the instructions of `block` that compute a constant with loads, and return
+/// how many were replaced
+fn fold(block: &mut Block, values: &[Value], prims: &[Option<Primitive>]) -> usize {
+    let instructions = std::mem::take(&mut block.instructions);
+    let phis = instructions
+        .iter()
+        .take_while(|mir| match **mir {
+            Mir::Phi { .. } => true,
+            _ => false,
+        })
+        .count();
+
+    // folded phis become loads, which have to come after the phis that are left
+    let mut loads = Vec::new();
+    let mut folded = 0;
+
+    for (i, mir) in instructions.into_iter().enumerate() {
+        let load = match mir {
+            Mir::Load { .. } => None,
+            _ => mir.def().and_then(|to| {
+                constant_load(values[to.0], prims[to.0]).map(|from| Mir::Load { to, from })
+            }),
+        };
+
+        if load.is_some() {
+            folded += 1;
+        }
+
+        match load {
+            Some(load) if i < phis => loads.push(load),
+            Some(load) => block.instructions.push(load),
+            None => block.instructions.push(mir),
+        }
+
+        if i + 1 == phis {
+            block.instructions.append(&mut loads);
+        }
+    }
+
+    folded
+}
+
+/// the load of `value` into a register of type `prim`, if there is one with the same type
+fn constant_load(value: Value, prim: Option<Primitive>) -> Option<Load> {
+    match (value, prim?) {
+        (Value::Const(Const::Bool(x)), Primitive::Bool) => Some(Load::Bool(x)),
+        // the small loads are the ones the encoder uses for literals
+        (Value::Const(Const::Int(x)), Primitive::I32) => u8::try_from(x)
+            .map(Load::U8)
+            .or_else(|_| u16::try_from(x).map(Load::U16))
+            .or_else(|_| i32::try_from(x).map(Load::I32))
+            .ok(),
+        _ => None,
+    }
+}
+
+/// the only block a branch or switch can go to, if it tests a constant
+fn constant_target(
+    terminator: &Terminator,
+    values: &[Value],
+    prims: &[Option<Primitive>],
+) -> Option<usize> {
+    match *terminator {
+        Terminator::Branch {
+            cond,
+            then,
+            otherwise,
+        } => match values[cond.0] {
+            Value::Const(Const::Bool(true)) => Some(then),
+            Value::Const(Const::Bool(false)) => Some(otherwise),
+            _ => None,
+        },
+        Terminator::Switch {
+            value,
+            ref cases,
+            otherwise,
+        } => match (values[value.0], prims[value.0]) {
+            // the cases are truncated to the type of the value, like in the interpreter
+            (Value::Const(Const::Int(x)), Some(prim)) => cases
+                .iter()
+                .find(|&&(case, _)| wrap(i128::from(case as u64), prim) == x)
+                .map_or(Some(otherwise), |&(_, target)| Some(target)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct Propagation<'a> {
+    blocks: &'a [Block],
+    prims: &'a [Option<Primitive>],
+    values: Vec<Value>,
+    executable: Vec<bool>,
+    /// the edges between blocks that were found to be executable
+    edges: HashSet<(usize, usize)>,
+    /// the edges that may have become executable
+    flow_list: Vec<(usize, usize)>,
+    /// the registers whose value was lowered, so their uses have to be visited again
+    ssa_list: Vec<Reg>,
+    /// where each register is read, the instruction in a block or its terminator
+    uses: Vec<Vec<(usize, Option<usize>)>>,
+}
+
+impl<'a> Propagation<'a> {
+    fn new(blocks: &'a [Block], prims: &'a [Option<Primitive>]) -> Self {
+        let mut uses = vec![Vec::new(); prims.len()];
+
+        for (b, block) in blocks.iter().enumerate() {
+            for (i, mir) in block.instructions.iter().enumerate() {
+                for reg in mir.uses() {
+                    uses[reg.0].push((b, Some(i)));
+                }
+            }
+
+            for reg in block.terminator.uses() {
+                uses[reg.0].push((b, None));
+            }
+        }
+
+        Self {
+            blocks,
+            prims,
+            values: vec![Value::Unknown; prims.len()],
+            executable: vec![false; blocks.len()],
+            edges: HashSet::new(),
+            flow_list: Vec::new(),
+            ssa_list: Vec::new(),
+            uses,
+        }
+    }
+
+    fn run(&mut self) {
+        self.executable[0] = true;
+        self.visit_block(0);
+
+        loop {
+            if let Some((from, to)) = self.flow_list.pop() {
+                if !self.edges.insert((from, to)) {
+                    continue;
+                }
+
+                // the phis read a new source, the rest of the block only has to be visited once
+                if self.executable[to] {
+                    self.visit_phis(to);
+                } else {
+                    self.executable[to] = true;
+                    self.visit_block(to);
+                }
+            } else if let Some(reg) = self.ssa_list.pop() {
+                for i in 0..self.uses[reg.0].len() {
+                    let (b, at) = self.uses[reg.0][i];
+
+                    if !self.executable[b] {
+                        continue;
+                    }
+
+                    match at {
+                        Some(i) => self.visit_instruction(b, i),
+                        None => self.visit_terminator(b),
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn visit_block(&mut self, b: usize) {
+        for i in 0..self.blocks[b].instructions.len() {
+            self.visit_instruction(b, i);
+        }
+
+        self.visit_terminator(b);
+    }
+
+    fn visit_phis(&mut self, b: usize) {
+        let blocks = self.blocks;
+
+        for (i, mir) in blocks[b].instructions.iter().enumerate() {
+            match *mir {
+                Mir::Phi { .. } => self.visit_instruction(b, i),
+                _ => break,
+            }
+        }
+    }
+
+    fn visit_instruction(&mut self, b: usize, i: usize) {
+        let (blocks, prims) = (self.blocks, self.prims);
+        let mir = &blocks[b].instructions[i];
+
+        let value = match *mir {
+            Mir::Load { to, from } => load(from, prims[to.0]),
+            Mir::LoadReg { from, .. } => self.values[from.0],
+            Mir::BinOp {
+                op, left, right, ..
+            } => self.fold(&[left, right], |args| {
+                fold_bin_op(op, prims[left.0]?, args[0], args[1])
+            }),
+            Mir::PreOp { op, arg, .. } => {
+                self.fold(&[arg], |args| fold_pre_op(op, prims[arg.0]?, args[0]))
+            }
+            Mir::Cast { to, from, .. } => {
+                self.fold(&[from], |args| fold_cast(args[0], prims[to.0]?))
+            }
+            Mir::Phi { ref from, .. } => from
+                .iter()
+                .filter(|&&(source, _)| self.edges.contains(&(source, b)))
+                .fold(Value::Unknown, |value, &(_, reg)| {
+                    value.meet(self.values[reg.0])
+                }),
+            _ => Value::Varying,
+        };
+
+        if let Some(def) = mir.def() {
+            self.lower(def, value);
+        }
+    }
+
+    fn visit_terminator(&mut self, b: usize) {
+        let blocks = self.blocks;
+        let terminator = &blocks[b].terminator;
+
+        match *terminator {
+            Terminator::Branch { cond: reg, .. } | Terminator::Switch { value: reg, .. } => {
+                if self.values[reg.0] == Value::Unknown {
+                    return;
+                }
+
+                if let Some(target) = constant_target(terminator, &self.values, self.prims) {
+                    self.flow_list.push((b, target));
+                    return;
+                }
+            }
+            Terminator::Call { ret, .. } => self.lower(ret, Value::Varying),
+            _ => (),
+        }
+
+        for target in terminator.successors() {
+            self.flow_list.push((b, target));
+        }
+    }
+
+    /// the value of an instruction that reads `args`, `fold` computes it from their
+    /// constants, and returns `None` if it can't be computed before the program runs
+    fn fold(&self, args: &[Reg], fold: impl FnOnce(&[Const]) -> Option<Const>) -> Value {
+        let mut consts = Vec::with_capacity(args.len());
+
+        for &arg in args {
+            match self.values[arg.0] {
+                Value::Varying => return Value::Varying,
+                Value::Const(x) => consts.push(x),
+                Value::Unknown => (),
+            }
+        }
+
+        if consts.len() < args.len() {
+            return Value::Unknown;
+        }
+
+        fold(&consts).map_or(Value::Varying, Value::Const)
+    }
+
+    /// lower the value of `reg` to `value`, values only ever go down, so this terminates
+    fn lower(&mut self, reg: Reg, value: Value) {
+        let lowered = self.values[reg.0].meet(value);
+
+        if lowered != self.values[reg.0] {
+            self.values[reg.0] = lowered;
+            self.ssa_list.push(reg);
+        }
+    }
+}
+
+/// the value of a load into a register of type `prim`
+fn load(from: Load, prim: Option<Primitive>) -> Value {
+    let x = match from {
+        Load::Bool(x) => return Value::Const(Const::Bool(x)),
+        Load::U8(x) => x.into(),
+        Load::U16(x) => x.into(),
+        Load::U32(x) => x.into(),
+        Load::U64(x) => x,
+        // loads are 64 bits wide when the program runs
+        Load::U128(x) => x as u64,
+        Load::I32(x) => x as u64,
+    };
+
+    match prim {
+        Some(prim) if prim.is_integer() => Value::Const(Const::Int(wrap(x.into(), prim))),
+        _ => Value::Varying,
+    }
+}
+
+/// `x` truncated to the integer type `prim`, like an `as` cast
+fn wrap(x: i128, prim: Primitive) -> i128 {
+    let bits = prim.size() * 8;
+    let x = x & ((1 << bits) - 1);
+
+    if prim.is_signed() && x >= 1 << (bits - 1) {
+        x - (1 << bits)
+    } else {
+        x
+    }
+}
+
+/// `x` if it fits in the integer type `prim`, otherwise the operation overflowed
+fn in_range(x: i128, prim: Primitive) -> Option<Const> {
+    if wrap(x, prim) == x {
+        Some(Const::Int(x))
+    } else {
+        None
+    }
+}
+
+fn fold_bin_op(op: BinOpType, prim: Primitive, left: Const, right: Const) -> Option<Const> {
+    match (left, right) {
+        (Const::Bool(l), Const::Bool(r)) => match op {
+            BinOpType::Equal => Some(Const::Bool(l == r)),
+            BinOpType::NotEqual => Some(Const::Bool(l != r)),
+            _ => None,
+        },
+        (Const::Int(l), Const::Int(r)) => match op {
+            BinOpType::Add => in_range(l.checked_add(r)?, prim),
+            BinOpType::Sub => in_range(l.checked_sub(r)?, prim),
+            BinOpType::Mul => in_range(l.checked_mul(r)?, prim),
+            // dividing by zero panics, and so does dividing the minimum by -1
+            BinOpType::Div if r == 0 => None,
+            BinOpType::Div => in_range(l / r, prim),
+            BinOpType::Equal => Some(Const::Bool(l == r)),
+            BinOpType::NotEqual => Some(Const::Bool(l != r)),
+            BinOpType::LessThan => Some(Const::Bool(l < r)),
+            BinOpType::LessThanOrEqual => Some(Const::Bool(l <= r)),
+            BinOpType::GreaterThan => Some(Const::Bool(l > r)),
+            BinOpType::GreaterThanOrEqual => Some(Const::Bool(l >= r)),
+        },
+        _ => None,
+    }
+}
+
+fn fold_pre_op(op: PreOpType, prim: Primitive, arg: Const) -> Option<Const> {
+    match (op, arg) {
+        (PreOpType::Not, Const::Bool(x)) => Some(Const::Bool(!x)),
+        (PreOpType::Not, Const::Int(x)) => Some(Const::Int(wrap(!x, prim))),
+        (PreOpType::Neg, Const::Int(x)) if prim.is_signed() => in_range(-x, prim),
+        _ => None,
+    }
+}
+
+fn fold_cast(from: Const, to: Primitive) -> Option<Const> {
+    match from {
+        Const::Bool(x) if to == Primitive::Bool => Some(Const::Bool(x)),
+        _ if !to.is_integer() => None,
+        Const::Bool(x) => Some(Const::Int(x.into())),
+        Const::Int(x) => Some(Const::Int(wrap(x, to))),
+    }
+}
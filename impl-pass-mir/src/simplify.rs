@@ -0,0 +1,230 @@
+//! Simplifies the control flow graph of a `StackFrame`
+//!
+//! The encoder creates a block wherever control flow may join, so `if` and `loop` leave
+//! behind many empty blocks that only jump to the next one. This pass
+//!
+//! * threads jumps through empty blocks, straight to the block they forward to
+//! * turns branches and switches whose targets are all the same into jumps
+//! * merges a block into the block before it, if that is its only predecessor and
+//!   always jumps to it
+//! * removes the blocks that are no longer reached and renumbers the rest
+//!
+//! Jumps are never threaded into a block with phis, since the phis would need a source
+//! for the new predecessor, and cleanup landing pads are never merged, since they are
+//! entered by unwinding, not by a jump.
+
+use core_mir::Terminator;
+
+use std::fmt;
+use std::ops::AddAssign;
+
+use super::*;
+
+/// how much the control flow graph was simplified
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// jumps that go straight to their target instead of through empty blocks
+    pub threaded: usize,
+    /// blocks merged into their only predecessor
+    pub merged: usize,
+    /// blocks that were removed
+    pub blocks: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.threaded += other.threaded;
+        self.merged += other.merged;
+        self.blocks += other.blocks;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "threaded {} jumps, merged {} blocks and removed {} blocks",
+            self.threaded, self.merged, self.blocks
+        )
+    }
+}
+
+/// simplify the control flow graph of `frame` and of all of its functions
+pub fn simplify(frame: &mut StackFrame) -> Stats {
+    let mut stats = Stats::default();
+
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                stats += simplify(stack_frame);
+            }
+        }
+    }
+
+    loop {
+        let threaded = thread_jumps(frame);
+        let merged = merge_blocks(frame);
+
+        if threaded == 0 && merged == 0 {
+            break;
+        }
+
+        stats.threaded += threaded;
+        stats.merged += merged;
+    }
+
+    stats.blocks += cfg::remove_unreachable(frame);
+    stats
+}
+
+fn has_phis(block: &Block) -> bool {
+    match block.instructions.first() {
+        Some(Mir::Phi { .. }) => true,
+        _ => false,
+    }
+}
+
+/// redirect every jump to an empty block to the block it forwards to, returns how many
+/// jumps were redirected
+fn thread_jumps(frame: &mut StackFrame) -> usize {
+    let blocks = frame.blocks();
+
+    let forward = |b: usize| match blocks[b].terminator {
+        Terminator::Goto(target)
+            if blocks[b].instructions.is_empty() && !has_phis(&blocks[target]) =>
+        {
+            Some(target)
+        }
+        _ => None,
+    };
+
+    // the block each block forwards to in the end, an empty loop forwards nowhere
+    let destinations = (0..blocks.len())
+        .map(|b| {
+            let mut target = b;
+
+            for _ in 0..blocks.len() {
+                match forward(target) {
+                    Some(next) => target = next,
+                    None => return target,
+                }
+            }
+
+            b
+        })
+        .collect::<Vec<_>>();
+
+    let mut threaded = 0;
+
+    let mut redirect = |target: &mut usize| {
+        if destinations[*target] != *target {
+            *target = destinations[*target];
+            threaded += 1;
+        }
+    };
+
+    for block in frame.blocks_mut() {
+        for target in block.terminator.successors_mut() {
+            redirect(target);
+        }
+
+        for mir in block.instructions.iter_mut() {
+            if let Mir::PushLandingPad { ref mut cleanup } = *mir {
+                redirect(cleanup);
+            }
+        }
+
+        let mut successors = block.terminator.successors();
+        successors.sort_unstable();
+        successors.dedup();
+
+        let target = match (&block.terminator, &successors[..]) {
+            (Terminator::Branch { .. }, &[target]) | (Terminator::Switch { .. }, &[target]) => {
+                Some(target)
+            }
+            _ => None,
+        };
+
+        if let Some(target) = target {
+            block.terminator = Terminator::Goto(target);
+        }
+    }
+
+    threaded
+}
+
+/// merge every block into its predecessor, if that is its only predecessor and always
+/// jumps to it, returns how many blocks were merged
+fn merge_blocks(frame: &mut StackFrame) -> usize {
+    let mut predecessors = frame.predecessors();
+    let mut is_cleanup = vec![false; frame.blocks().len()];
+
+    for block in frame.blocks() {
+        for mir in block.instructions.iter() {
+            if let Mir::PushLandingPad { cleanup } = *mir {
+                is_cleanup[cleanup] = true;
+            }
+        }
+    }
+
+    let mut merged = 0;
+
+    for a in 0..frame.blocks().len() {
+        while let Terminator::Goto(b) = frame.blocks()[a].terminator {
+            if b == a || b == 0 || is_cleanup[b] || predecessors[b] != [a] {
+                break;
+            }
+
+            let blocks = frame.blocks_mut();
+            let next = std::mem::replace(
+                &mut blocks[b],
+                Block {
+                    instructions: Vec::new(),
+                    terminator: Terminator::Unreachable,
+                    meta: BlockMeta { span: None },
+                },
+            );
+
+            // `a` is the only predecessor, so each phi has a single source
+            let instructions = next.instructions.into_iter().map(|mir| match mir {
+                Mir::Phi { to, from } => Mir::LoadReg {
+                    to,
+                    from: from[0].1,
+                },
+                mir => mir,
+            });
+
+            blocks[a].instructions.extend(instructions);
+            blocks[a].terminator = next.terminator;
+
+            if blocks[a].meta.span.is_none() {
+                blocks[a].meta = next.meta;
+            }
+
+            predecessors[b].clear();
+
+            // the successors of `b` now come from `a`
+            for succ in blocks[a].terminator.successors() {
+                for pred in predecessors[succ].iter_mut().filter(|pred| **pred == b) {
+                    *pred = a;
+                }
+
+                for mir in blocks[succ].instructions.iter_mut() {
+                    if let Mir::Phi { ref mut from, .. } = *mir {
+                        for (source, _) in from.iter_mut().filter(|(source, _)| *source == b) {
+                            *source = a;
+                        }
+                    }
+                }
+            }
+
+            merged += 1;
+        }
+    }
+
+    merged
+}
@@ -0,0 +1,490 @@
+//! Converts a `StackFrame` into static single assignment form and back
+//!
+//! The encoder writes every assignment to a variable into the same register, in SSA form
+//! every register is written by exactly one instruction instead, and where different
+//! writes to a variable meet a phi picks the one from the path control came from. This
+//! makes the flow of values explicit, so passes can work on it without a dataflow analysis.
+//!
+//! Phis are placed at the iterated dominance frontiers of the blocks that write a register,
+//! as in "Efficiently Computing Static Single Assignment Form and the Control Dependence
+//! Graph" by Cytron et al, and phis that would read a register that isn't assigned on some
+//! path are removed, their value can't be used anyway.
+//!
+//! Neither the interpreter nor the C backend need SSA form, so `destruct` lowers the
+//! phis back to copies at the end of the blocks they come from.
+
+use core_mir::{Reg, Terminator};
+
+use super::cfg::{self, Cfg, Dominators};
+use super::*;
+
+/// convert `frame` and the frames of all of its functions to SSA form
+///
+/// frames with cleanups are left as they are, a cleanup can be entered from the middle
+/// of a block, which a phi can't express, see `cfg::has_cleanups`
+///
+/// the registers are renumbered, so the types of the frame must be inferred again
+pub fn construct(frame: &mut StackFrame) {
+    construct_frame(frame, None);
+}
+
+/// lower the phis of `frame` and of all of its functions to copies
+///
+/// the registers are no longer assigned only once, so passes which need SSA form
+/// can't run on the frame afterwards
+pub fn destruct(frame: &mut StackFrame) {
+    for b in 0..frame.blocks().len() {
+        for mir in frame.blocks_mut()[b].instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                destruct(stack_frame);
+            }
+        }
+    }
+
+    for b in 0..frame.blocks().len() {
+        let phis = frame.blocks()[b]
+            .instructions
+            .iter()
+            .take_while(|mir| match **mir {
+                Mir::Phi { .. } => true,
+                _ => false,
+            })
+            .count();
+
+        if phis == 0 {
+            continue;
+        }
+
+        let phis = frame.blocks_mut()[b]
+            .instructions
+            .drain(..phis)
+            .map(|mir| match mir {
+                Mir::Phi { to, from } => (to, from),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        let sources = phis[0]
+            .1
+            .iter()
+            .map(|&(source, _)| source)
+            .collect::<Vec<_>>();
+
+        for source in sources {
+            let copies = phis
+                .iter()
+                .map(|&(to, ref from)| {
+                    let &(_, from) = from
+                        .iter()
+                        .find(|&&(block, _)| block == source)
+                        .expect("every phi of a block has the same sources");
+
+                    (to, from)
+                })
+                .collect::<Vec<_>>();
+
+            let copies = sequentialize(copies, &mut frame.meta.max_reg_count);
+            let terminator = &frame.blocks()[source].terminator;
+
+            // the copies can only go at the end of `source` if they don't run
+            // on the way to any other block, or before a call writes its return value
+            if terminator.successors().len() == 1 && terminator.def().is_none() {
+                frame.blocks_mut()[source].instructions.extend(copies);
+            } else {
+                let edge = frame.push(Block {
+                    instructions: copies,
+                    terminator: Terminator::Goto(b),
+                    meta: BlockMeta { span: None },
+                });
+
+                for target in frame.blocks_mut()[source].terminator.successors_mut() {
+                    if *target == b {
+                        *target = edge;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// order copies which happen all at once so that no copy overwrites a register another
+/// copy still has to read, cycles are broken with a new register
+fn sequentialize(mut copies: Vec<(Reg, Reg)>, max_reg_count: &mut usize) -> Vec<Mir> {
+    let mut sequence = Vec::with_capacity(copies.len());
+
+    copies.retain(|&(to, from)| to != from);
+
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|&(to, _)| copies.iter().all(|&(_, from)| from != to));
+
+        match ready {
+            Some(ready) => {
+                let (to, from) = copies.remove(ready);
+                sequence.push(Mir::LoadReg { to, from });
+            }
+            None => {
+                // every destination is still read by another copy, so save one of them
+                let (saved, _) = copies[0];
+                let temp = Reg(*max_reg_count);
+                *max_reg_count += 1;
+
+                sequence.push(Mir::LoadReg {
+                    to: temp,
+                    from: saved,
+                });
+
+                for (_, from) in copies.iter_mut() {
+                    if *from == saved {
+                        *from = temp;
+                    }
+                }
+            }
+        }
+    }
+
+    sequence
+}
+
+/// a phi which is being renamed, its sources are `None` until their block is renamed,
+/// or if the register isn't assigned at the end of the source
+struct Phi {
+    /// the register the phi merges, before renaming
+    var: Reg,
+    to: Reg,
+    from: Vec<(usize, Option<Reg>)>,
+}
+
+struct Renamer<'a> {
+    cfg: &'a Cfg,
+    doms: &'a Dominators,
+    /// the current name of each register, innermost last
+    names: Vec<Vec<Reg>>,
+    phis: Vec<Vec<Phi>>,
+    next_reg: usize,
+    ret: Option<Reg>,
+    /// the name of `ret` when the function returns
+    new_ret: Option<Reg>,
+}
+
+/// returns the register that holds the return value of the frame after renaming
+fn construct_frame(frame: &mut StackFrame, ret: Option<Reg>) -> Option<Reg> {
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::CreateFunc {
+                ret: ref mut func_ret,
+                ref mut stack_frame,
+                ..
+            } = *mir
+            {
+                *func_ret = construct_frame(stack_frame, Some(*func_ret)).unwrap();
+            }
+        }
+    }
+
+    if cfg::has_cleanups(frame) {
+        return ret;
+    }
+
+    // unreachable blocks are never renamed, so they are emptied to keep them from
+    // reading registers that no longer exist, and from being predecessors of any block
+    let cfg = Cfg::new(frame);
+
+    for (b, block) in frame.blocks_mut().iter_mut().enumerate() {
+        if !cfg.is_reachable(b) {
+            block.instructions.clear();
+            block.terminator = Terminator::Unreachable;
+        }
+    }
+
+    // a function returns the value of `ret` from a single block, so the different
+    // values it may hold when it returns can be merged by a phi
+    let returns = (0..frame.blocks().len())
+        .filter(|&b| frame.blocks()[b].terminator == Terminator::Return)
+        .collect::<Vec<_>>();
+
+    if ret.is_some() && returns.len() > 1 {
+        let exit = frame.push(Block {
+            instructions: Vec::new(),
+            terminator: Terminator::Return,
+            meta: BlockMeta { span: None },
+        });
+
+        for b in returns {
+            frame.blocks_mut()[b].terminator = Terminator::Goto(exit);
+        }
+    }
+
+    let cfg = Cfg::new(frame);
+    let doms = Dominators::new(&cfg);
+    let frontiers = doms.frontiers(&cfg);
+    let max_reg_count = frame.meta.max_reg_count;
+
+    let mut def_blocks = vec![Vec::new(); max_reg_count];
+
+    for &b in cfg.reverse_postorder() {
+        let block = &frame.blocks()[b];
+        let defs = block.instructions.iter().filter_map(Mir::def);
+
+        for reg in defs.chain(block.terminator.def()) {
+            if !def_blocks[reg.0].contains(&b) {
+                def_blocks[reg.0].push(b);
+            }
+        }
+    }
+
+    let mut phis = (0..frame.blocks().len())
+        .map(|_| Vec::new())
+        .collect::<Vec<_>>();
+
+    // a cleanup only resumes unwinding, so it never needs a phi, and one couldn't tell
+    // which write it sees, since a cleanup is entered from the middle of a block
+    let mut is_cleanup = vec![false; frame.blocks().len()];
+
+    for block in frame.blocks() {
+        for mir in block.instructions.iter() {
+            if let Mir::PushLandingPad { cleanup } = *mir {
+                is_cleanup[cleanup] = true;
+            }
+        }
+    }
+
+    for (reg, def_blocks) in def_blocks.into_iter().enumerate() {
+        // a register that is only written in one block is assigned in a block that
+        // dominates all of its uses, any phi it would get could never be used
+        if def_blocks.len() < 2 {
+            continue;
+        }
+
+        let mut has_phi = vec![false; frame.blocks().len()];
+        let mut work = def_blocks;
+
+        while let Some(b) = work.pop() {
+            for &join in frontiers[b].iter() {
+                if has_phi[join] || is_cleanup[join] {
+                    continue;
+                }
+
+                has_phi[join] = true;
+                work.push(join);
+
+                phis[join].push(Phi {
+                    var: Reg(reg),
+                    to: Reg(reg),
+                    from: cfg
+                        .predecessors(join)
+                        .iter()
+                        .map(|&pred| (pred, None))
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    let mut renamer = Renamer {
+        cfg: &cfg,
+        doms: &doms,
+        names: vec![Vec::new(); max_reg_count],
+        phis,
+        next_reg: 0,
+        ret,
+        new_ret: None,
+    };
+
+    if !frame.blocks().is_empty() {
+        renamer.rename(frame.blocks_mut(), 0);
+    }
+
+    let Renamer {
+        phis,
+        next_reg,
+        mut new_ret,
+        ..
+    } = renamer;
+
+    for (block, phis) in frame.blocks_mut().iter_mut().zip(prune(phis)) {
+        block.instructions.splice(0..0, phis);
+    }
+
+    compact(frame, next_reg, &mut new_ret);
+
+    // the return register may never be assigned if the function can't return
+    ret.map(|_| {
+        new_ret.unwrap_or_else(|| {
+            frame.meta.max_reg_count += 1;
+            Reg(frame.meta.max_reg_count - 1)
+        })
+    })
+}
+
+/// renumber the registers of `frame` so there are no gaps between them, the pruned phis
+/// and the code other passes remove leave behind registers which are never written,
+/// and so would never get a type
+pub(crate) fn compact(frame: &mut StackFrame, max_reg_count: usize, ret: &mut Option<Reg>) {
+    let mut names = vec![None; max_reg_count];
+    let mut next_reg = 0;
+
+    let mut rename = |reg: &mut Reg| {
+        *reg = *names[reg.0].get_or_insert_with(|| {
+            next_reg += 1;
+            Reg(next_reg - 1)
+        });
+    };
+
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            mir.uses_mut().into_iter().for_each(&mut rename);
+
+            if let Some(reg) = mir.def_mut() {
+                rename(reg);
+            }
+        }
+
+        let terminator = &mut block.terminator;
+        terminator.uses_mut().into_iter().for_each(&mut rename);
+
+        if let Some(reg) = terminator.def_mut() {
+            rename(reg);
+        }
+    }
+
+    if let Some(ret) = ret {
+        rename(ret);
+    }
+
+    frame.meta.max_reg_count = next_reg;
+}
+
+/// remove every phi which reads a register that isn't assigned on some path, and every
+/// phi which reads such a phi, and turn the rest into instructions
+fn prune(phis: Vec<Vec<Phi>>) -> Vec<Vec<Mir>> {
+    let mut removed = Vec::new();
+    let mut keep = phis
+        .iter()
+        .map(|phis| vec![true; phis.len()])
+        .collect::<Vec<_>>();
+
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for (b, phis) in phis.iter().enumerate() {
+            for (i, phi) in phis.iter().enumerate() {
+                let is_dead = phi.from.iter().any(|&(_, from)| match from {
+                    Some(from) => removed.contains(&from),
+                    None => true,
+                });
+
+                if keep[b][i] && is_dead {
+                    keep[b][i] = false;
+                    removed.push(phi.to);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    phis.into_iter()
+        .zip(keep)
+        .map(|(phis, keep)| {
+            phis.into_iter()
+                .zip(keep)
+                .filter(|&(_, keep)| keep)
+                .map(|(phi, _)| Mir::Phi {
+                    to: phi.to,
+                    from: phi
+                        .from
+                        .into_iter()
+                        .map(|(block, from)| (block, from.unwrap()))
+                        .collect(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl Renamer<'_> {
+    fn new_name(&mut self, reg: &mut Reg, pushed: &mut Vec<Reg>) {
+        let name = Reg(self.next_reg);
+        self.next_reg += 1;
+
+        self.names[reg.0].push(name);
+        pushed.push(*reg);
+        *reg = name;
+    }
+
+    fn current_name(&self, reg: Reg) -> Option<Reg> {
+        self.names[reg.0].last().copied()
+    }
+
+    fn rename_use(&self, reg: &mut Reg) {
+        *reg = self
+            .current_name(*reg)
+            .expect("registers are assigned before they are used");
+    }
+
+    /// rename the registers of block `b` and of all the blocks it dominates
+    fn rename(&mut self, blocks: &mut [Block], b: usize) {
+        // the registers given a new name in this block, they go out of scope
+        // once all blocks dominated by this one are renamed
+        let mut pushed = Vec::new();
+
+        for i in 0..self.phis[b].len() {
+            let mut to = self.phis[b][i].var;
+            self.new_name(&mut to, &mut pushed);
+            self.phis[b][i].to = to;
+        }
+
+        let block = &mut blocks[b];
+
+        for mir in block.instructions.iter_mut() {
+            for reg in mir.uses_mut() {
+                self.rename_use(reg);
+            }
+
+            if let Some(reg) = mir.def_mut() {
+                self.new_name(reg, &mut pushed);
+            }
+        }
+
+        for reg in block.terminator.uses_mut() {
+            self.rename_use(reg);
+        }
+
+        if let Some(reg) = block.terminator.def_mut() {
+            self.new_name(reg, &mut pushed);
+        }
+
+        if let (Some(ret), Terminator::Return) = (self.ret, &block.terminator) {
+            self.new_ret = self.current_name(ret);
+        }
+
+        let (cfg, doms) = (self.cfg, self.doms);
+
+        for &succ in cfg.successors(b) {
+            for i in 0..self.phis[succ].len() {
+                let name = self.current_name(self.phis[succ][i].var);
+
+                for (source, from) in self.phis[succ][i].from.iter_mut() {
+                    if *source == b {
+                        *from = name;
+                    }
+                }
+            }
+        }
+
+        for &child in doms.children(b) {
+            self.rename(blocks, child);
+        }
+
+        for reg in pushed {
+            self.names[reg.0].pop();
+        }
+    }
+}
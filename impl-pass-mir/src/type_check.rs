@@ -1,7 +1,7 @@
-use core_mir::{BinOpType, Load, Mir, Reg};
+use core_mir::{BinOpType, CastKind, Load, Reg, Terminator};
 use core_types::{Primitive, Ty, Type, Variant};
 
-use core_tokens::Ident;
+use core_tokens::{Ident, Span};
 use lib_arena::cache::Cache;
 use lib_intern::Interner;
 
@@ -9,22 +9,43 @@ use super::*;
 
 use vec_utils::VecExt;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::ops::{Index, IndexMut};
 use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
 
 static FUNC_ID: AtomicU64 = AtomicU64::new(0);
 
+#[derive(Clone, Copy)]
 pub struct Context<'idt, 'tcx> {
     pub ident: &'idt Interner,
     pub ty: &'tcx Cache<Type<'idt, 'tcx>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct InfIdx(usize);
+/// the registers of the frames that a frame encoded with `encode::write_continued`
+/// continues, which it can read without assigning them first
+#[derive(Default)]
+pub struct Known<'f, 'idt, 'tcx> {
+    pub types: Vec<Ty<'idt, 'tcx>>,
+    /// the function held by each register, and the return register of its frame
+    pub functions: HashMap<Reg, (&'f StackFrame, Reg)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub message: String,
+    /// the location of the instruction or block the error was found in
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct ProjectIdx(usize);
+struct InfIdx(usize);
 
 impl From<Reg> for InfIdx {
     fn from(Reg(reg): Reg) -> Self {
@@ -33,21 +54,18 @@ impl From<Reg> for InfIdx {
 }
 
 struct InferenceVariables<'idt, 'tcx>(Vec<Infer<'idt, 'tcx>>);
-struct ProjectedVariables<'idt>(Vec<Project<'idt>>);
 
-impl InferenceVariables<'_, '_> {
-    pub fn inf(&mut self) -> InfIdx {
-        let idx = InfIdx(self.0.len());
-        self.0.push(Infer::Inf(idx));
-        idx
-    }
-}
+impl<'idt, 'tcx> InferenceVariables<'idt, 'tcx> {
+    /// follow the chain of inference variables starting at `idx`
+    fn resolve(&self, mut idx: InfIdx) -> Infer<'idt, 'tcx> {
+        for _ in 0..self.0.len() {
+            match self[idx] {
+                Infer::Inf(other) if other != idx => idx = other,
+                ty => return ty,
+            }
+        }
 
-impl<'idt> ProjectedVariables<'idt> {
-    pub fn push(&mut self, project: Project<'idt>) -> ProjectIdx {
-        let idx = ProjectIdx(self.0.len());
-        self.0.push(project);
-        idx
+        self[idx]
     }
 }
 
@@ -79,67 +97,132 @@ impl<'idt, 'tcx> IndexMut<Reg> for InferenceVariables<'idt, 'tcx> {
     }
 }
 
-impl<'idt> Index<ProjectIdx> for ProjectedVariables<'idt> {
-    type Output = Project<'idt>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Infer<'idt, 'tcx> {
+    Concrete(Ty<'idt, 'tcx>),
+    Inf(InfIdx),
+}
 
-    fn index(&self, ProjectIdx(idx): ProjectIdx) -> &Self::Output {
-        &self.0[idx]
+impl Infer<'_, '_> {
+    fn is_inference(&self) -> bool {
+        match *self {
+            Self::Inf(_) => true,
+            _ => false,
+        }
     }
 }
 
-impl<'idt> IndexMut<ProjectIdx> for ProjectedVariables<'idt> {
-    fn index_mut(&mut self, ProjectIdx(idx): ProjectIdx) -> &mut Self::Output {
-        &mut self.0[idx]
+impl fmt::Display for Infer<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Concrete(ty) => write!(f, "`{}`", ty.name),
+            Self::Inf(_) => f.write_str("an unknown type"),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Infer<'idt, 'tcx> {
-    Concrete(Ty<'idt, 'tcx>),
-    Inf(InfIdx),
-    Project(ProjectIdx),
+/// the span of `mir`, or of `block` if `mir` doesn't have one
+fn span_of(mir: &Mir, block: &Block) -> Option<Span> {
+    match *mir {
+        Mir::BinOp { span, .. } | Mir::PreOp { span, .. } | Mir::Panic { span, .. } => Some(span),
+        _ => block.meta.span,
+    }
 }
 
-struct Project<'idt> {
-    inf: InfIdx,
-    ty: ProjectTy<'idt>,
+/// the span of the first instruction or terminator that mentions `reg`
+fn first_mention(frame: &StackFrame, reg: Reg) -> Option<Span> {
+    frame.blocks().iter().find_map(|block| {
+        let mir = block
+            .instructions
+            .iter()
+            .find(|mir| mir.def() == Some(reg) || mir.uses().contains(&reg));
+
+        match mir {
+            Some(mir) => span_of(mir, block),
+            None if block.terminator.def() == Some(reg)
+                || block.terminator.uses().contains(&reg) =>
+            {
+                block.meta.span
+            }
+            None => None,
+        }
+    })
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum ProjectTy<'idt> {
-    ReturnType(InfIdx),
-    Function {
-        name: Ident<'idt>,
-        captures: BTreeMap<Ident<'idt>, InfIdx>,
-        return_type: InfIdx,
-    },
+/// infer the types of every register in `frame`, and resolve the kind of every cast
+pub fn infer_types<'tcx, 'idt>(
+    frame: &mut StackFrame,
+    ctx: Context<'idt, 'tcx>,
+) -> Result<Vec<Ty<'idt, 'tcx>>, Error> {
+    infer_continued(frame, &Known::default(), ctx)
 }
 
-impl Infer<'_, '_> {
-    fn is_inference(&self) -> bool {
-        match *self {
-            Self::Inf(_) | Self::Project(..) => true,
-            _ => false,
+/// like `infer_types`, for a frame that continues the frames whose registers are `known`
+pub fn infer_continued<'tcx, 'idt>(
+    frame: &mut StackFrame,
+    known: &Known<'_, 'idt, 'tcx>,
+    ctx: Context<'idt, 'tcx>,
+) -> Result<Vec<Ty<'idt, 'tcx>>, Error> {
+    let types = infer(frame, &[], known, ctx)?;
+
+    for block in frame.blocks_mut() {
+        for mir in block.instructions.iter_mut() {
+            if let Mir::Cast {
+                from,
+                ty,
+                ref mut kind,
+                ..
+            } = *mir
+            {
+                if let Variant::Primitive(from_ty) = types[from.0].ty {
+                    *kind = CastKind::new(from_ty, ty).expect("casts are checked by `infer_call`");
+                }
+            }
         }
     }
+
+    Ok(types)
 }
 
-pub fn infer_types<'tcx, 'idt>(
+/// infer the types of every register in the frame of a function called with `arguments`
+///
+/// functions are generic over their arguments, so the types of nested frames
+/// depend on the call site, and their casts are left unresolved
+pub fn infer_call<'tcx, 'idt>(
     frame: &StackFrame,
+    arguments: &[Ty<'idt, 'tcx>],
     ctx: Context<'idt, 'tcx>,
-) -> Option<Vec<Ty<'idt, 'tcx>>> {
+) -> Result<Vec<Ty<'idt, 'tcx>>, Error> {
+    infer(frame, arguments, &Known::default(), ctx)
+}
+
+fn infer<'f, 'tcx, 'idt>(
+    frame: &'f StackFrame,
+    arguments: &[Ty<'idt, 'tcx>],
+    known: &Known<'f, 'idt, 'tcx>,
+    ctx: Context<'idt, 'tcx>,
+) -> Result<Vec<Ty<'idt, 'tcx>>, Error> {
     let types = (0..frame.meta.max_reg_count)
-        .map(InfIdx)
-        .map(Infer::Inf)
+        .map(|reg| match known.types.get(reg) {
+            Some(&ty) => Infer::Concrete(ty),
+            None => Infer::Inf(InfIdx(reg)),
+        })
         .collect::<Vec<_>>();
 
     let mut types = InferenceVariables(types);
-    let mut projections = ProjectedVariables(Vec::new());
+
+    // the functions declared in this frame and the frames it continues,
+    // and the registers that hold them
+    let mut functions = known.functions.clone();
+    let mut next_argument = 0;
+
+    // the span of the instruction or terminator being checked, errors are reported there
+    let mut span: Option<Span>;
 
     macro_rules! debug {
         ($($rest:tt)*) => {
-            if true {
-                println!("DEBUG: {}", format_args!($($rest)*))
+            if false {
+                eprintln!("DEBUG: {}", format_args!($($rest)*))
             }
         };
     }
@@ -187,18 +270,27 @@ pub fn infer_types<'tcx, 'idt>(
         }
     }
 
+    let prim_ty = |prim: Primitive| {
+        ctx.ty.insert(
+            Type::new(
+                core_tokens::Ident::new(ctx.ident.insert(prim.name())),
+                Variant::Primitive(prim),
+            )
+            .with_size(prim.size())
+            .align_to(prim.size()),
+        )
+    };
+
     macro_rules! write_type {
         ($reg:ident <- $ty:expr) => {{
             let rty = &mut types[$reg];
             if rty.is_inference() || *rty == $ty || $ty.is_inference() && !rty.is_inference() {
                 *rty = $ty;
             } else {
-                eprintln!(
-                    "TypeError ({}), found type: {:?}, expected {:?}",
-                    $reg.0, rty, $ty,
-                );
-
-                return None;
+                return Err(Error {
+                    message: format!("mismatched types, expected {}, found {}", $ty, rty),
+                    span,
+                });
             }
         }};
         ($a:ident == $b:ident) => {{
@@ -218,9 +310,10 @@ pub fn infer_types<'tcx, 'idt>(
                     (_, true) => *b = a.clone(),
                     (false, false) => {
                         if a != b {
-                            eprintln!("TypeError ({}), found type: {:?}, expected {:?}", $a, b, a,);
-
-                            return None;
+                            return Err(Error {
+                                message: format!("mismatched types, expected {}, found {}", a, b),
+                                span,
+                            });
                         }
                     }
                 }
@@ -230,62 +323,73 @@ pub fn infer_types<'tcx, 'idt>(
 
     for block in frame.blocks().iter() {
         for mir in block.instructions.iter() {
-            match *mir {
-                Mir::CallFunction | Mir::Jump(_) | Mir::Print(_) => {
-                    // no types can be gleaned from a print/jump
-                }
-                Mir::BranchTrue { cond, .. } => {
-                    // cond must be a bool
+            span = span_of(mir, block);
 
-                    debug!("{} <- bool (branch condition)", cond);
-                    write_type!(cond <- Infer::Concrete(bool_ty));
+            match *mir {
+                Mir::Print(_)
+                | Mir::Panic { .. }
+                | Mir::PushLandingPad { .. }
+                | Mir::PopLandingPad => {
+                    // no types can be gleaned from a print/panic
                 }
                 Mir::Load { to, from } => match from {
                     Load::Bool(_) => write_type!(to <- Infer::Concrete(bool_ty)),
-                    Load::U8(_) | Load::U16(_) => {
+                    Load::U8(_) | Load::U16(_) | Load::I32(_) => {
                         debug!("{} <- i32 (load immediate)", to);
                         write_type!(to <- Infer::Concrete(i32_ty))
                     }
                     _ => {
-                        eprintln!(
-                            "TypeError ({}), found type: {{large integer}}, expected {:?}",
-                            to,
-                            Infer::Concrete(i32_ty),
-                        );
-
-                        return None;
+                        return Err(Error {
+                            message: format!(
+                                "mismatched types, expected {}, found a larger integer",
+                                Infer::Concrete(i32_ty),
+                            ),
+                            span,
+                        })
                     }
                 },
                 Mir::LoadReg { to, from } => {
+                    // a copy of a function can be called like the function
+                    if let Some(&function) = functions.get(&from) {
+                        functions.insert(to, function);
+                    }
+
                     debug!("{} == {} (load register)", to, from);
                     write_type!(to == from);
                 }
+                Mir::Phi { to, ref from } => {
+                    // the sources along back edges are only written later, so they are
+                    // linked to `to` instead of the other way around
+                    for &(_, from) in from.iter() {
+                        debug!("{} == {} (phi)", from, to);
+                        write_type!(from == to);
+                    }
+                }
                 Mir::BinOp {
                     op,
                     out,
                     left,
                     right,
+                    ..
                 } => match op {
                     BinOpType::Add | BinOpType::Sub | BinOpType::Mul | BinOpType::Div => {
-                        write_type!(out <- Infer::Concrete(i32_ty));
-                        write_type!(left <- Infer::Concrete(i32_ty));
-                        write_type!(right <- Infer::Concrete(i32_ty));
+                        // the operands must be numeric, this is checked
+                        // once all types are resolved
+                        write_type!(left == right);
+                        write_type!(out == left);
 
-                        debug!("{} <- i32 (arith)", out);
-                        debug!("{} <- i32 (arith)", left);
-                        debug!("{} <- i32 (arith)", right);
+                        debug!("{} == {} (arith)", left, right);
+                        debug!("{} == {} (arith)", out, left);
                     }
                     BinOpType::LessThan
                     | BinOpType::GreaterThan
                     | BinOpType::LessThanOrEqual
                     | BinOpType::GreaterThanOrEqual => {
                         write_type!(out <- Infer::Concrete(bool_ty));
-                        write_type!(left <- Infer::Concrete(i32_ty));
-                        write_type!(right <- Infer::Concrete(i32_ty));
+                        write_type!(left == right);
 
                         debug!("{} <- bool (comp)", out);
-                        debug!("{} <- i32 (comp)", left);
-                        debug!("{} <- i32 (comp)", right);
+                        debug!("{} == {} (comp)", left, right);
                     }
                     BinOpType::Equal | BinOpType::NotEqual => {
                         write_type!(out <- Infer::Concrete(bool_ty));
@@ -296,6 +400,12 @@ pub fn infer_types<'tcx, 'idt>(
                     }
                 },
                 Mir::PreOp { .. } => {}
+                Mir::Cast { to, ty, .. } => {
+                    debug!("{} <- {} (cast)", to, ty.name());
+
+                    let ty = Infer::Concrete(prim_ty(ty));
+                    write_type!(to <- ty);
+                }
                 Mir::CreateFunc {
                     binding,
                     ret,
@@ -307,38 +417,117 @@ pub fn infer_types<'tcx, 'idt>(
                         panic!("tried to create too many functions!")
                     }
 
-                    let ret = types.inf();
-
-                    let func_proj = projections.push(Project {
-                        inf: InfIdx::from(binding),
-                        ty: ProjectTy::Function {
-                            name: Ident::new(ctx.ident.insert(&format!("$fn({})", id))),
+                    // functions can't capture anything, so they don't need any space,
+                    // the argument and return types depend on the call site
+                    let func_ty = ctx.ty.insert(Type::new(
+                        Ident::new(ctx.ident.insert(&format!("$fn({})", id))),
+                        Variant::Function {
                             captures: BTreeMap::new(),
-                            return_type: ret,
+                            arguments: Vec::new(),
+                            return_type: unit,
                         },
-                    });
+                    ));
 
-                    debug!("{} <- fn (function declaration)", func_proj.0);
-                    debug!("{} <- () (unit return type)", ret.0);
+                    debug!("{} <- $fn({}) (function declaration)", binding, id);
 
-                    write_type!(binding <- Infer::Project(func_proj));
-                    write_type!(ret <- Infer::Concrete(unit));
+                    write_type!(binding <- Infer::Concrete(func_ty));
+                    functions.insert(binding, (stack_frame, ret));
                 }
-                Mir::LoadFunction { func, ret } => {
-                    let proj = projections.push(Project {
-                        inf: InfIdx::from(ret),
-                        ty: ProjectTy::ReturnType(InfIdx::from(func)),
-                    });
-
-                    write_type!(ret <- Infer::Project(proj));
-                    debug!("{} <- ret({}) (load fn)", ret.0, func.0);
+                Mir::PopArgument { arg } => match arguments.get(next_argument) {
+                    Some(&ty) => {
+                        next_argument += 1;
+
+                        debug!("{} <- {:?} (argument)", arg, ty.name);
+                        write_type!(arg <- Infer::Concrete(ty));
+                    }
+                    None => {
+                        return Err(Error {
+                            message: format!(
+                                "expected at least {} arguments, found {}",
+                                next_argument + 1,
+                                arguments.len()
+                            ),
+                            span,
+                        })
+                    }
+                },
+            }
+        }
+
+        span = block.meta.span;
+
+        match block.terminator {
+            Terminator::Goto(_)
+            | Terminator::Return
+            | Terminator::Unreachable
+            | Terminator::Resume => {
+                // no types can be gleaned from a jump/return
+            }
+            Terminator::Branch { cond, .. } => {
+                // cond must be a bool
+
+                debug!("{} <- bool (branch condition)", cond);
+                write_type!(cond <- Infer::Concrete(bool_ty));
+            }
+            Terminator::Switch { .. } => {
+                // the value must be an integer, this is checked
+                // once all types are resolved
+            }
+            Terminator::Call {
+                func,
+                ref args,
+                ret,
+                ..
+            } => {
+                let (callee, callee_ret) = match functions.get(&func) {
+                    Some(&function) => function,
+                    None => {
+                        return Err(Error {
+                            message: "could not find the function to call".to_string(),
+                            span,
+                        })
+                    }
+                };
+
+                let mut arguments = Vec::with_capacity(args.len());
+
+                for &arg in args {
+                    match types.resolve(InfIdx::from(arg)) {
+                        Infer::Concrete(ty) => arguments.push(ty),
+                        Infer::Inf(_) => {
+                            return Err(Error {
+                                message: "the type of an argument must be known before the call"
+                                    .to_string(),
+                                span,
+                            })
+                        }
+                    }
                 }
-                Mir::PopArgument { arg } => {}
-                Mir::PushArguement { arg } => {}
+
+                // errors without a location in the callee are reported at the call
+                let callee_types = infer_call(callee, &arguments, ctx).map_err(|error| Error {
+                    span: error.span.or(span),
+                    ..error
+                })?;
+                let ret_ty = Infer::Concrete(callee_types[callee_ret.0]);
+
+                debug!("{} <- ret({}) (call fn)", ret, func);
+                write_type!(ret <- ret_ty);
             }
         }
     }
 
+    if next_argument != arguments.len() {
+        return Err(Error {
+            message: format!(
+                "expected {} arguments, found {}",
+                next_argument,
+                arguments.len()
+            ),
+            span: None,
+        });
+    }
+
     loop {
         let mut has_changed = false;
 
@@ -347,55 +536,84 @@ pub fn infer_types<'tcx, 'idt>(
             match types[ty] {
                 Infer::Concrete(_) => (),
                 Infer::Inf(other) => {
-                    if other == ty {
-                        eprintln!("Failed to infer type of {}", ty.0);
-                    }
-
                     has_changed |= types[ty] != types[other];
                     types[ty] = types[other];
                 }
-                Infer::Project(idx) => {
-                    let proj = &projections[idx].ty;
-
-                    match *proj {
-                        ProjectTy::Function {
-                            name: _,
-                            ref captures,
-                            return_type,
-                        } => {
-                            assert!(captures.is_empty());
-
-                            todo!("register function");
-
-                            // let $ty_var = ctx.ty.insert(
-                            //     Type::new(
-                            //         core_tokens::Ident::new(ctx.ident.insert(&$name)),
-                            //         $variant,
-                            //     )
-                            //         .with_size($size)
-                            //         .align_to($align)
-                            // );
-
-                            // types[ty] = Type::/
-                        }
-                        ProjectTy::ReturnType(func) => {
-                            types[ty] = Infer::Concrete(unit);
+            }
+        }
+
+        if !has_changed {
+            break;
+        }
+    }
+
+    if let Some(reg) = types.0.iter().position(Infer::is_inference) {
+        return Err(Error {
+            message: "could not infer the type of this value".to_string(),
+            span: first_mention(frame, Reg(reg)),
+        });
+    }
+
+    let types = types.0.map(|x| match x {
+        Infer::Concrete(t) => t,
+        Infer::Inf(_) => unreachable!("unresolved types are reported above"),
+    });
 
-                            // if let Infer::Concrete(ty) = types[func] {}
+    let primitive = |reg: Reg| match types[reg.0].ty {
+        Variant::Primitive(prim) => Some(prim),
+        _ => None,
+    };
+
+    for block in frame.blocks() {
+        for mir in block.instructions.iter() {
+            match *mir {
+                Mir::BinOp { op, left, .. } => match op {
+                    BinOpType::Equal | BinOpType::NotEqual => (),
+                    _ => {
+                        if !primitive(left).map_or(false, Primitive::is_numeric) {
+                            return Err(Error {
+                                message: format!(
+                                    "mismatched types, expected a numeric type, found `{}`",
+                                    types[left.0].name,
+                                ),
+                                span: span_of(mir, block),
+                            });
                         }
                     }
+                },
+                Mir::Cast { from, ty, .. } => {
+                    let from_ty = primitive(from);
+
+                    if from_ty
+                        .and_then(|from_ty| CastKind::new(from_ty, ty))
+                        .is_none()
+                    {
+                        return Err(Error {
+                            message: format!(
+                                "cannot cast `{}` as `{}`",
+                                types[from.0].name,
+                                ty.name(),
+                            ),
+                            span: span_of(mir, block),
+                        });
+                    }
                 }
+                _ => (),
             }
         }
 
-        if !has_changed {
-            break;
+        if let Terminator::Switch { value, .. } = block.terminator {
+            if !primitive(value).map_or(false, Primitive::is_integer) {
+                return Err(Error {
+                    message: format!(
+                        "mismatched types, expected an integer type, found `{}`",
+                        types[value.0].name,
+                    ),
+                    span: block.meta.span,
+                });
+            }
         }
     }
 
-    Some(types.0.map(|x| match x {
-        Infer::Concrete(t) => t,
-        Infer::Inf(i) => panic!("inference failed at: {}", i.0),
-        Infer::Project(i) => panic!("project {} not resolved", i.0),
-    }))
+    Ok(types)
 }
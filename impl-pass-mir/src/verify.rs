@@ -0,0 +1,451 @@
+//! Checks that a `StackFrame` is well formed, so that a pass that breaks the MIR
+//! is caught right after it runs instead of when the broken MIR is interpreted.
+//!
+//! A frame is well formed if
+//! * every register is below the frame's `max_reg_count`
+//! * every register is assigned on all paths before it is read
+//! * every block that is jumped to exists
+//! * `PopArgument`s only appear at the start of a function
+//! * phis only appear at the start of a block, with one source for each predecessor
+//! * the return register of a function is assigned whenever it returns
+//! * the frames of all nested functions are well formed
+//!
+//! `verify_ssa` also checks that every register is assigned only once, in every frame
+//! without landing pads, since those are never converted to SSA form, and
+//! `verify_continued` checks a frame that continues earlier ones, whose registers
+//! are already assigned when it starts
+
+use core_mir::{Reg, Terminator};
+
+use std::fmt;
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    RegOutOfBounds {
+        reg: Reg,
+        max_reg_count: usize,
+    },
+    /// a register that is read before it is assigned on some path
+    Unassigned(Reg),
+    TargetOutOfBounds(usize),
+    /// a `PopArgument` in the top level frame, or after any other instruction
+    MisplacedPopArgument,
+    /// the return register of a function is not assigned when it returns
+    UnassignedReturn(Reg),
+    /// a phi after any other instruction
+    MisplacedPhi,
+    /// the blocks of a phi are not the predecessors of its block
+    PhiPredecessors,
+    /// a register that is assigned more than once in SSA form
+    Reassigned(Reg),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// the block and instruction of each `CreateFunc` leading to the frame with the error
+    pub path: Vec<(usize, usize)>,
+    pub block: usize,
+    /// `None` if the error is in the terminator of the block
+    pub instruction: Option<usize>,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RegOutOfBounds { reg, max_reg_count } => write!(
+                f,
+                "{} is out of bounds, the frame has {} registers",
+                reg, max_reg_count
+            ),
+            Self::Unassigned(reg) => write!(f, "{} is used before it is assigned", reg),
+            Self::TargetOutOfBounds(target) => write!(f, "block {} doesn't exist", target),
+            Self::MisplacedPopArgument => {
+                write!(f, "arguments can only be popped at the start of a function")
+            }
+            Self::UnassignedReturn(reg) => write!(
+                f,
+                "the return register {} is not assigned when the function returns",
+                reg
+            ),
+            Self::MisplacedPhi => write!(f, "phis can only be at the start of a block"),
+            Self::PhiPredecessors => write!(
+                f,
+                "a phi must have exactly one source for each predecessor of its block"
+            ),
+            Self::Reassigned(reg) => write!(f, "{} is assigned more than once", reg),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &(block, instruction) in self.path.iter() {
+            write!(
+                f,
+                "in the function created at block {}, instruction {}: ",
+                block, instruction
+            )?;
+        }
+
+        match self.instruction {
+            Some(instruction) => write!(f, "block {}, instruction {}: ", self.block, instruction)?,
+            None => write!(f, "block {}, terminator: ", self.block)?,
+        }
+
+        self.kind.fmt(f)
+    }
+}
+
+/// check that `frame` and all of its nested frames are well formed
+pub fn verify(frame: &StackFrame) -> Result<(), Vec<Error>> {
+    run(frame, false, 0)
+}
+
+/// check that `frame` and all of its nested frames are well formed and in SSA form
+pub fn verify_ssa(frame: &StackFrame) -> Result<(), Vec<Error>> {
+    run(frame, true, 0)
+}
+
+/// like `verify`, but the registers below `reg_count` are assigned by earlier frames
+/// before `frame` starts, see `encode::write_continued`
+pub fn verify_continued(frame: &StackFrame, reg_count: usize) -> Result<(), Vec<Error>> {
+    run(frame, false, reg_count)
+}
+
+fn run(frame: &StackFrame, ssa: bool, reg_count: usize) -> Result<(), Vec<Error>> {
+    let mut verifier = Verifier {
+        path: Vec::new(),
+        errors: Vec::new(),
+        ssa,
+        reg_count,
+    };
+
+    verifier.frame(frame, None);
+
+    if verifier.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(verifier.errors)
+    }
+}
+
+struct Verifier {
+    path: Vec<(usize, usize)>,
+    errors: Vec<Error>,
+    ssa: bool,
+    /// the registers assigned before the top level frame starts
+    reg_count: usize,
+}
+
+impl Verifier {
+    fn error(&mut self, block: usize, instruction: Option<usize>, kind: ErrorKind) {
+        self.errors.push(Error {
+            path: self.path.clone(),
+            block,
+            instruction,
+            kind,
+        });
+    }
+
+    /// `ret` is the return register if `frame` is the frame of a function
+    fn frame(&mut self, frame: &StackFrame, ret: Option<Reg>) {
+        let blocks = frame.blocks();
+        let max_reg_count = frame.meta.max_reg_count;
+
+        // `StackFrame::predecessors` can't be used, the targets may be out of bounds
+        let mut predecessors = vec![Vec::new(); blocks.len()];
+
+        for (from, block) in blocks.iter().enumerate() {
+            for to in block.terminator.successors() {
+                if to < blocks.len() && !predecessors[to].contains(&from) {
+                    predecessors[to].push(from);
+                }
+            }
+        }
+
+        for (b, block) in blocks.iter().enumerate() {
+            self.instructions(b, block, &predecessors, max_reg_count, ret.is_some());
+        }
+
+        // functions can't capture anything, so only the top level frame continues earlier ones
+        let assigned_before = if self.path.is_empty() {
+            self.reg_count
+        } else {
+            0
+        };
+
+        self.assignments(blocks, max_reg_count, assigned_before, ret);
+
+        if self.ssa {
+            self.single_assignments(blocks, max_reg_count);
+        }
+    }
+
+    /// check that no register is assigned more than once
+    fn single_assignments(&mut self, blocks: &[Block], max_reg_count: usize) {
+        let has_landing_pads = blocks.iter().any(|block| {
+            block.instructions.iter().any(|mir| match *mir {
+                Mir::PushLandingPad { .. } => true,
+                _ => false,
+            })
+        });
+
+        if has_landing_pads {
+            return;
+        }
+
+        let mut assigned = vec![false; max_reg_count];
+
+        for (b, block) in blocks.iter().enumerate() {
+            let defs = block
+                .instructions
+                .iter()
+                .map(Mir::def)
+                .enumerate()
+                .map(|(i, def)| (Some(i), def))
+                .chain(std::iter::once((None, block.terminator.def())));
+
+            for (i, def) in defs {
+                let reg = match def {
+                    Some(reg) if reg.0 < max_reg_count => reg,
+                    _ => continue,
+                };
+
+                if assigned[reg.0] {
+                    self.error(b, i, ErrorKind::Reassigned(reg));
+                }
+
+                assigned[reg.0] = true;
+            }
+        }
+    }
+
+    fn regs(&mut self, b: usize, i: Option<usize>, regs: Vec<Reg>, max_reg_count: usize) {
+        for reg in regs {
+            if reg.0 >= max_reg_count {
+                self.error(b, i, ErrorKind::RegOutOfBounds { reg, max_reg_count });
+            }
+        }
+    }
+
+    /// check each instruction of a block on its own
+    fn instructions(
+        &mut self,
+        b: usize,
+        block: &Block,
+        predecessors: &[Vec<usize>],
+        max_reg_count: usize,
+        is_function: bool,
+    ) {
+        let block_count = predecessors.len();
+        let mut popping_arguments = is_function && b == 0;
+        let mut in_phis = true;
+
+        for (i, mir) in block.instructions.iter().enumerate() {
+            let regs = mir.uses().into_iter().chain(mir.def()).collect();
+            self.regs(b, Some(i), regs, max_reg_count);
+
+            match *mir {
+                Mir::PushLandingPad { cleanup } if cleanup >= block_count => {
+                    self.error(b, Some(i), ErrorKind::TargetOutOfBounds(cleanup))
+                }
+                Mir::PopArgument { .. } if !popping_arguments => {
+                    self.error(b, Some(i), ErrorKind::MisplacedPopArgument)
+                }
+                Mir::Phi { .. } if !in_phis => self.error(b, Some(i), ErrorKind::MisplacedPhi),
+                Mir::Phi { ref from, .. } => {
+                    let mut sources = from.iter().map(|&(source, _)| source).collect::<Vec<_>>();
+                    let mut expected = predecessors[b].clone();
+                    sources.sort_unstable();
+                    expected.sort_unstable();
+
+                    match sources.iter().find(|&&source| source >= block_count) {
+                        Some(&source) => {
+                            self.error(b, Some(i), ErrorKind::TargetOutOfBounds(source))
+                        }
+                        None if sources != expected => {
+                            self.error(b, Some(i), ErrorKind::PhiPredecessors)
+                        }
+                        None => (),
+                    }
+                }
+                Mir::CreateFunc {
+                    ret,
+                    ref stack_frame,
+                    ..
+                } => {
+                    self.path.push((b, i));
+                    self.frame(stack_frame, Some(ret));
+                    self.path.pop();
+                }
+                _ => (),
+            }
+
+            if let Mir::PopArgument { .. } = *mir {
+            } else {
+                popping_arguments = false;
+            }
+
+            if let Mir::Phi { .. } = *mir {
+            } else {
+                in_phis = false;
+            }
+        }
+
+        let terminator = &block.terminator;
+        let regs = terminator
+            .uses()
+            .into_iter()
+            .chain(terminator.def())
+            .collect();
+        self.regs(b, None, regs, max_reg_count);
+
+        for target in terminator.successors() {
+            if target >= block_count {
+                self.error(b, None, ErrorKind::TargetOutOfBounds(target));
+            }
+        }
+    }
+
+    /// check that every register is assigned before it is read, on every path through the frame
+    ///
+    /// the registers below `assigned_before` are assigned before the frame starts
+    fn assignments(
+        &mut self,
+        blocks: &[Block],
+        max_reg_count: usize,
+        assigned_before: usize,
+        ret: Option<Reg>,
+    ) {
+        if blocks.is_empty() {
+            return;
+        }
+
+        let successors = |b: usize| {
+            blocks[b]
+                .terminator
+                .successors()
+                .into_iter()
+                .filter(|&target| target < blocks.len())
+        };
+
+        let assign = |assigned: &mut Vec<bool>, reg: Option<Reg>| {
+            if let Some(assigned) = reg.and_then(|reg| assigned.get_mut(reg.0)) {
+                *assigned = true;
+            }
+        };
+
+        // the registers that are assigned on every path to the start of each block,
+        // `None` for blocks that can't be reached
+        let mut assigned_at_start = vec![None; blocks.len()];
+        assigned_at_start[0] = Some(
+            (0..max_reg_count)
+                .map(|reg| reg < assigned_before)
+                .collect::<Vec<_>>(),
+        );
+
+        let mut work = vec![0];
+
+        while let Some(b) = work.pop() {
+            let mut assigned = assigned_at_start[b].clone().unwrap();
+
+            for mir in blocks[b].instructions.iter() {
+                assign(&mut assigned, mir.def());
+            }
+
+            assign(&mut assigned, blocks[b].terminator.def());
+
+            for child in successors(b) {
+                let changed = match assigned_at_start[child] {
+                    Some(ref mut start) => {
+                        let mut changed = false;
+
+                        for (start, &assigned) in start.iter_mut().zip(assigned.iter()) {
+                            if *start && !assigned {
+                                *start = false;
+                                changed = true;
+                            }
+                        }
+
+                        changed
+                    }
+                    None => {
+                        assigned_at_start[child] = Some(assigned.clone());
+                        true
+                    }
+                };
+
+                if changed {
+                    work.push(child);
+                }
+            }
+        }
+
+        let is_assigned =
+            |assigned: &[bool], reg: Reg| assigned.get(reg.0).copied().unwrap_or(false);
+
+        // the sources of a phi are read at the end of the blocks they come from
+        let assigned_at_end = blocks
+            .iter()
+            .zip(assigned_at_start.iter())
+            .map(|(block, start)| {
+                let mut assigned = start.clone()?;
+
+                for mir in block.instructions.iter() {
+                    assign(&mut assigned, mir.def());
+                }
+
+                assign(&mut assigned, block.terminator.def());
+                Some(assigned)
+            })
+            .collect::<Vec<_>>();
+
+        for (b, block) in blocks.iter().enumerate() {
+            let mut assigned = match assigned_at_start[b] {
+                Some(ref assigned) => assigned.clone(),
+                None => continue,
+            };
+
+            for (i, mir) in block.instructions.iter().enumerate() {
+                if let Mir::Phi { ref from, .. } = *mir {
+                    for &(source, reg) in from.iter() {
+                        let at_end = assigned_at_end.get(source).and_then(Option::as_ref);
+
+                        if let Some(at_end) = at_end {
+                            if reg.0 < max_reg_count && !is_assigned(at_end, reg) {
+                                self.error(b, Some(i), ErrorKind::Unassigned(reg));
+                            }
+                        }
+                    }
+
+                    assign(&mut assigned, mir.def());
+                    continue;
+                }
+
+                for reg in mir.uses() {
+                    // out of bounds registers were already reported
+                    if reg.0 < max_reg_count && !is_assigned(&assigned, reg) {
+                        self.error(b, Some(i), ErrorKind::Unassigned(reg));
+                    }
+                }
+
+                assign(&mut assigned, mir.def());
+            }
+
+            for reg in block.terminator.uses() {
+                if reg.0 < max_reg_count && !is_assigned(&assigned, reg) {
+                    self.error(b, None, ErrorKind::Unassigned(reg));
+                }
+            }
+
+            if let (Some(ret), Terminator::Return) = (ret, &block.terminator) {
+                if !is_assigned(&assigned, ret) {
+                    self.error(b, None, ErrorKind::UnassignedReturn(ret));
+                }
+            }
+        }
+    }
+}
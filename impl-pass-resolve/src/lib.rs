@@ -0,0 +1,378 @@
+//! Name resolution, which links every use of a name to the binding it refers to.
+//!
+//! Names are resolved the same way the encoder resolves them: the value of a `let`
+//! is resolved before its name is bound, so `let a = a + 1;` refers to an earlier `a`,
+//! assignments must refer to a binding that is already in scope, scopes can see the
+//! bindings of the scopes around them, and functions can only see their own parameters.
+//!
+//! The encoder gives each `DefId` its own register, and finds the binding of every name
+//! it lowers with `def_at_span` and `use_at_span`, so it doesn't resolve names itself.
+//!
+//! ```ignore
+//! let resolution = impl_pass_resolve::resolve(&hir);
+//!
+//! for use_ in resolution.uses_of(def) {
+//!     rename(use_.span);
+//! }
+//! ```
+
+use core_hir::visit::{walk_expr, walk_hir, walk_scope, Visit};
+use core_hir::{Expr, Hir, Node, Pattern, SimpleExpr};
+use core_tokens::{Ident, Span};
+
+use std::collections::HashMap;
+
+/// a binding, unique within a whole program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefId(pub usize);
+
+/// a function, the top level of the program is `FuncId(0)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FuncId(pub usize);
+
+/// a binding, unique within the function that defines it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocalId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Let,
+    /// a `let` whose value is a function
+    Function,
+    Parameter,
+    /// a top level binding of an earlier program, see `resolve_continued`
+    Global,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Def<'idt> {
+    pub name: Ident<'idt>,
+    pub kind: DefKind,
+    /// the span of the name, globals were bound in another source so their span is empty
+    pub span: Span,
+    /// the span of the whole `let` statement, or the name of a parameter
+    pub statement: Span,
+    pub func: FuncId,
+    pub local: LocalId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UseKind {
+    Read,
+    /// the target of an assignment
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Use {
+    /// the span of the name
+    pub span: Span,
+    pub def: DefId,
+    pub kind: UseKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Problem<'idt> {
+    /// a name that isn't bound in any scope that can see it
+    Unresolved {
+        name: Ident<'idt>,
+        span: Span,
+        /// the name is bound outside of the enclosing function, but functions can't capture
+        captured: bool,
+    },
+    /// `def` hides `shadowed`, an earlier binding of the same name
+    Shadowed { def: DefId, shadowed: DefId },
+    /// `def` is never read, names starting with `_` are never reported
+    Unused { def: DefId },
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution<'idt> {
+    /// every binding, indexed by `DefId`
+    pub defs: Vec<Def<'idt>>,
+    /// every use of a binding, in the order they appear in the source
+    pub uses: Vec<Use>,
+    /// sorted by where they appear in the source
+    pub problems: Vec<Problem<'idt>>,
+    /// the bindings of the top level scope at the end of the program
+    pub top_level: HashMap<Ident<'idt>, DefId>,
+    defs_by_span: HashMap<Span, DefId>,
+    uses_by_span: HashMap<Span, DefId>,
+}
+
+impl<'idt> Resolution<'idt> {
+    pub fn def(&self, def: DefId) -> &Def<'idt> {
+        &self.defs[def.0]
+    }
+
+    /// the binding of the name at `offset`, whether `offset` is on a use or on the binding itself
+    pub fn def_at(&self, offset: usize) -> Option<DefId> {
+        let contains = |span: Span| span.start() <= offset && offset <= span.end();
+
+        self.uses
+            .iter()
+            .find(|use_| contains(use_.span))
+            .map(|use_| use_.def)
+            .or_else(|| {
+                self.defs
+                    .iter()
+                    .position(|def| def.kind != DefKind::Global && contains(def.span))
+                    .map(DefId)
+            })
+    }
+
+    /// the binding whose name is at exactly `span`
+    pub fn def_at_span(&self, span: Span) -> Option<DefId> {
+        self.defs_by_span.get(&span).copied()
+    }
+
+    /// the binding used by the name at exactly `span`, `None` if it is unresolved
+    pub fn use_at_span(&self, span: Span) -> Option<DefId> {
+        self.uses_by_span.get(&span).copied()
+    }
+
+    pub fn uses_of(&self, def: DefId) -> impl Iterator<Item = &Use> {
+        self.uses.iter().filter(move |use_| use_.def == def)
+    }
+
+    /// there are unresolved names, so the program can't be lowered
+    pub fn has_errors(&self) -> bool {
+        self.problems.iter().any(|problem| match problem {
+            Problem::Unresolved { .. } => true,
+            _ => false,
+        })
+    }
+
+    /// the span a problem points to
+    pub fn span_of(&self, problem: &Problem<'idt>) -> Span {
+        match *problem {
+            Problem::Unresolved { span, .. } => span,
+            Problem::Shadowed { def, .. } | Problem::Unused { def } => self.def(def).span,
+        }
+    }
+}
+
+/// resolve every name in `hir`
+pub fn resolve<'idt>(hir: &[Node<Hir<'_, 'idt, '_>>]) -> Resolution<'idt> {
+    resolve_continued(hir, std::iter::empty())
+}
+
+/// resolve every name in `hir`, which continues earlier programs that bound `globals`
+/// in their top level scope, like each input of the repl
+pub fn resolve_continued<'idt>(
+    hir: &[Node<Hir<'_, 'idt, '_>>],
+    globals: impl IntoIterator<Item = Ident<'idt>>,
+) -> Resolution<'idt> {
+    let mut resolver = Resolver {
+        resolution: Resolution::default(),
+        funcs: vec![Function {
+            id: FuncId(0),
+            locals: 0,
+            scopes: vec![Vec::new()],
+        }],
+        func_count: 1,
+    };
+
+    for name in globals {
+        let empty = Span::new(0, 0);
+        resolver.define(name, empty, empty, DefKind::Global);
+    }
+
+    for node in hir {
+        resolver.visit_hir(node);
+    }
+
+    let top_level = resolver.funcs[0].scopes[0].iter().copied().collect();
+
+    let mut resolution = resolver.resolution;
+    let mut problems = std::mem::take(&mut resolution.problems);
+
+    for (i, def) in resolution.defs.iter().enumerate() {
+        let is_read = resolution
+            .uses_of(DefId(i))
+            .any(|use_| use_.kind == UseKind::Read);
+
+        if !is_read && def.kind != DefKind::Global && !def.name.as_str().starts_with('_') {
+            problems.push(Problem::Unused { def: DefId(i) });
+        }
+
+        if def.kind != DefKind::Global {
+            resolution.defs_by_span.insert(def.span, DefId(i));
+        }
+    }
+
+    for use_ in resolution.uses.iter() {
+        resolution.uses_by_span.insert(use_.span, use_.def);
+    }
+
+    problems.sort_by_key(|problem| resolution.span_of(problem).start());
+    resolution.problems = problems;
+    resolution.top_level = top_level;
+
+    resolution
+}
+
+/// a function that is being resolved
+struct Function<'idt> {
+    id: FuncId,
+    /// the number of bindings in this function so far
+    locals: usize,
+    /// the bindings of each open scope, later bindings hide earlier ones
+    scopes: Vec<Vec<(Ident<'idt>, DefId)>>,
+}
+
+impl<'idt> Function<'idt> {
+    fn lookup(&self, name: Ident<'idt>) -> Option<DefId> {
+        self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .iter()
+                .rev()
+                .find(|&&(scope_name, _)| scope_name == name)
+                .map(|&(_, def)| def)
+        })
+    }
+}
+
+struct Resolver<'idt> {
+    resolution: Resolution<'idt>,
+    /// the functions that are being resolved, the innermost one is last
+    funcs: Vec<Function<'idt>>,
+    func_count: usize,
+}
+
+impl<'idt> Resolver<'idt> {
+    fn func(&mut self) -> &mut Function<'idt> {
+        self.funcs.last_mut().unwrap()
+    }
+
+    fn define(&mut self, name: Ident<'idt>, span: Span, statement: Span, kind: DefKind) {
+        let def = DefId(self.resolution.defs.len());
+
+        if let Some(shadowed) = self.func().lookup(name) {
+            self.resolution
+                .problems
+                .push(Problem::Shadowed { def, shadowed });
+        }
+
+        let func = self.func();
+        let local = LocalId(func.locals);
+        func.locals += 1;
+        func.scopes.last_mut().unwrap().push((name, def));
+        let func = func.id;
+
+        self.resolution.defs.push(Def {
+            name,
+            kind,
+            span,
+            statement,
+            func,
+            local,
+        });
+    }
+
+    fn refer(&mut self, name: Ident<'idt>, span: Span, kind: UseKind) {
+        match self.func().lookup(name) {
+            Some(def) => self.resolution.uses.push(Use { span, def, kind }),
+            None => {
+                let captured = self.funcs.iter().any(|func| func.lookup(name).is_some());
+
+                self.resolution.problems.push(Problem::Unresolved {
+                    name,
+                    span,
+                    captured,
+                });
+            }
+        }
+    }
+
+    /// bind every name in `pat`, the items of a tuple get the span of the whole tuple
+    fn bind(&mut self, pat: &Pattern<'_, 'idt>, span: Span, statement: Span, kind: DefKind) {
+        match *pat {
+            Pattern::Literal(_) => (),
+            Pattern::Ident(name, _) => self.define(name, span, statement, kind),
+            Pattern::Tuple(ref items) => {
+                for item in items {
+                    self.bind(item, span, statement, kind);
+                }
+            }
+        }
+    }
+
+    /// resolve every name in the target of an assignment
+    fn assign(&mut self, pat: &Pattern<'_, 'idt>, span: Span) {
+        match *pat {
+            Pattern::Literal(_) => (),
+            Pattern::Ident(name, _) => self.refer(name, span, UseKind::Write),
+            Pattern::Tuple(ref items) => {
+                for item in items {
+                    self.assign(item, span);
+                }
+            }
+        }
+    }
+}
+
+impl<'str, 'idt, 'hir> Visit<'str, 'idt, 'hir> for Resolver<'idt> {
+    fn visit_hir(&mut self, node: &Node<Hir<'str, 'idt, 'hir>>) {
+        match node.val {
+            Hir::Let { ref pat, ref value } => {
+                self.visit_expr(&value.val, value.span);
+
+                let kind = match value.val {
+                    Expr::Func { .. } => DefKind::Function,
+                    _ => DefKind::Let,
+                };
+
+                self.bind(&pat.val, pat.span, node.span, kind);
+            }
+            Hir::Mut { ref pat, ref value } => {
+                self.visit_expr(&value.val, value.span);
+                self.assign(&pat.val, pat.span);
+            }
+            Hir::Print(name) => self.refer(name.val, name.span, UseKind::Read),
+            _ => walk_hir(self, node),
+        }
+    }
+
+    fn visit_scope(&mut self, scope: &[Node<Hir<'str, 'idt, 'hir>>]) {
+        self.func().scopes.push(Vec::new());
+        walk_scope(self, scope);
+        self.func().scopes.pop();
+    }
+
+    fn visit_expr(&mut self, expr: &Expr<'str, 'idt, 'hir>, span: Span) {
+        match *expr {
+            Expr::Func {
+                ref parameter_list,
+                ref body,
+            } => {
+                let id = FuncId(self.func_count);
+                self.func_count += 1;
+
+                self.funcs.push(Function {
+                    id,
+                    locals: 0,
+                    scopes: vec![Vec::new()],
+                });
+
+                for param in parameter_list {
+                    self.define(param.name, param.span, param.span, DefKind::Parameter);
+                }
+
+                self.visit_expr(&body.val, body.span);
+                self.funcs.pop();
+            }
+            // the type of a cast is not a variable
+            Expr::Cast { ref value, .. } => self.visit_expr(&value.val, value.span),
+            // tuple expressions are not implemented by the encoder
+            Expr::Tuple(_) => (),
+            _ => walk_expr(self, expr, span),
+        }
+    }
+
+    fn visit_simple_expr(&mut self, expr: &Node<SimpleExpr<'str, 'idt>>) {
+        if let SimpleExpr::Ident(name) = expr.val {
+            self.refer(name, expr.span, UseKind::Read);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use core_tokens::Span;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            level: Level::Warning,
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+
+    /// display this diagnostic along with the location it points to in `source`
+    pub fn render<'a>(&'a self, file_name: &'a str, source: &'a str) -> Render<'a> {
+        Render {
+            diagnostic: self,
+            file_name,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.level, self.message)
+    }
+}
+
+pub struct Render<'a> {
+    diagnostic: &'a Diagnostic,
+    file_name: &'a str,
+    source: &'a str,
+}
+
+impl fmt::Display for Render<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.diagnostic)?;
+
+        if let Some(span) = self.diagnostic.span {
+            let (line, column) = span.line_col(self.source);
+            write!(f, "\n --> {}:{}:{}", self.file_name, line, column)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,639 @@
+mod diagnostic;
+
+pub use diagnostic::{Diagnostic, Level, Render};
+
+use core_hir::{Expr, Hir, Node};
+use core_mir::Reg;
+use core_tokens::Token;
+use core_types::{Ty, Type};
+use impl_pass_mir::encode::Globals;
+use impl_pass_mir::StackFrame;
+use impl_pass_mir::{dce, gvn, inline, licm, sccp, simplify, type_check};
+use impl_pass_resolve::{DefId, Problem, Resolution};
+
+use lib_arena::{cache::Cache, local::LocalUniqueArena};
+use lib_intern::{Interner, Store};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+pub type HirNode<'a> = Node<Hir<'a, 'a, 'a>>;
+
+/// Owns everything that lives for a whole compilation, the interned strings, the HIR
+/// arenas and the type cache, and runs each stage of the compiler.
+///
+/// Every stage borrows the session for `'a`, so the tokens, HIR and types it
+/// produces can refer to the session's arenas.
+///
+/// ```ignore
+/// let session = Session::new();
+/// let hir = session.parse_hir(source)?;
+/// let resolution = session.resolve(&hir);
+/// let mut mir = session.lower_to_mir(hir, &resolution)?;
+/// let types = session.infer_types(&mut mir)?;
+/// ```
+pub struct Session<'a> {
+    intern: Interner,
+    small_strings: Interner,
+    long_strings: Store,
+    arena: LocalUniqueArena<HirNode<'a>, 16>,
+    exprs: LocalUniqueArena<Node<Expr<'a, 'a, 'a>>, 16>,
+    ident: Interner,
+    ty: Cache<Type<'a, 'a>>,
+    diagnostics: RefCell<Vec<Diagnostic>>,
+}
+
+/// which of the optional MIR optimisations `Session::optimise` runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptOptions {
+    /// inline calls to small functions
+    pub inline: bool,
+    /// integer overflow wraps around, so multiplications in loops can be strength reduced
+    pub wrapping: bool,
+}
+
+impl Default for OptOptions {
+    fn default() -> Self {
+        Self {
+            inline: true,
+            wrapping: false,
+        }
+    }
+}
+
+/// what `Session::optimise` changed in a frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OptStats {
+    pub inlining: inline::Stats,
+    pub constants: sccp::Stats,
+    pub values: gvn::Stats,
+    pub loops: licm::Stats,
+    pub dead_code: dce::Stats,
+    pub cfg: simplify::Stats,
+}
+
+impl fmt::Display for OptStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "inlining: {}", self.inlining)?;
+        writeln!(f, "constant propagation: {}", self.constants)?;
+        writeln!(f, "value numbering: {}", self.values)?;
+        writeln!(f, "loop optimisation: {}", self.loops)?;
+        writeln!(f, "dead code elimination: {}", self.dead_code)?;
+        write!(f, "CFG simplification: {}", self.cfg)
+    }
+}
+
+impl Default for Session<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Session<'a> {
+    pub fn new() -> Self {
+        Self {
+            intern: Interner::new(),
+            small_strings: Interner::new(),
+            long_strings: Store::new(),
+            arena: LocalUniqueArena::new(),
+            exprs: LocalUniqueArena::new(),
+            ident: Interner::new(),
+            ty: Cache::new(),
+            diagnostics: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn lexer_context(&'a self) -> impl_lexer::Context<'a, 'a> {
+        impl_lexer::Context {
+            intern: &self.intern,
+            small_strings: &self.small_strings,
+            long_strings: &self.long_strings,
+            max_small_string_size: 64,
+        }
+    }
+
+    pub fn hir_context(&'a self) -> impl_pass_hir::Context<'a, 'a, 'a> {
+        impl_pass_hir::Context {
+            arena: &self.arena,
+            exprs: &self.exprs,
+        }
+    }
+
+    pub fn type_context(&'a self) -> type_check::Context<'a, 'a> {
+        type_check::Context {
+            ident: &self.ident,
+            ty: &self.ty,
+        }
+    }
+
+    /// report a diagnostic, it can be retrieved later with `diagnostics`
+    pub fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    /// all diagnostics reported so far
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow().clone()
+    }
+
+    /// remove and return all diagnostics reported so far
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.borrow_mut())
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.borrow().iter().any(Diagnostic::is_error)
+    }
+
+    pub fn lex(&'a self, source: &str) -> Vec<Token<'a, 'a>> {
+        use core_tokens::Lexer as _;
+
+        let mut lexer = impl_lexer::Lexer::new(source, self.lexer_context());
+
+        std::iter::from_fn(|| lexer.parse_token()).collect()
+    }
+
+    pub fn parse_hir(&'a self, source: &str) -> Option<Vec<HirNode<'a>>> {
+        let lexer = impl_lexer::Lexer::new(source, self.lexer_context());
+        let mut hir_parser = impl_pass_hir::HirParser::new(lexer, self.hir_context());
+
+        let hir = hir_parser.by_ref().collect::<Vec<_>>();
+
+        // the parser stops at the first item it can't parse
+        match hir_parser.peek() {
+            Some(token) => {
+                self.report(Diagnostic::error(
+                    format!("could not parse the program, unexpected {:?}", token.ty),
+                    Some(token.span),
+                ));
+
+                None
+            }
+            None => Some(hir),
+        }
+    }
+
+    /// reprint `source` in the canonical style, if it parses
+    pub fn format(&'a self, source: &str) -> Option<String> {
+        self.parse_hir(source)?;
+
+        Some(impl_fmt::format(source, &self.lex(source)))
+    }
+
+    /// resolve every name in `hir`, reporting unresolved names as errors, and shadowed
+    /// and unused bindings as warnings
+    ///
+    /// the resolution is returned even if some names couldn't be resolved, so tools
+    /// can still use it, but the program can't be lowered if `has_errors` is set
+    pub fn resolve(&'a self, hir: &[HirNode<'a>]) -> Resolution<'a> {
+        let resolution = impl_pass_resolve::resolve(hir);
+
+        for problem in resolution.problems.iter() {
+            self.report_problem(&resolution, problem);
+        }
+
+        resolution
+    }
+
+    /// resolve every name in `hir` after the frames that defined `globals`, see
+    /// `impl_pass_resolve::resolve_continued`, reporting unresolved names as errors
+    ///
+    /// bindings are only read by later inputs, and rebinding a name is how it is
+    /// changed, so unused and shadowed bindings aren't reported
+    pub fn resolve_continued(
+        &'a self,
+        hir: &[HirNode<'a>],
+        globals: &Globals<'a>,
+    ) -> Resolution<'a> {
+        let resolution =
+            impl_pass_resolve::resolve_continued(hir, globals.bindings.keys().copied());
+
+        for problem in resolution.problems.iter() {
+            if let Problem::Unresolved { .. } = *problem {
+                self.report_problem(&resolution, problem);
+            }
+        }
+
+        resolution
+    }
+
+    fn report_problem(&'a self, resolution: &Resolution<'a>, problem: &Problem<'a>) {
+        let span = Some(resolution.span_of(problem));
+
+        self.report(match *problem {
+            Problem::Unresolved {
+                name,
+                captured: false,
+                ..
+            } => Diagnostic::error(format!("cannot find `{}` in this scope", name), span),
+            Problem::Unresolved {
+                name,
+                captured: true,
+                ..
+            } => Diagnostic::error(format!("functions can't capture `{}`", name), span),
+            Problem::Shadowed { def, .. } => Diagnostic::warning(
+                format!(
+                    "`{}` shadows an earlier binding of the same name",
+                    resolution.def(def).name
+                ),
+                span,
+            ),
+            Problem::Unused { def } => Diagnostic::warning(
+                format!("unused variable `{}`", resolution.def(def).name),
+                span,
+            ),
+        });
+    }
+
+    /// lower `hir`, whose names must be resolved by `resolution` without errors
+    pub fn lower_to_mir(
+        &'a self,
+        hir: Vec<HirNode<'a>>,
+        resolution: &Resolution<'a>,
+    ) -> Option<StackFrame> {
+        let frame = impl_pass_mir::encode::write(hir, resolution);
+
+        match frame {
+            Ok(ref frame) if !self.verify_after(frame, "lowering") => None,
+            Ok(frame) => {
+                self.warn_unreachable(&frame);
+                Some(frame)
+            }
+            Err(error) => {
+                self.report(Diagnostic::error(error.message, error.span));
+                None
+            }
+        }
+    }
+
+    /// like `lower_to_mir`, but also return the register of each binding of the top level frame
+    pub fn lower_to_mir_with_bindings(
+        &'a self,
+        hir: Vec<HirNode<'a>>,
+        resolution: &Resolution<'a>,
+    ) -> Option<(StackFrame, HashMap<DefId, Reg>)> {
+        let output = impl_pass_mir::encode::write_with_bindings(hir, resolution);
+
+        match output {
+            Ok((ref frame, _)) if !self.verify_after(frame, "lowering") => None,
+            Ok(output) => {
+                self.warn_unreachable(&output.0);
+                Some(output)
+            }
+            Err(error) => {
+                self.report(Diagnostic::error(error.message, error.span));
+                None
+            }
+        }
+    }
+
+    /// lower `hir` after the frames that defined `globals`, see `encode::write_continued`,
+    /// and return the globals after it, its names must be resolved by `resolve_continued`
+    pub fn lower_to_mir_continued(
+        &'a self,
+        hir: Vec<HirNode<'a>>,
+        resolution: &Resolution<'a>,
+        globals: &Globals<'a>,
+    ) -> Option<(StackFrame, Globals<'a>)> {
+        let output = impl_pass_mir::encode::write_continued(hir, resolution, globals);
+
+        match output {
+            Ok((ref frame, _)) if !self.verify_continued_after(frame, globals, "lowering") => None,
+            Ok(output) => {
+                self.warn_unreachable(&output.0);
+                Some(output)
+            }
+            Err(error) => {
+                self.report(Diagnostic::error(error.message, error.span));
+                None
+            }
+        }
+    }
+
+    /// report every region of code that can never run as a warning
+    fn warn_unreachable(&'a self, frame: &StackFrame) {
+        for span in impl_pass_mir::cfg::unreachable_code(frame) {
+            self.report(Diagnostic::warning("unreachable code", span));
+        }
+    }
+
+    /// parse MIR in the textual format it is printed in
+    pub fn parse_mir(&'a self, source: &str) -> Option<StackFrame> {
+        match impl_pass_mir::parse::parse(source) {
+            Ok(frame) if self.verify(&frame, None) => Some(frame),
+            Ok(_) => None,
+            Err(error) => {
+                self.report(Diagnostic::error(error.message, Some(error.span)));
+                None
+            }
+        }
+    }
+
+    /// check that `frame` is well formed, reporting everything that is wrong with it,
+    /// `pass` is the pass that produced `frame` if it wasn't written by hand
+    pub fn verify(&'a self, frame: &StackFrame, pass: Option<&str>) -> bool {
+        self.report_invalid(impl_pass_mir::verify::verify(frame), pass)
+    }
+
+    fn report_invalid(
+        &'a self,
+        result: Result<(), Vec<impl_pass_mir::verify::Error>>,
+        pass: Option<&str>,
+    ) -> bool {
+        let errors = match result {
+            Ok(()) => return true,
+            Err(errors) => errors,
+        };
+
+        for error in errors {
+            let message = match pass {
+                Some(pass) => format!("invalid MIR after {}: {}", pass, error),
+                None => format!("invalid MIR: {}", error),
+            };
+
+            self.report(Diagnostic::error(message, None));
+        }
+
+        false
+    }
+
+    /// the passes are trusted in release builds, so the MIR they produce is only verified
+    /// in debug builds
+    fn verify_after(&'a self, frame: &StackFrame, pass: &str) -> bool {
+        !cfg!(debug_assertions) || self.verify(frame, Some(pass))
+    }
+
+    /// like `verify_after`, for a frame that continues the frames that defined `globals`
+    fn verify_continued_after(
+        &'a self,
+        frame: &StackFrame,
+        globals: &Globals<'a>,
+        pass: &str,
+    ) -> bool {
+        !cfg!(debug_assertions)
+            || self.report_invalid(
+                impl_pass_mir::verify::verify_continued(frame, globals.reg_count),
+                Some(pass),
+            )
+    }
+
+    /// convert `frame` to SSA form, so it can be optimised, the types of the frame
+    /// must be inferred again afterwards
+    pub fn construct_ssa(&'a self, frame: &mut StackFrame) -> bool {
+        impl_pass_mir::ssa::construct(frame);
+
+        !cfg!(debug_assertions)
+            || self.report_invalid(
+                impl_pass_mir::verify::verify_ssa(frame),
+                Some("SSA construction"),
+            )
+    }
+
+    /// fold the constants of `frame`, which must be in SSA form with `types` inferred for it,
+    /// the frame stays in SSA form, but its types must be inferred again afterwards
+    pub fn propagate_constants(
+        &'a self,
+        frame: &mut StackFrame,
+        types: &[Ty<'a, 'a>],
+    ) -> Option<sccp::Stats> {
+        let stats = sccp::propagate(frame, types);
+
+        if !cfg!(debug_assertions)
+            || self.report_invalid(
+                impl_pass_mir::verify::verify_ssa(frame),
+                Some("constant propagation"),
+            )
+        {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    /// inline the calls to small functions in `frame`, which must be in SSA form,
+    /// its types must be inferred again afterwards
+    pub fn inline(&'a self, frame: &mut StackFrame) -> Option<inline::Stats> {
+        let stats = inline::inline(frame);
+
+        if !cfg!(debug_assertions)
+            || self.report_invalid(impl_pass_mir::verify::verify_ssa(frame), Some("inlining"))
+        {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    /// replace the copies and redundant computations of `frame`, which must be in SSA form,
+    /// with the registers that already hold their value, its types must be inferred again
+    /// afterwards
+    pub fn number_values(&'a self, frame: &mut StackFrame) -> Option<gvn::Stats> {
+        let stats = gvn::number(frame);
+
+        if !cfg!(debug_assertions)
+            || self.report_invalid(
+                impl_pass_mir::verify::verify_ssa(frame),
+                Some("value numbering"),
+            )
+        {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    /// move the loop-invariant instructions of `frame`, which must be in SSA form with `types`
+    /// inferred for it, out of its loops, and strength reduce its multiplications by induction
+    /// variables if overflow is `wrapping`, its types must be inferred again afterwards
+    pub fn optimise_loops(
+        &'a self,
+        frame: &mut StackFrame,
+        types: &[Ty<'a, 'a>],
+        wrapping: bool,
+    ) -> Option<licm::Stats> {
+        let stats = licm::optimise(frame, types, wrapping);
+
+        if !cfg!(debug_assertions)
+            || self.report_invalid(
+                impl_pass_mir::verify::verify_ssa(frame),
+                Some("loop optimisation"),
+            )
+        {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    /// remove the instructions of `frame` whose results are never read, and the blocks that
+    /// can't be reached, its types must be inferred again afterwards
+    pub fn eliminate_dead_code(&'a self, frame: &mut StackFrame) -> Option<dce::Stats> {
+        let stats = dce::eliminate(frame);
+
+        if self.verify_after(frame, "dead code elimination") {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    /// merge and remove the blocks of `frame` that only jump to each other
+    pub fn simplify_cfg(&'a self, frame: &mut StackFrame) -> Option<simplify::Stats> {
+        let stats = simplify::simplify(frame);
+
+        if self.verify_after(frame, "CFG simplification") {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    /// run the MIR optimisations on `frame`, whose types must already be inferred,
+    /// and infer its types again
+    pub fn optimise(
+        &'a self,
+        frame: &mut StackFrame,
+        options: OptOptions,
+    ) -> Option<(Vec<Ty<'a, 'a>>, OptStats)> {
+        if !self.construct_ssa(frame) {
+            return None;
+        }
+
+        let inlining = if options.inline {
+            self.inline(frame)?
+        } else {
+            inline::Stats::default()
+        };
+
+        let types = self.infer_types(frame)?;
+
+        let constants = self.propagate_constants(frame, &types)?;
+        let values = self.number_values(frame)?;
+        let types = self.infer_types(frame)?;
+        let loops = self.optimise_loops(frame, &types, options.wrapping)?;
+        let dead_code = self.eliminate_dead_code(frame)?;
+        let cfg = self.simplify_cfg(frame)?;
+
+        if !self.destruct_ssa(frame) {
+            return None;
+        }
+
+        let types = self.infer_types(frame)?;
+
+        Some((
+            types,
+            OptStats {
+                inlining,
+                constants,
+                values,
+                loops,
+                dead_code,
+                cfg,
+            },
+        ))
+    }
+
+    /// lower the phis of `frame` back to copies, so it can be interpreted or emitted as C
+    pub fn destruct_ssa(&'a self, frame: &mut StackFrame) -> bool {
+        impl_pass_mir::ssa::destruct(frame);
+        self.verify_after(frame, "SSA destruction")
+    }
+
+    pub fn infer_types(&'a self, frame: &mut StackFrame) -> Option<Vec<Ty<'a, 'a>>> {
+        match type_check::infer_types(frame, self.type_context()) {
+            Ok(_) if !self.verify_after(frame, "type checking") => None,
+            Ok(types) => Some(types),
+            Err(error) => {
+                self.report(Diagnostic::error(error.message, error.span));
+                None
+            }
+        }
+    }
+
+    /// like `infer_types`, for a frame lowered by `lower_to_mir_continued`
+    pub fn infer_types_continued(
+        &'a self,
+        frame: &mut StackFrame,
+        globals: &Globals<'a>,
+        known: &type_check::Known<'_, 'a, 'a>,
+    ) -> Option<Vec<Ty<'a, 'a>>> {
+        match type_check::infer_continued(frame, known, self.type_context()) {
+            Ok(_) if !self.verify_continued_after(frame, globals, "type checking") => None,
+            Ok(types) => Some(types),
+            Err(error) => {
+                self.report(Diagnostic::error(error.message, error.span));
+                None
+            }
+        }
+    }
+
+    /// run every stage up to type checking
+    pub fn check(&'a self, source: &str) -> Option<(StackFrame, Vec<Ty<'a, 'a>>)> {
+        let hir = self.parse_hir(source)?;
+
+        let resolution = self.resolve(&hir);
+
+        if resolution.has_errors() {
+            return None;
+        }
+
+        let mut frame = self.lower_to_mir(hir, &resolution)?;
+        let types = self.infer_types(&mut frame)?;
+
+        Some((frame, types))
+    }
+
+    pub fn interpret(
+        &'a self,
+        frame: &StackFrame,
+        types: &[Ty<'a, 'a>],
+        overflow: interp_mir::Overflow,
+        output: &mut dyn Write,
+    ) -> Result<(), interp_mir::Error> {
+        interp_mir::interpret(frame, types, self.type_context(), overflow, output)
+    }
+
+    /// like `interpret`, for a frame lowered by `lower_to_mir_continued`
+    pub fn interpret_continued<'f>(
+        &'a self,
+        frame: &'f StackFrame,
+        types: &[Ty<'a, 'a>],
+        overflow: interp_mir::Overflow,
+        output: &mut dyn Write,
+        environment: &mut interp_mir::Environment<'f>,
+    ) -> Result<(), interp_mir::Error> {
+        interp_mir::interpret_continued(
+            frame,
+            types,
+            self.type_context(),
+            overflow,
+            output,
+            environment,
+        )
+    }
+
+    /// write `frame` as C, returns `false` if the C backend can't emit it, which is reported
+    pub fn emit_c(
+        &'a self,
+        frame: StackFrame,
+        writer: impl Write,
+        options: &interp_mir::Options<'_>,
+    ) -> io::Result<bool> {
+        match interp_mir::emit_c(frame, writer, &self.ident, options) {
+            Ok(()) => Ok(true),
+            Err(interp_mir::EmitError::Io(error)) => Err(error),
+            Err(error) => {
+                let span = match error {
+                    interp_mir::EmitError::Unsupported { span, .. } => span,
+                    interp_mir::EmitError::Type(ref error) => error.span,
+                    interp_mir::EmitError::Io(_) => None,
+                };
+
+                self.report(Diagnostic::error(error.to_string(), span));
+                Ok(false)
+            }
+        }
+    }
+}
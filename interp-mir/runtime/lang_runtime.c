@@ -0,0 +1,84 @@
+#include <setjmp.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <inttypes.h>
+
+#include "lang_runtime.h"
+
+// the exit status of a program that panicked
+#define LANG_PANIC_STATUS 101
+
+static __thread struct lang_landing_pad *__target = NULL;
+static __thread _Bool __is_unwinding = 0;
+
+static __thread struct panic {
+    const char *message;
+    const char *file;
+    uint32_t line;
+    uint32_t column;
+} panic_info;
+
+void lang_push_landing_pad(struct lang_landing_pad *pad) {
+    pad->prev = __target;
+    __target = pad;
+}
+
+void lang_pop_landing_pad(void) {
+    if(__target != NULL) {
+        __target = __target->prev;
+    }
+}
+
+static void lang_report_panic(void) {
+    fflush(stdout);
+    fprintf(
+        stderr,
+        "panicked at '%s', %s:%" PRIu32 ":%" PRIu32 "\n",
+        panic_info.message,
+        panic_info.file,
+        panic_info.line,
+        panic_info.column
+    );
+}
+
+_Noreturn void lang_resume_unwind(void) {
+    struct lang_landing_pad *pad = __target;
+
+    if(pad == NULL) {
+        // there are no more cleanups to run, so report the panic and exit
+        __is_unwinding = 0;
+        lang_report_panic();
+        exit(LANG_PANIC_STATUS);
+    }
+
+    __target = pad->prev;
+    longjmp(pad->target, 1);
+}
+
+_Noreturn void lang_panic(const char *message, const char *file, uint32_t line, uint32_t column) {
+    if(__is_unwinding) {
+        lang_report_panic();
+        fprintf(
+            stderr,
+            "panicked while panicking at '%s', %s:%" PRIu32 ":%" PRIu32 ", aborting\n",
+            message,
+            file,
+            line,
+            column
+        );
+        abort();
+    }
+
+    __is_unwinding = 1;
+
+    panic_info.message = message;
+    panic_info.file = file;
+    panic_info.line = line;
+    panic_info.column = column;
+
+    lang_resume_unwind();
+}
+
+int main(void) {
+    return lang_main();
+}
@@ -0,0 +1,26 @@
+#ifndef LANG_RUNTIME_H
+#define LANG_RUNTIME_H
+
+#include <setjmp.h>
+#include <stdint.h>
+
+// a cleanup landing pad, the generated code `setjmp`s into `target` and
+// registers the pad, a panic will `longjmp` to the innermost registered pad
+struct lang_landing_pad {
+    jmp_buf target;
+    struct lang_landing_pad *prev;
+};
+
+void lang_push_landing_pad(struct lang_landing_pad *pad);
+void lang_pop_landing_pad(void);
+
+// start unwinding with the given message and source location
+_Noreturn void lang_panic(const char *message, const char *file, uint32_t line, uint32_t column);
+
+// continue unwinding after a cleanup landing pad has finished
+_Noreturn void lang_resume_unwind(void);
+
+// the entry point of the generated program
+int lang_main(void);
+
+#endif
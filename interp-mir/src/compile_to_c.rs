@@ -1,11 +1,17 @@
-use core_mir::{BinOpType, Load, Mir, PreOpType, Reg};
+use core_mir::{BinOpType, CastKind, Load, Mir, PreOpType, Reg, Terminator};
+use core_tokens::Span;
 use core_types::{Primitive, Ty, Type, Variant};
+use impl_pass_mir::liveness::Interference;
 use impl_pass_mir::StackFrame;
+use std::fmt;
 use std::io::{self, Write};
 
 use std::alloc::Layout;
 
-pub fn layout(types: &[Ty<'_, '_>]) -> (Vec<usize>, Layout) {
+/// the offset of each register in the locals of a frame, registers that don't interfere
+/// share the same slot, so the locals only need to be as large as the registers that
+/// are live at the same time
+pub fn layout(types: &[Ty<'_, '_>], interference: &Interference) -> (Vec<usize>, Layout) {
     use std::cmp::Ordering;
 
     #[derive(Clone, Copy, Eq)]
@@ -57,10 +63,31 @@ pub fn layout(types: &[Ty<'_, '_>]) -> (Vec<usize>, Layout) {
         align = align.max(ty.align());
         let mask = ty.align() - 1;
 
+        // the registers in each slot, types with the same size and alignment
+        // can share slots, so each register is put in the first slot where
+        // it doesn't interfere with any other register
+        let mut slots: Vec<Vec<usize>> = Vec::new();
+
         for pos in items {
+            let slot = slots.iter_mut().find(|slot| {
+                slot.iter()
+                    .all(|&other| !interference.interferes(Reg(pos), Reg(other)))
+            });
+
+            match slot {
+                Some(slot) => slot.push(pos),
+                None => slots.push(vec![pos]),
+            }
+        }
+
+        for slot in slots {
             // fix alignment
             size = (size + mask) & !mask;
-            assign[pos] = size;
+
+            for pos in slot {
+                assign[pos] = size;
+            }
+
             size += ty.size;
         }
     }
@@ -68,52 +95,238 @@ pub fn layout(types: &[Ty<'_, '_>]) -> (Vec<usize>, Layout) {
     (assign, Layout::from_size_align(size, align).unwrap())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// integer overflow panics
+    Checked,
+    /// integer overflow wraps around, division by zero still panics
+    Wrapping,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    pub overflow: Overflow,
+    /// used to report the location of runtime panics
+    pub file_name: &'a str,
+    pub source: &'a str,
+}
+
+#[derive(Debug)]
+pub enum EmitError {
+    /// the types of the frame could not be inferred
+    Type(impl_pass_mir::type_check::Error),
+    /// the frame uses something the C backend can't emit yet, at the block
+    /// with the given span
+    Unsupported {
+        what: &'static str,
+        span: Option<Span>,
+    },
+    Io(io::Error),
+}
+
+impl From<io::Error> for EmitError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Type(error) => write!(f, "{}", error),
+            Self::Unsupported { what, .. } => {
+                write!(f, "{} are unsupported in the C backend", what)
+            }
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for EmitError {}
+
 pub fn emit_c(
     digest: StackFrame,
     mut writer: impl Write,
     ident: &lib_intern::Interner,
-) -> io::Result<()> {
-    write_c(digest, &mut writer, ident)
+    options: &Options<'_>,
+) -> Result<(), EmitError> {
+    write_c(digest, &mut writer, ident, options)
+}
+
+/// fails on the first thing in `frame` the C backend can't emit, so nothing is written
+/// for a frame it can't emit
+fn unsupported(frame: &StackFrame, types: &[Ty<'_, '_>]) -> Result<(), EmitError> {
+    for block in frame.blocks() {
+        let unsupported = |what| EmitError::Unsupported {
+            what,
+            span: block.meta.span,
+        };
+
+        for mir in block.instructions.iter() {
+            match *mir {
+                Mir::PreOp { .. } => return Err(unsupported("negation and `!`")),
+                // functions that don't capture anything have no value to store
+                Mir::CreateFunc { binding, .. } if types[binding.0].size != 0 => {
+                    return Err(unsupported("functions that capture variables"))
+                }
+                Mir::PopArgument { .. } => return Err(unsupported("function arguments")),
+                _ => (),
+            }
+        }
+
+        if let Terminator::Call { .. } = block.terminator {
+            return Err(unsupported("function calls"));
+        }
+    }
+
+    Ok(())
+}
+
+fn c_type(ty: Ty<'_, '_>) -> &'static str {
+    match ty.ty {
+        Variant::Primitive(prim) => match prim {
+            Primitive::Bool => "_Bool",
+            Primitive::I8 => "int8_t",
+            Primitive::I16 => "int16_t",
+            Primitive::I32 => "int32_t",
+            Primitive::I64 => "int64_t",
+            Primitive::U8 => "uint8_t",
+            Primitive::U16 => "uint16_t",
+            Primitive::U32 => "uint32_t",
+            Primitive::U64 => "uint64_t",
+            Primitive::F32 => "float",
+            Primitive::F64 => "double",
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// the `printf` format string used to print a value of the given type
+fn c_format(ty: Ty<'_, '_>) -> &'static str {
+    match ty.ty {
+        Variant::Primitive(prim) => match prim {
+            Primitive::Bool => r#""%b\n""#,
+            Primitive::I8 => r#""%" PRId8 "\n""#,
+            Primitive::I16 => r#""%" PRId16 "\n""#,
+            Primitive::I32 => r#""%" PRId32 "\n""#,
+            Primitive::I64 => r#""%" PRId64 "\n""#,
+            Primitive::U8 => r#""%" PRIu8 "\n""#,
+            Primitive::U16 => r#""%" PRIu16 "\n""#,
+            Primitive::U32 => r#""%" PRIu32 "\n""#,
+            Primitive::U64 => r#""%" PRIu64 "\n""#,
+            Primitive::F32 | Primitive::F64 => r#""%g\n""#,
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// the `(min, max)` macros from `stdint.h` for an integer type
+fn c_limits(ty: Ty<'_, '_>) -> (&'static str, &'static str) {
+    match ty.ty {
+        Variant::Primitive(prim) => match prim {
+            Primitive::I8 => ("INT8_MIN", "INT8_MAX"),
+            Primitive::I16 => ("INT16_MIN", "INT16_MAX"),
+            Primitive::I32 => ("INT32_MIN", "INT32_MAX"),
+            Primitive::I64 => ("INT64_MIN", "INT64_MAX"),
+            Primitive::U8 => ("0", "UINT8_MAX"),
+            Primitive::U16 => ("0", "UINT16_MAX"),
+            Primitive::U32 => ("0", "UINT32_MAX"),
+            Primitive::U64 => ("0", "UINT64_MAX"),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// escape `s` as a C string literal
+fn c_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+
+    out.push('"');
+
+    for byte in s.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b' '..=b'~' => out.push(byte as char),
+            // octal escapes never consume more than 3 digits
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    out.push('"');
+
+    out
 }
 
 struct GetLocal<'a, 'b> {
     assign: &'a [usize],
     reg: Reg,
     ty: &'b dyn std::fmt::Display,
+    volatile: bool,
 }
 
 impl std::fmt::Display for GetLocal<'_, '_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "*(({}*)(locals + {}))", self.ty, self.assign[self.reg.0])
+        let qualifier = if self.volatile { " volatile" } else { "" };
+
+        write!(
+            f,
+            "*(({}{}*)(locals + {}))",
+            self.ty, qualifier, self.assign[self.reg.0]
+        )
     }
 }
 
 fn write_c<'idt>(
-    digest: StackFrame,
+    mut digest: StackFrame,
     writer: &mut dyn Write,
     ident: &'idt lib_intern::Interner,
-) -> io::Result<()> {
+    options: &Options<'_>,
+) -> Result<(), EmitError> {
     macro_rules! emit {
         ($($t:tt)*) => {
             write!(writer, $($t)*)?;
         }
     }
 
+    let ty_ctx = lib_arena::cache::Cache::new();
+    let types = impl_pass_mir::type_check::infer_types(
+        &mut digest,
+        impl_pass_mir::type_check::Context { ident, ty: &ty_ctx },
+    )
+    .map_err(EmitError::Type)?;
+
+    unsupported(&digest, &types)?;
+
     emit!(
         "\
     #include <stdio.h>\n\
     #include <stdint.h>\n\
+    #include <inttypes.h>\n\
+    #include <setjmp.h>\n\
     #include <string.h>\n\
-    int main() {{\n"
+    #include \"{}\"\n\
+    int lang_main(void) {{\n",
+        crate::runtime::HEADER_NAME
     );
 
-    let ty_ctx = lib_arena::cache::Cache::new();
-    let types = impl_pass_mir::type_check::infer_types(
-        &digest,
-        impl_pass_mir::type_check::Context { ident, ty: &ty_ctx },
-    )
-    .expect("Could not deduce types");
-    let (assign, layout) = layout(&types);
+    let (assign, layout) = layout(&types, &Interference::new(&digest, None));
+
+    let landing_pads = digest
+        .blocks()
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter(|mir| match mir {
+            Mir::PushLandingPad { .. } => true,
+            _ => false,
+        })
+        .count();
+
+    // locals that are modified between a `setjmp` and a `longjmp`
+    // must be volatile, or their value will be indeterminate
+    let volatile = landing_pads != 0;
 
     macro_rules! get {
         ($reg:expr, $ty:expr) => {
@@ -121,42 +334,48 @@ fn write_c<'idt>(
                 assign: assign.as_slice(),
                 reg: $reg,
                 ty: &$ty as _,
+                volatile,
             }
         };
     }
 
     emit!(
-        "char locals[{size}] __attribute__((aligned({align})));\n",
+        "{qualifier}char locals[{size}] __attribute__((aligned({align})));\n",
+        qualifier = if volatile { "volatile " } else { "" },
         size = layout.size(),
         align = layout.align()
     );
 
+    for pad in 0..landing_pads {
+        emit!("struct lang_landing_pad __pad_{};\n", pad);
+    }
+
+    let mut next_pad = 0;
+
     for (block_idx, block) in digest.blocks().iter().enumerate() {
         emit!("\n_label_{}:\n", block_idx);
         for mir in block.instructions.iter() {
             match *mir {
-                Mir::Jump(j) => {
-                    emit!("goto _label_{};\n", j);
-                }
-                Mir::BranchTrue { cond, target } => {
-                    emit!("if( {} ) goto _label_{};\n", get!(cond, "_Bool"), target);
-                }
                 Mir::Load { from, to } => {
-                    let ty = match types[to.0].ty {
-                        Variant::Primitive(Primitive::Bool) => "_Bool",
-                        Variant::Primitive(Primitive::I32) => "int32_t",
-                        _ => unreachable!(),
-                    };
+                    let ty = c_type(types[to.0]);
 
                     let value = match from {
                         Load::Bool(x) => i32::from(x),
                         Load::U8(x) => i32::from(x),
                         Load::U16(x) => i32::from(x),
+                        Load::I32(x) => x,
                         _ => unreachable!(),
                     };
 
                     emit!("{} = {};\n", get!(to, ty), value);
                 }
+                // a copy can share its slot with its source, then there is nothing to copy
+                Mir::LoadReg { from, to } if assign[to.0] == assign[from.0] => {}
+                Mir::LoadReg { from, to } if volatile => {
+                    assert_eq!(types[to.0], types[from.0], "type check failure");
+                    let ty = c_type(types[to.0]);
+                    emit!("{} = {};\n", get!(to, ty), get!(from, ty));
+                }
                 Mir::LoadReg { from, to } => {
                     let ty = &types[to.0];
                     assert_eq!(ty, &types[from.0], "type check failure");
@@ -168,118 +387,228 @@ fn write_c<'idt>(
                     );
                 }
                 Mir::Print(reg) => {
-                    let (fmt_spec, ty) = match types[reg.0].ty {
-                        Variant::Primitive(Primitive::Bool) => ("b", "_Bool"),
-                        Variant::Primitive(Primitive::I32) => ("d", "int32_t"),
-                        _ => unreachable!(),
-                    };
+                    let fmt = c_format(types[reg.0]);
+                    let ty = c_type(types[reg.0]);
 
-                    emit!("printf(\"%{}\\n\", {});\n", fmt_spec, get!(reg, ty));
+                    emit!("printf({}, {});\n", fmt, get!(reg, ty));
                 }
                 Mir::BinOp {
                     op,
                     out,
                     left,
                     right,
-                } => match op {
-                    BinOpType::Add => emit!(
-                        "{} = {} + {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-                    BinOpType::Sub => emit!(
-                        "{} = {} - {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-                    BinOpType::Mul => emit!(
-                        "{} = {} * {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-                    BinOpType::Div => emit!(
-                        "{} = {} / {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-
-                    BinOpType::GreaterThan => emit!(
-                        "{} = {} > {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-                    BinOpType::LessThan => emit!(
-                        "{} = {} < {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-                    BinOpType::GreaterThanOrEqual => emit!(
-                        "{} = {} >= {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-                    BinOpType::LessThanOrEqual => emit!(
-                        "{} = {} <= {};\n",
-                        get!(out, "int32_t"),
-                        get!(left, "int32_t"),
-                        get!(right, "int32_t")
-                    ),
-
-                    BinOpType::Equal => {
-                        let ty = match types[left.0].ty {
-                            Variant::Primitive(Primitive::Bool) => "_Bool",
-                            Variant::Primitive(Primitive::I32) => "int32_t",
-                            _ => unreachable!(),
-                        };
-
-                        emit!(
-                            "{} = {} == {};\n",
-                            get!(out, "_Bool"),
-                            get!(left, ty),
-                            get!(right, ty),
-                        )
-                    }
+                    span,
+                } => {
+                    let out_ty = c_type(types[out.0]);
+                    let ty = c_type(types[left.0]);
 
-                    BinOpType::NotEqual => {
-                        let ty = match types[left.0].ty {
-                            Variant::Primitive(Primitive::Bool) => "_Bool",
-                            Variant::Primitive(Primitive::I32) => "int32_t",
-                            _ => unreachable!(),
-                        };
-
-                        emit!(
-                            "{} = {} != {};\n",
-                            get!(out, "_Bool"),
-                            get!(left, ty),
-                            get!(right, ty),
+                    let is_integer = match types[left.0].ty {
+                        Variant::Primitive(prim) => prim.is_integer(),
+                        _ => false,
+                    };
+
+                    let (line, column) = span.line_col(options.source);
+                    let panic = |message: &str| {
+                        format!(
+                            "lang_panic({}, {}, {}, {});",
+                            c_str(message),
+                            c_str(options.file_name),
+                            line,
+                            column
                         )
+                    };
+
+                    match op {
+                        BinOpType::Add | BinOpType::Sub | BinOpType::Mul if is_integer => {
+                            let (builtin, message) = match op {
+                                BinOpType::Add => ("add", "attempt to add with overflow"),
+                                BinOpType::Sub => ("sub", "attempt to subtract with overflow"),
+                                _ => ("mul", "attempt to multiply with overflow"),
+                            };
+
+                            // the builtins always write the wrapped result
+                            match options.overflow {
+                                Overflow::Checked => emit!(
+                                    "if( __builtin_{}_overflow({}, {}, &{}) ) {}\n",
+                                    builtin,
+                                    get!(left, ty),
+                                    get!(right, ty),
+                                    get!(out, out_ty),
+                                    panic(message),
+                                ),
+                                Overflow::Wrapping => emit!(
+                                    "(void)__builtin_{}_overflow({}, {}, &{});\n",
+                                    builtin,
+                                    get!(left, ty),
+                                    get!(right, ty),
+                                    get!(out, out_ty),
+                                ),
+                            }
+
+                            continue;
+                        }
+                        BinOpType::Div if is_integer => {
+                            emit!(
+                                "if( {} == 0 ) {}\n",
+                                get!(right, ty),
+                                panic("attempt to divide by zero")
+                            );
+
+                            if let Variant::Primitive(prim) = types[left.0].ty {
+                                if prim.is_signed() {
+                                    let (min, _) = c_limits(types[left.0]);
+
+                                    emit!(
+                                        "if( {} == {} && {} == -1 ) {}\n",
+                                        get!(left, ty),
+                                        min,
+                                        get!(right, ty),
+                                        panic("attempt to divide with overflow")
+                                    );
+                                }
+                            }
+                        }
+                        _ => (),
                     }
-                },
-                Mir::PreOp { op, out, arg } => todo!("comp2c preop"),
-                Mir::CreateFunc {
-                    binding,
-                    ret,
-                    ref stack_frame,
+
+                    let op = match op {
+                        BinOpType::Add => "+",
+                        BinOpType::Sub => "-",
+                        BinOpType::Mul => "*",
+                        BinOpType::Div => "/",
+                        BinOpType::GreaterThan => ">",
+                        BinOpType::LessThan => "<",
+                        BinOpType::GreaterThanOrEqual => ">=",
+                        BinOpType::LessThanOrEqual => "<=",
+                        BinOpType::Equal => "==",
+                        BinOpType::NotEqual => "!=",
+                    };
+
+                    emit!(
+                        "{} = {} {} {};\n",
+                        get!(out, out_ty),
+                        get!(left, ty),
+                        op,
+                        get!(right, ty),
+                    )
+                }
+                Mir::PreOp { .. } => unreachable!("checked by `unsupported`"),
+                Mir::Cast {
+                    to,
+                    from,
+                    ty: _,
+                    kind,
                 } => {
-                    if types[binding.0].size == 0 {
-                        continue;
+                    let to_ty = c_type(types[to.0]);
+                    let from_ty = c_type(types[from.0]);
+
+                    match kind {
+                        CastKind::Infer => {
+                            unreachable!("cast kinds are resolved by the type checker")
+                        }
+                        CastKind::FloatToInt => {
+                            // float to int casts saturate, and NaN is mapped to 0
+                            let (min, max) = c_limits(types[to.0]);
+                            let value = get!(from, from_ty);
+
+                            emit!(
+                                "{to} = {value} != {value} ? 0 : {value} <= {min} ? {min} : {value} >= {max} ? {max} : ({ty}){value};\n",
+                                to = get!(to, to_ty),
+                                ty = to_ty,
+                                value = value,
+                                min = min,
+                                max = max,
+                            )
+                        }
+                        CastKind::Identity
+                        | CastKind::Truncate
+                        | CastKind::SignExtend
+                        | CastKind::ZeroExtend
+                        | CastKind::IntToFloat
+                        | CastKind::FloatToFloat
+                        | CastKind::BoolToInt => {
+                            emit!(
+                                "{} = ({}){};\n",
+                                get!(to, to_ty),
+                                to_ty,
+                                get!(from, from_ty)
+                            )
+                        }
                     }
+                }
+                Mir::CreateFunc { binding, .. } => {
+                    debug_assert_eq!(types[binding.0].size, 0, "checked by `unsupported`");
+                }
+                Mir::Panic { ref message, span } => {
+                    let (line, column) = span.line_col(options.source);
+
+                    emit!(
+                        "lang_panic({}, {}, {}, {});\n",
+                        c_str(message),
+                        c_str(options.file_name),
+                        line,
+                        column
+                    );
+                }
+                Mir::PushLandingPad { cleanup } => {
+                    let pad = next_pad;
+                    next_pad += 1;
 
-                    todo!("comp2c stateful-func")
+                    emit!(
+                        "if( setjmp(__pad_{pad}.target) ) goto _label_{cleanup};\n\
+                        lang_push_landing_pad(&__pad_{pad});\n",
+                        pad = pad,
+                        cleanup = cleanup
+                    );
+                }
+                Mir::PopLandingPad => emit!("lang_pop_landing_pad();\n"),
+                Mir::Phi { .. } => {
+                    unreachable!("phis must be lowered to copies before emitting C")
                 }
-                _ => todo!("comp2c extra"),
+                Mir::PopArgument { .. } => unreachable!("checked by `unsupported`"),
             }
         }
 
-        if block.meta.children.is_empty() {
-            emit!("return 0;\n");
+        match block.terminator {
+            Terminator::Goto(target) => emit!("goto _label_{};\n", target),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => emit!(
+                "if( {} ) goto _label_{}; else goto _label_{};\n",
+                get!(cond, "_Bool"),
+                then,
+                otherwise
+            ),
+            Terminator::Switch {
+                value,
+                ref cases,
+                otherwise,
+            } => {
+                let ty = c_type(types[value.0]);
+                let mask = (1u128 << (types[value.0].size * 8)) - 1;
+                let mut seen = Vec::with_capacity(cases.len());
+
+                emit!("switch( {} ) {{\n", get!(value, ty));
+
+                for &(case, target) in cases.iter() {
+                    // the cases are truncated to the type of the value, and C doesn't
+                    // allow duplicate cases, so only the first of each is kept
+                    let case = (case & mask) as u64;
+
+                    if !seen.contains(&case) {
+                        seen.push(case);
+                        emit!("case ({}){}ull: goto _label_{};\n", ty, case, target);
+                    }
+                }
+
+                emit!("default: goto _label_{};\n}}\n", otherwise);
+            }
+            Terminator::Return => emit!("return 0;\n"),
+            Terminator::Unreachable => emit!("__builtin_unreachable();\n"),
+            Terminator::Call { .. } => unreachable!("checked by `unsupported`"),
+            Terminator::Resume => emit!("lang_resume_unwind();\n"),
         }
     }
 
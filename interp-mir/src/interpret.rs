@@ -0,0 +1,725 @@
+use core_mir::{BinOpType, Load, Mir, PreOpType, Reg, Terminator};
+use core_tokens::Span;
+use core_types::{Primitive, Ty, Variant};
+use impl_pass_mir::liveness::Interference;
+use impl_pass_mir::type_check::{self, infer_call, Context};
+use impl_pass_mir::StackFrame;
+
+use crate::compile_to_c::{layout, Overflow};
+use crate::stack_frame::StackFrame as Frame;
+
+use std::alloc::Layout;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Debug)]
+pub enum Error {
+    Panic {
+        message: String,
+        span: Span,
+    },
+    /// a panic while unwinding from another panic, this skips all cleanups
+    DoublePanic {
+        message: String,
+        span: Span,
+    },
+    /// the types of a called function could not be inferred
+    Type(type_check::Error),
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panic { message, .. } => write!(f, "panicked at '{}'", message),
+            Self::DoublePanic { message, .. } => {
+                write!(f, "panicked while panicking at '{}', aborting", message)
+            }
+            Self::Type(error) => write!(f, "{}", error),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// the values of the registers assigned by the frames run so far, which a frame
+/// encoded with `encode::write_continued` reads
+#[derive(Default, Clone)]
+pub struct Environment<'f> {
+    values: HashMap<Reg, Value>,
+    functions: HashMap<Reg, (&'f StackFrame, Reg)>,
+}
+
+impl<'f> Environment<'f> {
+    /// the function held by each register, and the return register of its frame
+    pub fn functions(&self) -> &HashMap<Reg, (&'f StackFrame, Reg)> {
+        &self.functions
+    }
+}
+
+/// run `frame`, whose registers have the given `types`, and write everything
+/// that is printed to `output`
+pub fn interpret<'idt, 'tcx>(
+    frame: &StackFrame,
+    types: &[Ty<'idt, 'tcx>],
+    ctx: Context<'idt, 'tcx>,
+    overflow: Overflow,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    let mut interpreter = Interpreter::new(ctx, overflow, output);
+
+    interpreter.run(frame, types, Vec::new(), None)?;
+
+    Ok(())
+}
+
+/// like `interpret`, but `frame` continues the frames that assigned `environment`,
+/// which is updated with the registers of `frame` if it doesn't panic
+pub fn interpret_continued<'f, 'idt, 'tcx>(
+    frame: &'f StackFrame,
+    types: &[Ty<'idt, 'tcx>],
+    ctx: Context<'idt, 'tcx>,
+    overflow: Overflow,
+    output: &mut dyn Write,
+    environment: &mut Environment<'f>,
+) -> Result<(), Error> {
+    let mut interpreter = Interpreter::new(ctx, overflow, output);
+
+    // every register is kept, the top level bindings are read by the frames that come later
+    let mut activation =
+        Activation::new(types, Locals::Kept(environment.values.clone()), Vec::new());
+    activation.functions = environment.functions.clone();
+
+    interpreter.run_activation(frame, &mut activation)?;
+
+    if let Locals::Kept(values) = activation.locals {
+        environment.values = values;
+    }
+
+    environment.functions = activation.functions;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Unit,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+}
+
+struct Interpreter<'a, 'idt, 'tcx> {
+    ctx: Context<'idt, 'tcx>,
+    overflow: Overflow,
+    output: &'a mut dyn Write,
+    unwinding: bool,
+    /// the types of every function that was called, by the types of its arguments
+    calls: HashMap<(*const StackFrame, Vec<Ty<'idt, 'tcx>>), Vec<Ty<'idt, 'tcx>>>,
+}
+
+/// where an activation keeps the values of its registers
+enum Locals {
+    /// registers that are never live at the same time share storage, at the
+    /// offset each register is assigned
+    Shared { assign: Vec<usize>, frame: Frame },
+    /// every register is kept for as long as the activation
+    Kept(HashMap<Reg, Value>),
+}
+
+/// the state of a single function activation
+///
+/// `'t` is the lifetime of the types of the registers, and `'f` of the frames
+/// of the functions held by the registers
+struct Activation<'t, 'f, 'idt, 'tcx> {
+    types: &'t [Ty<'idt, 'tcx>],
+    locals: Locals,
+    arguments: std::vec::IntoIter<Value>,
+    /// the functions held by each register, functions are zero sized
+    /// so they can't be stored in `locals`
+    functions: HashMap<Reg, (&'f StackFrame, Reg)>,
+    landing_pads: Vec<usize>,
+    unwinding: Option<Error>,
+    /// the block that ran before the current one, which picks the source of each phi
+    previous: Option<usize>,
+}
+
+enum Control {
+    Jump(usize),
+    Return,
+}
+
+impl<'t, 'f, 'idt, 'tcx> Activation<'t, 'f, 'idt, 'tcx> {
+    fn new(types: &'t [Ty<'idt, 'tcx>], locals: Locals, arguments: Vec<Value>) -> Self {
+        Self {
+            types,
+            locals,
+            arguments: arguments.into_iter(),
+            functions: HashMap::new(),
+            landing_pads: Vec::new(),
+            unwinding: None,
+            previous: None,
+        }
+    }
+
+    fn read(&self, reg: Reg) -> Value {
+        let (assign, locals) = match self.locals {
+            Locals::Shared {
+                ref assign,
+                ref frame,
+            } => (assign, frame),
+            Locals::Kept(ref values) => {
+                return values.get(&reg).copied().unwrap_or(Value::Unit);
+            }
+        };
+
+        let offset = assign[reg.0];
+
+        match self.types[reg.0].ty {
+            Variant::Primitive(prim) => match prim {
+                Primitive::Bool => Value::Bool(locals.read::<u8>(offset) != 0),
+                Primitive::I8 => Value::I8(locals.read(offset)),
+                Primitive::I16 => Value::I16(locals.read(offset)),
+                Primitive::I32 => Value::I32(locals.read(offset)),
+                Primitive::I64 => Value::I64(locals.read(offset)),
+                Primitive::U8 => Value::U8(locals.read(offset)),
+                Primitive::U16 => Value::U16(locals.read(offset)),
+                Primitive::U32 => Value::U32(locals.read(offset)),
+                Primitive::U64 => Value::U64(locals.read(offset)),
+                Primitive::F32 => Value::F32(locals.read(offset)),
+                Primitive::F64 => Value::F64(locals.read(offset)),
+            },
+            _ => Value::Unit,
+        }
+    }
+
+    fn write(&mut self, reg: Reg, value: Value) {
+        let (assign, locals) = match self.locals {
+            Locals::Shared {
+                ref assign,
+                ref mut frame,
+            } => (assign, frame),
+            Locals::Kept(ref mut values) => {
+                values.insert(reg, value);
+                return;
+            }
+        };
+
+        let offset = assign[reg.0];
+
+        match value {
+            Value::Unit => (),
+            Value::Bool(x) => locals.write(offset, u8::from(x)),
+            Value::I8(x) => locals.write(offset, x),
+            Value::I16(x) => locals.write(offset, x),
+            Value::I32(x) => locals.write(offset, x),
+            Value::I64(x) => locals.write(offset, x),
+            Value::U8(x) => locals.write(offset, x),
+            Value::U16(x) => locals.write(offset, x),
+            Value::U32(x) => locals.write(offset, x),
+            Value::U64(x) => locals.write(offset, x),
+            Value::F32(x) => locals.write(offset, x),
+            Value::F64(x) => locals.write(offset, x),
+        }
+    }
+}
+
+impl<'a, 'idt, 'tcx> Interpreter<'a, 'idt, 'tcx> {
+    fn new(ctx: Context<'idt, 'tcx>, overflow: Overflow, output: &'a mut dyn Write) -> Self {
+        Self {
+            ctx,
+            overflow,
+            output,
+            unwinding: false,
+            calls: HashMap::new(),
+        }
+    }
+
+    fn panic(&mut self, message: String, span: Span) -> Error {
+        if self.unwinding {
+            Error::DoublePanic { message, span }
+        } else {
+            self.unwinding = true;
+            Error::Panic { message, span }
+        }
+    }
+
+    fn run(
+        &mut self,
+        frame: &StackFrame,
+        types: &[Ty<'idt, 'tcx>],
+        arguments: Vec<Value>,
+        ret: Option<Reg>,
+    ) -> Result<Value, Error> {
+        let (assign, layout) = layout(types, &Interference::new(frame, ret));
+
+        // `Frame` can't hold zero sized layouts
+        let layout = Layout::from_size_align(layout.size().max(1), layout.align()).unwrap();
+
+        let locals = Locals::Shared {
+            assign,
+            frame: Frame::new(layout),
+        };
+
+        let mut activation = Activation::new(types, locals, arguments);
+        self.run_activation(frame, &mut activation)?;
+
+        Ok(ret.map_or(Value::Unit, |ret| activation.read(ret)))
+    }
+
+    fn run_activation<'f>(
+        &mut self,
+        frame: &'f StackFrame,
+        activation: &mut Activation<'_, 'f, 'idt, 'tcx>,
+    ) -> Result<(), Error> {
+        let mut block_idx = 0;
+
+        loop {
+            match self.block(activation, &frame.blocks()[block_idx]) {
+                Ok(Control::Jump(target)) => {
+                    activation.previous = Some(block_idx);
+                    block_idx = target;
+                }
+                Ok(Control::Return) => break,
+                Err(error @ Error::Panic { .. }) => match activation.landing_pads.pop() {
+                    Some(cleanup) => {
+                        activation.unwinding = Some(error);
+                        activation.previous = Some(block_idx);
+                        block_idx = cleanup;
+                    }
+                    None => return Err(error),
+                },
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block<'f>(
+        &mut self,
+        activation: &mut Activation<'_, 'f, 'idt, 'tcx>,
+        block: &'f impl_pass_mir::Block,
+    ) -> Result<Control, Error> {
+        let phis = block
+            .instructions
+            .iter()
+            .take_while(|mir| match **mir {
+                Mir::Phi { .. } => true,
+                _ => false,
+            })
+            .count();
+
+        // phis happen all at once, so every source is read before any phi is written
+        let values = block.instructions[..phis]
+            .iter()
+            .map(|mir| match *mir {
+                Mir::Phi { to, ref from } => {
+                    let previous = activation.previous;
+                    let &(_, from) = from
+                        .iter()
+                        .find(|&&(source, _)| Some(source) == previous)
+                        .expect("a phi has a source for each predecessor");
+
+                    (
+                        to,
+                        activation.read(from),
+                        activation.functions.get(&from).copied(),
+                    )
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        for (to, value, function) in values {
+            if let Some(function) = function {
+                activation.functions.insert(to, function);
+            }
+
+            activation.write(to, value);
+        }
+
+        for mir in block.instructions[phis..].iter() {
+            self.execute(activation, mir)?;
+        }
+
+        self.terminate(activation, &block.terminator)
+    }
+
+    fn execute<'f>(
+        &mut self,
+        activation: &mut Activation<'_, 'f, 'idt, 'tcx>,
+        mir: &'f impl_pass_mir::Mir,
+    ) -> Result<(), Error> {
+        match *mir {
+            Mir::Load { to, from } => {
+                let value = match from {
+                    Load::Bool(x) => Value::Bool(x),
+                    Load::U8(x) => Value::U64(x.into()),
+                    Load::U16(x) => Value::U64(x.into()),
+                    Load::U32(x) => Value::U64(x.into()),
+                    Load::U64(x) => Value::U64(x),
+                    Load::U128(x) => Value::U64(x as u64),
+                    // sign extended, the cast to the register's type truncates it again
+                    Load::I32(x) => Value::U64(x as u64),
+                };
+
+                let value = match activation.types[to.0].ty {
+                    Variant::Primitive(prim) => cast(value, prim),
+                    _ => unreachable!("type check failure"),
+                };
+
+                activation.write(to, value);
+            }
+            Mir::LoadReg { to, from } => {
+                if let Some(&function) = activation.functions.get(&from) {
+                    activation.functions.insert(to, function);
+                }
+
+                let value = activation.read(from);
+                activation.write(to, value);
+            }
+            Mir::Print(reg) => {
+                let value = activation.read(reg);
+                writeln!(self.output, "{}", value)?;
+            }
+            Mir::BinOp {
+                op,
+                out,
+                left,
+                right,
+                span,
+            } => {
+                let left = activation.read(left);
+                let right = activation.read(right);
+
+                match bin_op(op, left, right, self.overflow) {
+                    Ok(value) => activation.write(out, value),
+                    Err(message) => return Err(self.panic(message.to_string(), span)),
+                }
+            }
+            Mir::PreOp { op, out, arg, span } => {
+                let arg = activation.read(arg);
+
+                match pre_op(op, arg, self.overflow) {
+                    Ok(value) => activation.write(out, value),
+                    Err(message) => return Err(self.panic(message.to_string(), span)),
+                }
+            }
+            Mir::Cast { to, from, ty, .. } => {
+                let value = cast(activation.read(from), ty);
+                activation.write(to, value);
+            }
+            Mir::CreateFunc {
+                binding,
+                ret,
+                ref stack_frame,
+            } => {
+                activation.functions.insert(binding, (stack_frame, ret));
+            }
+            Mir::PopArgument { arg } => {
+                let value = activation
+                    .arguments
+                    .next()
+                    .expect("type check failure, missing an argument");
+
+                activation.write(arg, value);
+            }
+            Mir::Panic { ref message, span } => return Err(self.panic(message.clone(), span)),
+            Mir::PushLandingPad { cleanup } => activation.landing_pads.push(cleanup),
+            Mir::PopLandingPad => {
+                activation.landing_pads.pop();
+            }
+            Mir::Phi { .. } => unreachable!("phis are only valid at the start of a block"),
+        }
+
+        Ok(())
+    }
+
+    fn terminate<'f>(
+        &mut self,
+        activation: &mut Activation<'_, 'f, 'idt, 'tcx>,
+        terminator: &'f Terminator,
+    ) -> Result<Control, Error> {
+        let target = match *terminator {
+            Terminator::Goto(target) => target,
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                if activation.read(cond) == Value::Bool(true) {
+                    then
+                } else {
+                    otherwise
+                }
+            }
+            Terminator::Switch {
+                value,
+                ref cases,
+                otherwise,
+            } => {
+                let prim = match activation.types[value.0].ty {
+                    Variant::Primitive(prim) => prim,
+                    _ => unreachable!("type check failure, switch on a non-primitive"),
+                };
+
+                let value = activation.read(value);
+
+                // the cases are truncated to the type of the value, like in C
+                cases
+                    .iter()
+                    .find(|&&(case, _)| cast(Value::U64(case as u64), prim) == value)
+                    .map_or(otherwise, |&(_, target)| target)
+            }
+            Terminator::Return => return Ok(Control::Return),
+            Terminator::Unreachable => unreachable!("reached the end of an unreachable block"),
+            Terminator::Call {
+                func,
+                ref args,
+                ret,
+                next,
+            } => {
+                let arguments = args.iter().map(|&arg| activation.read(arg)).collect();
+                let types = args.iter().map(|&arg| activation.types[arg.0]).collect();
+                let (callee, callee_ret) = activation.functions[&func];
+
+                let ctx = self.ctx;
+                let callee_types = match self.calls.entry((callee as *const _, types)) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let types = infer_call(callee, &entry.key().1, ctx).map_err(Error::Type)?;
+                        entry.insert(types)
+                    }
+                }
+                .clone();
+
+                let value = self.run(callee, &callee_types, arguments, Some(callee_ret))?;
+                activation.write(ret, value);
+
+                next
+            }
+            Terminator::Resume => {
+                return Err(activation
+                    .unwinding
+                    .take()
+                    .expect("resumed unwinding outside of a landing pad"))
+            }
+        };
+
+        Ok(Control::Jump(target))
+    }
+}
+
+fn overflowing<T>(
+    checked: Option<T>,
+    wrapped: T,
+    overflow: Overflow,
+    message: &'static str,
+) -> Result<T, &'static str> {
+    match overflow {
+        Overflow::Checked => checked.ok_or(message),
+        Overflow::Wrapping => Ok(wrapped),
+    }
+}
+
+fn bin_op(
+    op: BinOpType,
+    left: Value,
+    right: Value,
+    overflow: Overflow,
+) -> Result<Value, &'static str> {
+    macro_rules! bin_op {
+        ($($int:ident)*; $($float:ident)*) => {
+            match (left, right) {
+                $((Value::$int(l), Value::$int(r)) => Ok(match op {
+                    BinOpType::Add => Value::$int(overflowing(
+                        l.checked_add(r),
+                        l.wrapping_add(r),
+                        overflow,
+                        "attempt to add with overflow",
+                    )?),
+                    BinOpType::Sub => Value::$int(overflowing(
+                        l.checked_sub(r),
+                        l.wrapping_sub(r),
+                        overflow,
+                        "attempt to subtract with overflow",
+                    )?),
+                    BinOpType::Mul => Value::$int(overflowing(
+                        l.checked_mul(r),
+                        l.wrapping_mul(r),
+                        overflow,
+                        "attempt to multiply with overflow",
+                    )?),
+                    BinOpType::Div => {
+                        if r == 0 {
+                            return Err("attempt to divide by zero");
+                        }
+
+                        // division overflow panics even with wrapping arithmetic
+                        Value::$int(l.checked_div(r).ok_or("attempt to divide with overflow")?)
+                    }
+                    BinOpType::Equal => Value::Bool(l == r),
+                    BinOpType::NotEqual => Value::Bool(l != r),
+                    BinOpType::LessThan => Value::Bool(l < r),
+                    BinOpType::LessThanOrEqual => Value::Bool(l <= r),
+                    BinOpType::GreaterThan => Value::Bool(l > r),
+                    BinOpType::GreaterThanOrEqual => Value::Bool(l >= r),
+                }),)*
+                $((Value::$float(l), Value::$float(r)) => Ok(match op {
+                    BinOpType::Add => Value::$float(l + r),
+                    BinOpType::Sub => Value::$float(l - r),
+                    BinOpType::Mul => Value::$float(l * r),
+                    BinOpType::Div => Value::$float(l / r),
+                    BinOpType::Equal => Value::Bool(l == r),
+                    BinOpType::NotEqual => Value::Bool(l != r),
+                    BinOpType::LessThan => Value::Bool(l < r),
+                    BinOpType::LessThanOrEqual => Value::Bool(l <= r),
+                    BinOpType::GreaterThan => Value::Bool(l > r),
+                    BinOpType::GreaterThanOrEqual => Value::Bool(l >= r),
+                }),)*
+                (Value::Bool(l), Value::Bool(r)) => match op {
+                    BinOpType::Equal => Ok(Value::Bool(l == r)),
+                    BinOpType::NotEqual => Ok(Value::Bool(l != r)),
+                    _ => unreachable!("type check failure, arithmetic on booleans"),
+                },
+                _ => unreachable!("type check failure, mismatched operands"),
+            }
+        };
+    }
+
+    bin_op!(I8 I16 I32 I64 U8 U16 U32 U64; F32 F64)
+}
+
+fn pre_op(op: PreOpType, arg: Value, overflow: Overflow) -> Result<Value, &'static str> {
+    macro_rules! pre_op {
+        ($($signed:ident)*; $($unsigned:ident)*; $($float:ident)*) => {
+            match (op, arg) {
+                (PreOpType::Not, Value::Bool(x)) => Ok(Value::Bool(!x)),
+                $((PreOpType::Not, Value::$signed(x)) => Ok(Value::$signed(!x)),)*
+                $((PreOpType::Not, Value::$unsigned(x)) => Ok(Value::$unsigned(!x)),)*
+                $((PreOpType::Neg, Value::$signed(x)) => Ok(Value::$signed(overflowing(
+                    x.checked_neg(),
+                    x.wrapping_neg(),
+                    overflow,
+                    "attempt to negate with overflow",
+                )?)),)*
+                $((PreOpType::Neg, Value::$float(x)) => Ok(Value::$float(-x)),)*
+                _ => unreachable!("type check failure, invalid operand"),
+            }
+        };
+    }
+
+    pre_op!(I8 I16 I32 I64; U8 U16 U32 U64; F32 F64)
+}
+
+/// convert `value` to `to` with the same semantics as the C backend,
+/// float to int casts saturate and map NaN to 0
+fn cast(value: Value, to: Primitive) -> Value {
+    macro_rules! cast {
+        ($x:expr) => {
+            match to {
+                Primitive::Bool => unreachable!("type check failure, cast to bool"),
+                Primitive::I8 => Value::I8($x as _),
+                Primitive::I16 => Value::I16($x as _),
+                Primitive::I32 => Value::I32($x as _),
+                Primitive::I64 => Value::I64($x as _),
+                Primitive::U8 => Value::U8($x as _),
+                Primitive::U16 => Value::U16($x as _),
+                Primitive::U32 => Value::U32($x as _),
+                Primitive::U64 => Value::U64($x as _),
+                Primitive::F32 => Value::F32($x as _),
+                Primitive::F64 => Value::F64($x as _),
+            }
+        };
+    }
+
+    match value {
+        Value::Bool(x) if to == Primitive::Bool => Value::Bool(x),
+        Value::Bool(x) => cast!(u8::from(x)),
+        Value::I8(x) => cast!(x),
+        Value::I16(x) => cast!(x),
+        Value::I32(x) => cast!(x),
+        Value::I64(x) => cast!(x),
+        Value::U8(x) => cast!(x),
+        Value::U16(x) => cast!(x),
+        Value::U32(x) => cast!(x),
+        Value::U64(x) => cast!(x),
+        Value::F32(x) => cast!(x),
+        Value::F64(x) => cast!(x),
+        Value::Unit => unreachable!("type check failure, cast of a non-primitive"),
+    }
+}
+
+/// format a float like C's `%g`
+fn format_float(x: f64) -> String {
+    if x.is_nan() {
+        return if x.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+    }
+
+    if x.is_infinite() {
+        return if x < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+
+    if x == 0.0 {
+        return if x.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    const PRECISION: i32 = 6;
+
+    fn trim(s: &str) -> &str {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            s
+        }
+    }
+
+    let scientific = format!("{:.*e}", (PRECISION - 1) as usize, x);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+
+    if exponent < -4 || exponent >= PRECISION {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim(mantissa), sign, exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exponent) as usize, x);
+        trim(&fixed).to_string()
+    }
+}
+
+impl fmt::Display for Value {
+    /// matches the output of `printf` in the C backend
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Unit => write!(f, "()"),
+            Self::Bool(x) => write!(f, "{}", u8::from(x)),
+            Self::I8(x) => write!(f, "{}", x),
+            Self::I16(x) => write!(f, "{}", x),
+            Self::I32(x) => write!(f, "{}", x),
+            Self::I64(x) => write!(f, "{}", x),
+            Self::U8(x) => write!(f, "{}", x),
+            Self::U16(x) => write!(f, "{}", x),
+            Self::U32(x) => write!(f, "{}", x),
+            Self::U64(x) => write!(f, "{}", x),
+            Self::F32(x) => write!(f, "{}", format_float(x.into())),
+            Self::F64(x) => write!(f, "{}", format_float(x)),
+        }
+    }
+}
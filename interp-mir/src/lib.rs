@@ -1,2 +1,7 @@
 mod compile_to_c;
-pub use compile_to_c::emit_c;
+mod interpret;
+pub mod runtime;
+mod stack_frame;
+
+pub use compile_to_c::{emit_c, EmitError, Options, Overflow};
+pub use interpret::{interpret, interpret_continued, Environment, Error};
@@ -0,0 +1,15 @@
+use std::io;
+use std::path::Path;
+
+pub const HEADER_NAME: &str = "lang_runtime.h";
+pub const SOURCE_NAME: &str = "lang_runtime.c";
+
+pub const HEADER: &str = include_str!("../runtime/lang_runtime.h");
+pub const SOURCE: &str = include_str!("../runtime/lang_runtime.c");
+
+/// write the runtime support library to `dir`, the generated C code
+/// includes `HEADER_NAME` and must be linked against `SOURCE_NAME`
+pub fn write_runtime(dir: &Path) -> io::Result<()> {
+    std::fs::write(dir.join(HEADER_NAME), HEADER)?;
+    std::fs::write(dir.join(SOURCE_NAME), SOURCE)
+}
@@ -1,4 +1,4 @@
-use std::alloc::{alloc, dealloc, Layout};
+use std::alloc::{alloc_zeroed, dealloc, Layout};
 
 pub struct StackFrame {
     frame: *mut u8,
@@ -11,7 +11,7 @@ impl StackFrame {
 
         unsafe {
             Self {
-                frame: alloc(layout),
+                frame: alloc_zeroed(layout),
                 layout,
             }
         }
@@ -20,6 +20,18 @@ impl StackFrame {
     pub fn ptr(&self) -> *mut u8 {
         self.frame
     }
+
+    pub fn read<T: Copy>(&self, offset: usize) -> T {
+        assert!(offset + std::mem::size_of::<T>() <= self.layout.size());
+
+        unsafe { self.frame.add(offset).cast::<T>().read_unaligned() }
+    }
+
+    pub fn write<T: Copy>(&mut self, offset: usize, value: T) {
+        assert!(offset + std::mem::size_of::<T>() <= self.layout.size());
+
+        unsafe { self.frame.add(offset).cast::<T>().write_unaligned(value) }
+    }
 }
 
 impl Drop for StackFrame {
@@ -273,7 +273,8 @@ impl<T> std::ops::Deref for Str<'_, T> {
 
     fn deref(&self) -> &str {
         unsafe {
-            let ptr = self.ptr.as_ptr();
+            // interned strings are marked by setting the lowest bit of the pointer
+            let ptr = (self.ptr.as_ptr() as usize & !1) as *mut ();
 
             let len = ptr.cast::<usize>().read();
 
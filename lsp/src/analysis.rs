@@ -0,0 +1,227 @@
+//! everything the server knows about a single document
+
+use core_tokens::{Span, Token, Type};
+use impl_pass_resolve::DefKind;
+use impl_session::{Diagnostic, Session};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Let,
+    Parameter,
+}
+
+#[derive(Debug)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// the span of the name
+    pub span: Span,
+    /// the span of the whole `let` statement, or the name of a parameter
+    pub statement: Span,
+    pub is_function: bool,
+    /// defined in the top level scope of the document
+    pub is_top_level: bool,
+    /// the inferred type, this is only known for bindings outside of functions
+    pub ty: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Reference {
+    pub span: Span,
+    pub definition: usize,
+}
+
+/// the token types of the semantic tokens legend, in order
+pub const TOKEN_TYPES: [&str; 9] = [
+    "keyword",
+    "variable",
+    "parameter",
+    "function",
+    "string",
+    "number",
+    "operator",
+    "comment",
+    "type",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Variable,
+    Parameter,
+    Function,
+    String,
+    Number,
+    Operator,
+    Comment,
+    Type,
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub tokens: Vec<(Span, TokenKind)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// the definition of the name at `offset`, whether `offset` is on a use or on the definition
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        let contains = |span: Span| span.start() <= offset && offset <= span.end();
+
+        self.references
+            .iter()
+            .find(|reference| contains(reference.span))
+            .map(|reference| &self.definitions[reference.definition])
+            .or_else(|| self.definitions.iter().find(|def| contains(def.span)))
+    }
+}
+
+pub fn analyze(source: &str) -> Analysis {
+    let session = Session::new();
+    let mut analysis = analyze_in(&session, source);
+    analysis.diagnostics = session.take_diagnostics();
+    analysis
+}
+
+fn analyze_in<'a>(session: &'a Session<'a>, source: &str) -> Analysis {
+    let tokens = session.lex(source);
+
+    let mut analysis = Analysis::default();
+
+    if let Some(hir) = session.parse_hir(source) {
+        let resolution = session.resolve(&hir);
+
+        analysis.definitions = resolution
+            .defs
+            .iter()
+            .map(|def| Definition {
+                name: def.name.to_string(),
+                kind: match def.kind {
+                    DefKind::Parameter => DefinitionKind::Parameter,
+                    DefKind::Let | DefKind::Function | DefKind::Global => DefinitionKind::Let,
+                },
+                span: def.span,
+                statement: def.statement,
+                is_function: def.kind == DefKind::Function,
+                is_top_level: hir.iter().any(|node| node.span == def.statement),
+                ty: None,
+            })
+            .collect();
+
+        analysis.references = resolution
+            .uses
+            .iter()
+            .map(|use_| Reference {
+                span: use_.span,
+                definition: use_.def.0,
+            })
+            .collect();
+
+        let bindings = if resolution.has_errors() {
+            None
+        } else {
+            session
+                .lower_to_mir_with_bindings(hir, &resolution)
+                .and_then(|(mut frame, bindings)| {
+                    let types = session.infer_types(&mut frame)?;
+                    Some((bindings, types))
+                })
+        };
+
+        if let Some((bindings, types)) = bindings {
+            for (def, reg) in bindings {
+                let ty = types[reg.0];
+                let ty = match ty.ty {
+                    core_types::Variant::Function { .. } => "fn".to_string(),
+                    _ => ty.name.to_string(),
+                };
+
+                // the definitions are indexed by `DefId`
+                analysis.definitions[def.0].ty = Some(ty);
+            }
+        }
+    }
+
+    analysis.tokens = semantic_tokens(source, &tokens, &analysis);
+
+    analysis
+}
+
+fn semantic_tokens(
+    source: &str,
+    tokens: &[Token<'_, '_>],
+    analysis: &Analysis,
+) -> Vec<(Span, TokenKind)> {
+    let mut output = Vec::new();
+    let mut after_as = false;
+
+    for token in tokens {
+        comments(source, token.leading_whitespace, &mut output);
+
+        let kind = match token.ty {
+            Type::Keyword(_) => Some(TokenKind::Keyword),
+            Type::Ident(_) if after_as => Some(TokenKind::Type),
+            Type::Ident(_) => {
+                let definition = analysis
+                    .references
+                    .iter()
+                    .find(|reference| reference.span == token.span)
+                    .map(|reference| &analysis.definitions[reference.definition])
+                    .or_else(|| {
+                        analysis
+                            .definitions
+                            .iter()
+                            .find(|def| def.span == token.span)
+                    });
+
+                Some(match definition {
+                    Some(def) if def.kind == DefinitionKind::Parameter => TokenKind::Parameter,
+                    Some(def) if def.is_function => TokenKind::Function,
+                    _ => TokenKind::Variable,
+                })
+            }
+            Type::Str(_) => Some(TokenKind::String),
+            Type::Int(_) | Type::Float(_) => Some(TokenKind::Number),
+            Type::Symbol(_) => Some(TokenKind::Operator),
+            Type::Grouping(..) => None,
+        };
+
+        after_as = match token.ty {
+            Type::Keyword(core_tokens::kw!(as)) => true,
+            _ => false,
+        };
+
+        if let Some(kind) = kind {
+            output.push((token.span, kind));
+        }
+    }
+
+    // comments after the last token
+    let end = tokens.last().map_or(0, |token| token.span.end());
+    comments(source, Span::new(end, source.len()), &mut output);
+
+    output
+}
+
+/// find all comments in the whitespace at `span`, each line of a block comment is a separate token
+fn comments(source: &str, span: Span, output: &mut Vec<(Span, TokenKind)>) {
+    for comment in core_tokens::comments(source, span) {
+        let mut line_start = comment.start();
+
+        for (pos, _) in source[comment.start()..comment.end()].match_indices('\n') {
+            let line_end = comment.start() + pos;
+
+            if line_start < line_end {
+                output.push((Span::new(line_start, line_end), TokenKind::Comment));
+            }
+
+            line_start = line_end + 1;
+        }
+
+        if line_start < comment.end() {
+            output.push((Span::new(line_start, comment.end()), TokenKind::Comment));
+        }
+    }
+}
@@ -0,0 +1,314 @@
+mod analysis;
+mod rpc;
+
+use analysis::{Analysis, DefinitionKind, TOKEN_TYPES};
+use core_tokens::Span;
+use impl_session::Level;
+
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// converts between byte offsets and LSP positions, which count UTF-16 code units
+struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(pos, _)| pos + 1))
+            .collect();
+
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Value {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+
+        let start = self.line_starts[line];
+        let character = self.source[start..offset.min(self.source.len())]
+            .encode_utf16()
+            .count();
+
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, span: Span) -> Value {
+        json!({ "start": self.position(span.start()), "end": self.position(span.end()) })
+    }
+
+    fn offset(&self, position: &Value) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+
+        let start = *self.line_starts.get(line)?;
+        let mut units = 0;
+
+        for (pos, c) in self.source[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(start + pos);
+            }
+
+            units += c.len_utf16();
+        }
+
+        Some(self.source.len())
+    }
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let stdout = io::stdout();
+
+    let mut server = Server {
+        output: stdout.lock(),
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+
+    while let Some(message) = rpc::read(&mut stdin)? {
+        if let Some(code) = server.handle(message)? {
+            std::process::exit(code)
+        }
+    }
+
+    Ok(())
+}
+
+impl<W: Write> Server<W> {
+    /// handle a single message, and return the exit code if the server should exit
+    fn handle(&mut self, message: Value) -> io::Result<Option<i32>> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    // the full text is sent on every change
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                        "full": true,
+                    },
+                },
+                "serverInfo": { "name": "lsp" },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Value::Null)
+            }
+            "exit" => return Ok(Some(if self.shutdown { 0 } else { 1 })),
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+
+                self.documents.insert(uri.to_string(), text.to_string());
+                self.publish_diagnostics(uri)?;
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+
+                if let Some(text) = text {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    self.publish_diagnostics(uri)?;
+                }
+
+                None
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+                self.documents.remove(uri);
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )?;
+                None
+            }
+            "textDocument/hover" => Some(self.with_document(params, hover)),
+            "textDocument/definition" => Some(self.with_document(params, definition)),
+            "textDocument/documentSymbol" => Some(self.with_document(params, document_symbols)),
+            "textDocument/semanticTokens/full" => Some(self.with_document(params, semantic_tokens)),
+            _ => {
+                // unknown notifications are ignored
+                if let Some(id) = id {
+                    let error = rpc::error(id, rpc::METHOD_NOT_FOUND, "method not found");
+                    rpc::write(&mut self.output, &error)?;
+                }
+
+                return Ok(None);
+            }
+        };
+
+        match (id, result) {
+            (Some(id), Some(result)) => rpc::write(&mut self.output, &rpc::response(id, result))?,
+            (Some(id), None) => {
+                let error = rpc::error(id, rpc::INVALID_PARAMS, "expected a notification");
+                rpc::write(&mut self.output, &error)?
+            }
+            (None, _) => (),
+        }
+
+        Ok(None)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        rpc::write(&mut self.output, &rpc::notification(method, params))
+    }
+
+    /// analyze the document in `params`, and build a response from it
+    fn with_document(
+        &self,
+        params: &Value,
+        f: impl FnOnce(&Value, &str, &LineIndex<'_>, &Analysis) -> Value,
+    ) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        match self.documents.get(uri) {
+            Some(source) => {
+                let analysis = analysis::analyze(source);
+                f(params, uri, &LineIndex::new(source), &analysis)
+            }
+            None => Value::Null,
+        }
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let source = &self.documents[uri];
+        let index = LineIndex::new(source);
+        let analysis = analysis::analyze(source);
+
+        let diagnostics = analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                // diagnostics without a location point at the start of the document
+                let span = diagnostic.span.unwrap_or_else(|| Span::new(0, 0));
+                let severity = match diagnostic.level {
+                    Level::Error => 1,
+                    Level::Warning => 2,
+                };
+
+                json!({
+                    "range": index.range(span),
+                    "severity": severity,
+                    "source": "lsp",
+                    "message": diagnostic.message,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let params = json!({ "uri": uri, "diagnostics": diagnostics });
+        self.notify("textDocument/publishDiagnostics", params)
+    }
+}
+
+fn hover(params: &Value, _: &str, index: &LineIndex<'_>, analysis: &Analysis) -> Value {
+    let definition = index
+        .offset(&params["position"])
+        .and_then(|offset| analysis.definition_at(offset));
+
+    let definition = match definition {
+        Some(definition) => definition,
+        None => return Value::Null,
+    };
+
+    let text = match (definition.kind, &definition.ty) {
+        (DefinitionKind::Let, Some(ty)) => format!("let {}: {}", definition.name, ty),
+        (DefinitionKind::Let, None) => format!("let {}", definition.name),
+        (DefinitionKind::Parameter, _) => format!("{} (parameter)", definition.name),
+    };
+
+    json!({
+        "contents": { "kind": "markdown", "value": format!("```\n{}\n```", text) },
+    })
+}
+
+fn definition(params: &Value, uri: &str, index: &LineIndex<'_>, analysis: &Analysis) -> Value {
+    index
+        .offset(&params["position"])
+        .and_then(|offset| analysis.definition_at(offset))
+        .map_or(
+            Value::Null,
+            |definition| json!({ "uri": uri, "range": index.range(definition.span) }),
+        )
+}
+
+fn document_symbols(_: &Value, _: &str, index: &LineIndex<'_>, analysis: &Analysis) -> Value {
+    const FUNCTION: u32 = 12;
+    const VARIABLE: u32 = 13;
+
+    let symbols = analysis
+        .definitions
+        .iter()
+        .filter(|def| def.is_top_level && def.kind == DefinitionKind::Let)
+        .map(|def| {
+            json!({
+                "name": def.name,
+                "detail": def.ty,
+                "kind": if def.is_function { FUNCTION } else { VARIABLE },
+                "range": index.range(def.statement),
+                "selectionRange": index.range(def.span),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Value::Array(symbols)
+}
+
+fn semantic_tokens(_: &Value, _: &str, index: &LineIndex<'_>, analysis: &Analysis) -> Value {
+    let mut tokens = analysis.tokens.clone();
+    tokens.sort_by_key(|(span, _)| span.start());
+
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut prev_line, mut prev_start) = (0, 0);
+
+    for (span, kind) in tokens {
+        let start = index.position(span.start());
+        let line = start["line"].as_u64().unwrap();
+        let character = start["character"].as_u64().unwrap();
+        let length = index.source[span.start()..span.end()]
+            .encode_utf16()
+            .count() as u64;
+
+        // positions are relative to the previous token
+        let delta_start = if line == prev_line {
+            character - prev_start
+        } else {
+            character
+        };
+
+        data.extend_from_slice(&[line - prev_line, delta_start, length, kind as u64, 0]);
+
+        prev_line = line;
+        prev_start = character;
+    }
+
+    json!({ "data": data })
+}
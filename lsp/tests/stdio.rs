@@ -0,0 +1,260 @@
+use serde_json::{json, Value};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("could not start the server");
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut client = Self {
+            child,
+            stdin,
+            stdout,
+            next_id: 0,
+        };
+
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        let content = message.to_string();
+        write!(
+            self.stdin,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+        .unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut content_length = 0;
+
+        loop {
+            let mut header = String::new();
+            self.stdout.read_line(&mut header).unwrap();
+
+            let header = header.trim_end();
+
+            if header.is_empty() {
+                break;
+            }
+
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+
+        let mut content = vec![0; content_length];
+        self.stdout.read_exact(&mut content).unwrap();
+        serde_json::from_slice(&content).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// send a request and wait for its response, skipping any notifications
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+
+        loop {
+            let message = self.receive();
+
+            if message["id"] == id {
+                return message["result"].clone();
+            }
+        }
+    }
+
+    /// wait for the next diagnostics of a document
+    fn diagnostics(&mut self) -> Vec<Value> {
+        loop {
+            let message = self.receive();
+
+            if message["method"] == "textDocument/publishDiagnostics" {
+                return message["params"]["diagnostics"].as_array().unwrap().clone();
+            }
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "lang", "version": 0, "text": text },
+            }),
+        );
+    }
+
+    fn shutdown(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+
+        let status = self.child.wait().unwrap();
+        assert!(status.success());
+    }
+}
+
+fn position(line: u32, character: u32) -> Value {
+    json!({ "line": line, "character": character })
+}
+
+#[test]
+fn diagnostics_on_open_and_change() {
+    let mut client = Client::start();
+
+    client.open("file:///a.lang", "let a = 1;\nprint a;\n");
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": "file:///a.lang", "version": 1 },
+            "contentChanges": [{ "text": "let a = true;\nlet b = a + 1;\nprint b;\n" }],
+        }),
+    );
+
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"]["start"], position(1, 8));
+
+    client.shutdown();
+}
+
+#[test]
+fn survives_unimplemented_features() {
+    let mut client = Client::start();
+
+    // string literals can't be lowered to MIR yet
+    client.open("file:///d.lang", "let s = \"hello\";\nprint s;\n");
+
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"]["start"], position(0, 8));
+    assert_eq!(
+        diagnostics[0]["message"],
+        "string literals can't be lowered to MIR yet"
+    );
+
+    client.open("file:///e.lang", "let a = 1;\nprint a;\n");
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    client.shutdown();
+}
+
+#[test]
+fn hover_and_definition() {
+    let mut client = Client::start();
+
+    client.open("file:///b.lang", "let a = 1;\nlet b = a + 2;\nprint b;\n");
+    client.diagnostics();
+
+    let document = json!({ "uri": "file:///b.lang" });
+
+    let hover = client.request(
+        "textDocument/hover",
+        json!({ "textDocument": document, "position": position(1, 8) }),
+    );
+    assert_eq!(hover["contents"]["value"], "```\nlet a: i32\n```");
+
+    let definition = client.request(
+        "textDocument/definition",
+        json!({ "textDocument": document, "position": position(2, 6) }),
+    );
+    assert_eq!(definition["range"]["start"], position(1, 4));
+
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": document }),
+    );
+    let names = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["a", "b"]);
+
+    client.shutdown();
+}
+
+#[test]
+fn hover_shadowed_binding() {
+    let mut client = Client::start();
+
+    client.open(
+        "file:///f.lang",
+        "let a = true;\nprint a;\nlet a = 1;\nprint a;\n",
+    );
+    client.diagnostics();
+
+    let document = json!({ "uri": "file:///f.lang" });
+
+    let hover = client.request(
+        "textDocument/hover",
+        json!({ "textDocument": document, "position": position(0, 4) }),
+    );
+    assert_eq!(hover["contents"]["value"], "```\nlet a: bool\n```");
+
+    let hover = client.request(
+        "textDocument/hover",
+        json!({ "textDocument": document, "position": position(3, 6) }),
+    );
+    assert_eq!(hover["contents"]["value"], "```\nlet a: i32\n```");
+
+    client.shutdown();
+}
+
+#[test]
+fn semantic_tokens() {
+    let mut client = Client::start();
+
+    client.open("file:///c.lang", "// one\n/* a /* b */\nc */ let a = 1;\n");
+    client.diagnostics();
+
+    let tokens = client.request(
+        "textDocument/semanticTokens/full",
+        json!({ "textDocument": { "uri": "file:///c.lang" } }),
+    );
+
+    // comment, both lines of the nested block comment, let, a, =, 1, ;
+    #[rustfmt::skip]
+    let expected = json!([
+        0, 0, 6, 7, 0,
+        1, 0, 12, 7, 0,
+        1, 0, 4, 7, 0,
+        0, 5, 3, 0, 0,
+        0, 4, 1, 1, 0,
+        0, 2, 1, 6, 0,
+        0, 2, 1, 5, 0,
+        0, 1, 1, 6, 0,
+    ]);
+
+    assert_eq!(tokens["data"], expected);
+
+    client.shutdown();
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: main <command> [options] <file>
+       main repl [options]
+
+commands:
+    lex                 print the tokens of <file>
+    parse               print <file> as it was parsed
+    check               type check <file>
+    build               compile <file> to an executable
+    run                 build and run <file>
+    emit --stage=<s>    print a stage of the compiler, one of tokens, hir, hir-json, mir, types or c
+    fmt                 format <file> in place
+    repl                evaluate statements interactively
+
+options:
+    --out-dir <dir>     the directory to put build artifacts in (default: target_c)
+    --cc <path>         the C compiler to use (default: $CC or cc)
+    --cflag <flag>      pass <flag> to the C compiler, may be repeated
+    -O <level>          the optimization level, 0 to 3, the MIR is optimised from 1 (default: 0)
+    --stats             print what the MIR optimisations changed
+    --no-inline         don't inline calls to small functions when optimising the MIR
+    --release           wrap on integer overflow instead of panicking
+    --interpret         run the program in the interpreter instead of building it
+    --check             with `fmt`, only check that <file> is formatted
+    -h, --help          print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Lex,
+    Parse,
+    Check,
+    Build,
+    Run,
+    Emit(Stage),
+    Fmt,
+    Repl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Tokens,
+    Hir,
+    HirJson,
+    Mir,
+    Types,
+    C,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    /// the input file, this is only `None` for `repl`
+    pub file: Option<PathBuf>,
+    pub out_dir: PathBuf,
+    pub cc: String,
+    pub cflags: Vec<String>,
+    pub opt_level: u8,
+    /// print what the MIR optimisations changed
+    pub stats: bool,
+    pub inline: bool,
+    pub overflow: interp_mir::Overflow,
+    pub interpret: bool,
+    /// don't write the formatted file, fail if it isn't formatted
+    pub check: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Help,
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidValue(&'static str, String),
+    MissingFile,
+    UnexpectedArgument(String),
+    MissingStage,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Help => write!(f, "help requested"),
+            Self::MissingCommand => write!(f, "no command given"),
+            Self::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            Self::UnknownOption(option) => write!(f, "unknown option `{}`", option),
+            Self::MissingValue(option) => write!(f, "`{}` expects a value", option),
+            Self::InvalidValue(option, value) => {
+                write!(f, "invalid value `{}` for `{}`", value, option)
+            }
+            Self::MissingFile => write!(f, "no input file given"),
+            Self::UnexpectedArgument(arg) => write!(f, "unexpected argument `{}`", arg),
+            Self::MissingStage => write!(f, "`emit` expects a `--stage`"),
+        }
+    }
+}
+
+impl Stage {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tokens" => Some(Self::Tokens),
+            "hir" => Some(Self::Hir),
+            "hir-json" => Some(Self::HirJson),
+            "mir" => Some(Self::Mir),
+            "types" => Some(Self::Types),
+            "c" => Some(Self::C),
+            _ => None,
+        }
+    }
+}
+
+/// parse the command line arguments, not including the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, Error> {
+    let mut args = args.into_iter();
+
+    let command = match args.next().as_deref() {
+        None => return Err(Error::MissingCommand),
+        Some("-h") | Some("--help") => return Err(Error::Help),
+        Some("lex") => Command::Lex,
+        Some("parse") => Command::Parse,
+        Some("check") => Command::Check,
+        Some("build") => Command::Build,
+        Some("run") => Command::Run,
+        // the stage is filled in once all options are parsed
+        Some("emit") => Command::Emit(Stage::Tokens),
+        Some("fmt") => Command::Fmt,
+        Some("repl") => Command::Repl,
+        Some(command) => return Err(Error::UnknownCommand(command.to_string())),
+    };
+
+    let mut file = None;
+    let mut stage = None;
+    let mut out_dir = PathBuf::from("target_c");
+    let mut cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut cflags = Vec::new();
+    let mut opt_level = 0;
+    let mut stats = false;
+    let mut inline = true;
+    let mut overflow = interp_mir::Overflow::Checked;
+    let mut interpret = false;
+    let mut check = false;
+
+    while let Some(arg) = args.next() {
+        // options may be given as `--name value` or `--name=value`
+        let (name, inline_value) = match arg.find('=') {
+            Some(pos) if arg.starts_with("--") => (&arg[..pos], Some(arg[pos + 1..].to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        let mut value = |option: &'static str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(Error::MissingValue(option))
+        };
+
+        match name {
+            "-h" | "--help" => return Err(Error::Help),
+            "--stage" => {
+                let name = value("--stage")?;
+                stage = Some(
+                    Stage::from_name(&name).ok_or_else(|| Error::InvalidValue("--stage", name))?,
+                );
+            }
+            "--out-dir" => out_dir = value("--out-dir")?.into(),
+            "--cc" => cc = value("--cc")?,
+            "--cflag" => cflags.push(value("--cflag")?),
+            "-O" | "--opt-level" => {
+                let level = value("-O")?;
+                opt_level = match level.parse() {
+                    Ok(level @ 0..=3) => level,
+                    _ => return Err(Error::InvalidValue("-O", level)),
+                };
+            }
+            "-O0" | "-O1" | "-O2" | "-O3" => opt_level = name.as_bytes()[2] - b'0',
+            "--stats" => stats = true,
+            "--no-inline" => inline = false,
+            "--release" => overflow = interp_mir::Overflow::Wrapping,
+            "--interpret" => interpret = true,
+            "--check" => check = true,
+            _ if name.starts_with('-') => return Err(Error::UnknownOption(arg)),
+            _ if file.is_none() && command != Command::Repl => file = Some(PathBuf::from(arg)),
+            _ => return Err(Error::UnexpectedArgument(arg)),
+        }
+    }
+
+    let command = match command {
+        Command::Emit(_) => Command::Emit(stage.ok_or(Error::MissingStage)?),
+        command => command,
+    };
+
+    if file.is_none() && command != Command::Repl {
+        return Err(Error::MissingFile);
+    }
+
+    Ok(Options {
+        command,
+        file,
+        out_dir,
+        cc,
+        cflags,
+        opt_level,
+        stats,
+        inline,
+        overflow,
+        interpret,
+        check,
+    })
+}
@@ -1,106 +1,357 @@
-use lib_arena::{cache::Cache, local::LocalUniqueArena};
-use lib_intern::{Interner, Store};
-
-fn main() -> std::io::Result<()> {
-    let _ = std::fs::create_dir("target_c");
-    let _ = std::fs::create_dir("target_c/fragments");
-    let _ = std::fs::create_dir("target_c/fragment_objects");
-
-    let digest = {
-        let file = std::env::args().nth(1).unwrap();
-        let file = std::fs::read_to_string(file).unwrap();
-
-        let intern = Interner::new();
-        let small_strings = Interner::new();
-        let long_strings = Store::new();
-
-        let context = impl_lexer::Context {
-            intern: &intern,
-            small_strings: &small_strings,
-            long_strings: &long_strings,
-            max_small_string_size: 64,
+mod cli;
+mod repl;
+
+use cli::{Command, Options, Stage};
+
+use impl_session::{Diagnostic, OptOptions, Session};
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// the exit status when the program could not be compiled
+const EXIT_FAILURE: i32 = 1;
+/// the exit status when the command line could not be parsed
+const EXIT_USAGE: i32 = 2;
+/// the exit status of a program that panicked, this matches the C runtime
+const EXIT_PANIC: i32 = 101;
+
+#[derive(Debug)]
+enum Error {
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    Io(io::Error),
+    Spawn(String, io::Error),
+    CCompiler(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(path, error) => write!(f, "could not read `{}`: {}", path.display(), error),
+            Self::Write(path, error) => {
+                write!(f, "could not write `{}`: {}", path.display(), error)
+            }
+            Self::Io(error) => write!(f, "{}", error),
+            Self::Spawn(program, error) => write!(f, "could not run `{}`: {}", program, error),
+            Self::CCompiler(stderr) => write!(f, "the C compiler failed\n{}", stderr),
+        }
+    }
+}
+
+fn main() {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(cli::Error::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::USAGE);
+            std::process::exit(EXIT_USAGE)
+        }
+    };
+
+    match drive(&options) {
+        Ok(0) => (),
+        Ok(code) => std::process::exit(code),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(EXIT_FAILURE)
+        }
+    }
+}
+
+/// run the command in `options`, and return the exit status
+fn drive(options: &Options) -> Result<i32, Error> {
+    let path = match options.file {
+        Some(ref path) => path,
+        None => {
+            repl::run(options.overflow)?;
+            return Ok(0);
+        }
+    };
+
+    let file_name = path.to_string_lossy();
+    let file = std::fs::read_to_string(path).map_err(|error| Error::Read(path.clone(), error))?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    let session = Session::new();
+
+    let result = match options.command {
+        Command::Fmt => format(options, &session, path, &file),
+        _ => compile(options, &session, &file, &file_name, &mut stdout),
+    };
+
+    for diagnostic in session.take_diagnostics() {
+        eprintln!("{}", diagnostic.render(&file_name, &file));
+    }
+
+    // diagnostics were already reported
+    Ok(result?.unwrap_or(EXIT_FAILURE))
+}
+
+/// run the stages of the compiler needed by `options.command`,
+/// returns `None` if a stage reported an error
+fn compile<'a>(
+    options: &Options,
+    session: &'a Session<'a>,
+    file: &str,
+    file_name: &str,
+    stdout: &mut io::StdoutLock<'_>,
+) -> Result<Option<i32>, Error> {
+    if let Command::Lex | Command::Emit(Stage::Tokens) = options.command {
+        for token in session.lex(file) {
+            writeln!(
+                stdout,
+                "{}..{} {:?}",
+                token.span.start(),
+                token.span.end(),
+                token.ty
+            )?;
+        }
+
+        return Ok(Some(0));
+    }
+
+    let hir = match session.parse_hir(file) {
+        Some(hir) => hir,
+        None => return Ok(None),
+    };
+
+    match options.command {
+        Command::Parse => {
+            for node in hir.iter() {
+                writeln!(stdout, "{}", node)?;
+            }
+
+            return Ok(Some(0));
+        }
+        Command::Emit(Stage::Hir) => {
+            write!(stdout, "{}", core_hir::dump::to_sexpr(&hir))?;
+            return Ok(Some(0));
+        }
+        Command::Emit(Stage::HirJson) => {
+            write!(stdout, "{}", core_hir::dump::to_json(&hir))?;
+            return Ok(Some(0));
+        }
+        _ => (),
+    }
+
+    let resolution = session.resolve(&hir);
+
+    if resolution.has_errors() {
+        return Ok(None);
+    }
+
+    let mut digest = match session.lower_to_mir(hir, &resolution) {
+        Some(digest) => digest,
+        None => return Ok(None),
+    };
+
+    let mut types = match session.infer_types(&mut digest) {
+        Some(types) => types,
+        None => return Ok(None),
+    };
+
+    if options.opt_level > 0 {
+        let opt_options = OptOptions {
+            inline: options.inline,
+            wrapping: options.overflow == interp_mir::Overflow::Wrapping,
         };
-        let lexer = impl_lexer::Lexer::new(&file, context);
 
-        let arena = LocalUniqueArena::new();
-        let exprs = LocalUniqueArena::new();
-        let context = impl_pass_hir::Context {
-            arena: &arena,
-            exprs: &exprs,
+        let (optimised, stats) = match session.optimise(&mut digest, opt_options) {
+            Some(optimised) => optimised,
+            None => return Ok(None),
         };
-        let hir_parser = impl_pass_hir::HirParser::new(lexer, context);
 
-        impl_pass_mir::encode::write(hir_parser).expect("could not encode for some reason")
+        if options.stats {
+            eprintln!("{}", stats);
+        }
+
+        types = optimised;
+    }
+
+    match options.command {
+        Command::Check => return Ok(Some(0)),
+        Command::Emit(Stage::Mir) => {
+            write!(stdout, "{}", digest)?;
+            return Ok(Some(0));
+        }
+        Command::Emit(Stage::Types) => {
+            for (i, ty) in types.iter().enumerate() {
+                writeln!(stdout, "type_of {}: {:?}", i, ty)?;
+            }
+
+            return Ok(Some(0));
+        }
+        Command::Run if options.interpret => {
+            return match session.interpret(&digest, &types, options.overflow, stdout) {
+                Ok(()) => Ok(Some(0)),
+                Err(error) => match error {
+                    interp_mir::Error::Panic { span, .. }
+                    | interp_mir::Error::DoublePanic { span, .. } => {
+                        let (line, column) = span.line_col(file);
+
+                        stdout.flush()?;
+                        eprintln!("{}, {}:{}:{}", error, file_name, line, column);
+
+                        Ok(Some(EXIT_PANIC))
+                    }
+                    interp_mir::Error::Io(error) => Err(Error::Io(error)),
+                    interp_mir::Error::Type(error) => {
+                        session.report(Diagnostic::error(error.message, error.span));
+                        Ok(None)
+                    }
+                },
+            };
+        }
+        _ => (),
+    }
+
+    let c_options = interp_mir::Options {
+        overflow: options.overflow,
+        file_name,
+        source: file,
+    };
+
+    if let Command::Emit(Stage::C) = options.command {
+        if !session.emit_c(digest, stdout, &c_options)? {
+            return Ok(None);
+        }
+
+        return Ok(Some(0));
+    }
+
+    let exe = match build(options, session, digest, &c_options)? {
+        Some(exe) => exe,
+        None => return Ok(None),
+    };
+
+    if options.command == Command::Build {
+        return Ok(Some(0));
+    }
+
+    stdout.flush()?;
+
+    let status = std::process::Command::new(&exe)
+        .status()
+        .map_err(|error| Error::Spawn(exe.display().to_string(), error))?;
+
+    // a program killed by a signal has no exit code
+    Ok(Some(status.code().unwrap_or(EXIT_FAILURE)))
+}
+
+/// format the file at `path`, or check that it is formatted with `--check`
+fn format<'a>(
+    options: &Options,
+    session: &'a Session<'a>,
+    path: &Path,
+    file: &str,
+) -> Result<Option<i32>, Error> {
+    let formatted = match session.format(file) {
+        Some(formatted) => formatted,
+        None => return Ok(None),
     };
 
-    let ty_ctx = Cache::new();
-    let ident = Interner::new();
-
-    println!("CODE");
-    println!("{}", digest);
-
-    let types = impl_pass_mir::type_check::infer_types(
-        &digest,
-        impl_pass_mir::type_check::Context {
-            ident: &ident,
-            ty: &ty_ctx,
-        },
-    )
-    .unwrap();
-
-    println!("CODE (type checked)");
-    println!("{}", digest);
-    println!("\nBLOCK DATA");
-
-    for (i, block) in digest.blocks().iter().enumerate() {
-        println!(
-            "BLOCK({}):\
-            \n\tparents: {:?}\
-            \n\tchildren: {:?}",
-            i, block.meta.parents, block.meta.children
-        );
+    if formatted == file {
+        return Ok(Some(0));
+    }
+
+    if options.check {
+        eprintln!("`{}` is not formatted", path.display());
+        return Ok(Some(EXIT_FAILURE));
     }
 
-    println!("\nTYPE INFO");
+    std::fs::write(path, formatted).map_err(|error| Error::Write(path.to_path_buf(), error))?;
+
+    Ok(Some(0))
+}
+
+/// compile `digest` to an executable in the output directory, and return its path,
+/// returns `None` if the C backend reported an error
+fn build<'a>(
+    options: &Options,
+    session: &'a Session<'a>,
+    digest: impl_pass_mir::StackFrame,
+    c_options: &interp_mir::Options<'_>,
+) -> Result<Option<PathBuf>, Error> {
+    let stem = options
+        .file
+        .as_ref()
+        .and_then(|file| file.file_stem())
+        .map_or("out".into(), |stem| stem.to_string_lossy());
 
-    for (i, ty) in types.iter().enumerate() {
-        println!("type_of {}: {:?}", i, ty);
+    let runtime_dir = options.out_dir.join("runtime");
+    let objects_dir = options.out_dir.join("objects");
+
+    std::fs::create_dir_all(&runtime_dir)?;
+    std::fs::create_dir_all(&objects_dir)?;
+
+    let c_path = options.out_dir.join(format!("{}.c", stem));
+    let c_file = std::fs::File::create(&c_path)?;
+
+    if !session.emit_c(digest, io::BufWriter::new(c_file), c_options)? {
+        return Ok(None);
     }
 
-    println!("\nPROGRAM OUTPUT:\n");
-
-    // interp_mir::interpret(digest);
-
-    let file = std::fs::File::create("target_c/fragments/test.c")?;
-
-    let ty_names = lib_intern::Interner::new();
-    interp_mir::emit_c(digest, &file, &ty_names)?;
-
-    let out = std::process::Command::new("gcc")
-        .arg("-Iinc")
-        .arg("-c")
-        .arg("target_c/fragments/test.c")
-        .arg("-o")
-        .arg("target_c/fragment_objects/test.o")
-        .arg("-O3")
-        .stdout(std::process::Stdio::piped())
-        .spawn()?
-        .wait()?;
-    std::process::Command::new("gcc")
-        .arg("target_c/fragment_objects/test.o")
-        .arg("-o")
-        .arg("target_c/test.exe")
-        .stdout(std::process::Stdio::piped())
-        .spawn()?
-        .wait()?;
-    // std::process::Command::new("./target_c/test.exe")
-    //     .stdout(std::process::Stdio::piped())
-    //     .stderr(std::process::Stdio::piped())
-    //     .spawn()?
-    //     .wait()?;
-    // while let Some(hir_let) = hir_parser.parse() {
-    //     dbg!(hir_let);
-    // }
-
-    Ok(())
+    interp_mir::runtime::write_runtime(&runtime_dir)?;
+
+    let runtime_object = objects_dir.join("lang_runtime.o");
+    let object = objects_dir.join(format!("{}.o", stem));
+    let exe = options.out_dir.join(&*stem);
+
+    run_c_compiler(options, |cc| {
+        cc.arg("-c")
+            .arg(runtime_dir.join(interp_mir::runtime::SOURCE_NAME))
+            .arg("-o")
+            .arg(&runtime_object);
+    })?;
+
+    run_c_compiler(options, |cc| {
+        cc.arg("-I")
+            .arg(&runtime_dir)
+            .arg("-c")
+            .arg(&c_path)
+            .arg("-o")
+            .arg(&object);
+    })?;
+
+    run_c_compiler(options, |cc| {
+        cc.arg(&object).arg(&runtime_object).arg("-o").arg(&exe);
+    })?;
+
+    Ok(Some(exe))
+}
+
+fn run_c_compiler(
+    options: &Options,
+    args: impl FnOnce(&mut std::process::Command),
+) -> Result<(), Error> {
+    let mut cc = std::process::Command::new(&options.cc);
+
+    cc.arg(format!("-O{}", options.opt_level))
+        .args(&options.cflags);
+
+    args(&mut cc);
+
+    let output = cc
+        .output()
+        .map_err(|error| Error::Spawn(options.cc.clone(), error))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::CCompiler(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
 }
@@ -0,0 +1,354 @@
+use core_tokens::Span;
+use core_types::Ty;
+use impl_pass_mir::encode::Globals;
+use impl_pass_mir::{type_check, StackFrame};
+use impl_session::{Diagnostic, Session};
+
+use lib_arena::local::LocalSharedArena;
+
+use std::io::{self, BufRead, Write};
+
+const FILE_NAME: &str = "<repl>";
+
+/// the binding that holds the value of an expression that was typed in
+const IT: &str = "__it";
+
+const HELP: &str = "\
+statements are evaluated and their bindings are kept for later inputs,
+expressions are evaluated and their value and type are printed
+
+commands:
+    :type <expr>    print the type of <expr>
+    :mir <expr>     print the MIR of <expr>
+    :reset          forget all bindings
+    :help           print this message
+    :quit           exit the repl";
+
+/// Every input is compiled on its own, into a frame that continues the frames of the
+/// inputs before it, so it can read and assign their top level bindings and call the
+/// functions they declared. Only the new frame is run, and the values of its registers
+/// are kept for the inputs after it, unless it panics.
+struct Repl<'a> {
+    session: &'a Session<'a>,
+    overflow: interp_mir::Overflow,
+    /// the frame of every input that was run, the functions they declared live in them
+    frames: &'a LocalSharedArena<StackFrame, 16>,
+    /// the top level bindings of every input that was run
+    globals: Globals<'a>,
+    /// the types of the registers of `globals`
+    types: Vec<Ty<'a, 'a>>,
+    /// the values of the registers of `globals`
+    environment: interp_mir::Environment<'a>,
+}
+
+/// an input compiled after every input that was run
+struct Compiled<'a> {
+    frame: StackFrame,
+    types: Vec<Ty<'a, 'a>>,
+    globals: Globals<'a>,
+}
+
+enum Exit {
+    Quit,
+    Reset,
+}
+
+pub fn run(overflow: interp_mir::Overflow) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+
+    // `:reset` starts over in a new session, which frees everything the old one allocated
+    while let Exit::Reset = run_session(overflow, &mut stdin)? {}
+
+    Ok(())
+}
+
+fn run_session(overflow: interp_mir::Overflow, stdin: &mut impl BufRead) -> io::Result<Exit> {
+    let session = Session::new();
+    let frames = LocalSharedArena::new();
+
+    let mut repl = Repl {
+        session: &session,
+        overflow,
+        frames: &frames,
+        globals: Globals::default(),
+        types: Vec::new(),
+        environment: interp_mir::Environment::default(),
+    };
+
+    let mut input = String::new();
+
+    loop {
+        print!("{}", if input.is_empty() { ">> " } else { ".. " });
+        io::stdout().flush()?;
+
+        if stdin.read_line(&mut input)? == 0 {
+            println!();
+            return Ok(Exit::Quit);
+        }
+
+        // keep reading lines until every brace is closed
+        if open_groups(&input) > 0 {
+            continue;
+        }
+
+        let line = std::mem::take(&mut input);
+        let line = line.trim();
+
+        match line {
+            "" => (),
+            ":quit" | ":q" => return Ok(Exit::Quit),
+            ":help" | ":h" => println!("{}", HELP),
+            ":reset" => return Ok(Exit::Reset),
+            _ => {
+                if let Some(expr) = line.strip_prefix(":type ") {
+                    repl.print_type(expr.trim());
+                } else if let Some(expr) = line.strip_prefix(":mir ") {
+                    repl.print_mir(expr.trim());
+                } else if line.starts_with(':') {
+                    println!("unknown command `{}`, try `:help`", line);
+                } else {
+                    repl.eval(line);
+                }
+            }
+        }
+    }
+}
+
+/// the number of `{`, `(` and `[` that are not closed in `input`, skipping strings and comments
+fn open_groups(input: &str) -> isize {
+    let mut depth = 0;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            _ => (),
+        }
+    }
+
+    depth
+}
+
+impl<'a> Repl<'a> {
+    /// compile `source` after every input that was run
+    ///
+    /// `offset` is the start of the input in `source`, diagnostics are
+    /// reported relative to it
+    fn compile(&self, source: &str, offset: usize) -> Option<Compiled<'a>> {
+        let session = self.session;
+
+        let compiled = (|| {
+            let hir = session.parse_hir(source)?;
+            let resolution = session.resolve_continued(&hir, &self.globals);
+
+            if resolution.has_errors() {
+                return None;
+            }
+
+            let (mut frame, globals) =
+                session.lower_to_mir_continued(hir, &resolution, &self.globals)?;
+
+            let known = type_check::Known {
+                types: self.types.clone(),
+                functions: self.environment.functions().clone(),
+            };
+
+            let types = session.infer_types_continued(&mut frame, &self.globals, &known)?;
+
+            Some(Compiled {
+                frame,
+                types,
+                globals,
+            })
+        })();
+
+        for diagnostic in session.take_diagnostics() {
+            let diagnostic = Diagnostic {
+                span: diagnostic.span.and_then(|span| shift(span, offset)),
+                ..diagnostic
+            };
+
+            println!("{}", diagnostic.render(FILE_NAME, &source[offset..]));
+        }
+
+        compiled
+    }
+
+    /// run `compiled` and keep its bindings, returns everything it printed
+    ///
+    /// if it panics, the output and the panic are shown, and the bindings are
+    /// left as they were before it ran
+    fn run(&mut self, compiled: Compiled<'a>, source: &str, offset: usize) -> Option<String> {
+        let Compiled {
+            frame,
+            types,
+            globals,
+        } = compiled;
+
+        // the frame outlives this input, the functions it declares can be called later
+        let frames = self.frames;
+        let frame = frames.alloc(frame);
+        let mut output = Vec::new();
+
+        let result = self.session.interpret_continued(
+            frame,
+            &types,
+            self.overflow,
+            &mut output,
+            &mut self.environment,
+        );
+
+        let output = String::from_utf8_lossy(&output).into_owned();
+
+        match result {
+            Ok(()) => {
+                self.globals = globals;
+                self.types = types;
+                Some(output)
+            }
+            Err(error) => {
+                print!("{}", output);
+
+                match error {
+                    interp_mir::Error::Panic { span, .. }
+                    | interp_mir::Error::DoublePanic { span, .. } => match shift(span, offset) {
+                        Some(span) => {
+                            let (line, column) = span.line_col(&source[offset..]);
+                            println!("{}, {}:{}:{}", error, FILE_NAME, line, column);
+                        }
+                        None => println!("{}", error),
+                    },
+                    _ => println!("error: {}", error),
+                }
+
+                None
+            }
+        }
+    }
+
+    /// evaluate a statement or an expression
+    fn eval(&mut self, input: &str) {
+        // anything that doesn't parse as a statement is treated as an expression,
+        // the parser may consume a lone identifier without producing anything
+        let is_statement = match self.session.parse_hir(input) {
+            Some(hir) => !hir.is_empty(),
+            None => false,
+        };
+        self.session.take_diagnostics();
+
+        if !is_statement {
+            return self.eval_expr(input);
+        }
+
+        let source = format!("{}\n", input);
+
+        let compiled = match self.compile(&source, 0) {
+            Some(compiled) => compiled,
+            None => return,
+        };
+
+        // show the type of every binding that was created or shadowed
+        let mut changed = compiled
+            .globals
+            .bindings
+            .iter()
+            .filter(|&(name, reg)| {
+                self.globals.bindings.get(name) != Some(reg) && name.as_str() != IT
+            })
+            .map(|(name, &reg)| (reg, format!("{} : {}", name, compiled.types[reg.0].name)))
+            .collect::<Vec<_>>();
+
+        changed.sort_by_key(|&(reg, _)| reg.0);
+
+        if let Some(output) = self.run(compiled, &source, 0) {
+            print!("{}", output);
+
+            for (_, binding) in changed {
+                println!("{}", binding);
+            }
+        }
+    }
+
+    fn eval_expr(&mut self, expr: &str) {
+        let ty = match self.type_of(expr) {
+            Some(ty) => ty,
+            None => return,
+        };
+
+        // only primitives can be printed
+        if let core_types::Variant::Primitive(_) = ty.ty {
+            let prefix = format!("let {} = ", IT);
+            let source = format!("{}{};\nprint {};\n", prefix, expr, IT);
+
+            let output = self
+                .compile(&source, prefix.len())
+                .and_then(|compiled| self.run(compiled, &source, prefix.len()));
+
+            if let Some(output) = output {
+                println!("{} : {}", output.trim_end(), ty.name);
+            }
+        } else {
+            println!("{} : {}", expr, ty.name);
+        }
+    }
+
+    /// compile `expr` bound to `IT`, without running it
+    fn compile_expr(&self, expr: &str) -> Option<Compiled<'a>> {
+        let prefix = format!("let {} = ", IT);
+        let source = format!("{}{};\n", prefix, expr);
+
+        self.compile(&source, prefix.len())
+    }
+
+    /// the type of `expr`
+    fn type_of(&self, expr: &str) -> Option<Ty<'a, 'a>> {
+        let compiled = self.compile_expr(expr)?;
+
+        let (_, &reg) = compiled
+            .globals
+            .bindings
+            .iter()
+            .find(|&(name, _)| name.as_str() == IT)?;
+
+        Some(compiled.types[reg.0])
+    }
+
+    fn print_type(&self, expr: &str) {
+        if let Some(ty) = self.type_of(expr) {
+            println!("{} : {}", expr, ty.name);
+        }
+    }
+
+    /// print the MIR of `expr`, which only holds `expr`, the bindings it reads
+    /// are registers of the earlier inputs
+    fn print_mir(&self, expr: &str) {
+        if let Some(compiled) = self.compile_expr(expr) {
+            print!("{}", compiled.frame);
+        }
+    }
+}
+
+/// move `span` to be relative to `offset`, if it starts after `offset`
+fn shift(span: Span, offset: usize) -> Option<Span> {
+    if span.start() >= offset {
+        Some(Span::new(span.start() - offset, span.end() - offset))
+    } else {
+        None
+    }
+}
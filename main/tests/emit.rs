@@ -0,0 +1,104 @@
+//! Tests for `main emit --stage=hir-json`, which external tools read the spans of
+
+use serde_json::Value;
+
+use std::process::Command;
+
+const SOURCE: &str = "\
+let sum = 1 + 2;
+if sum == 3 {
+    print sum;
+} else {
+    panic \"wrong\";
+}
+let twice = x -> x * 2;
+";
+
+/// the kind of every node in `json` that has a span, with the source it spans
+fn spanned<'a>(json: &Value, source: &'a str, nodes: &mut Vec<(usize, usize, String, &'a str)>) {
+    match *json {
+        Value::Object(ref fields) => {
+            if let Some(span) = fields["span"].as_array() {
+                let start = span[0].as_u64().unwrap() as usize;
+                let end = span[1].as_u64().unwrap() as usize;
+                let kind = fields["kind"].as_str().unwrap().to_string();
+
+                nodes.push((start, end, kind, &source[start..end]));
+            }
+
+            for field in fields.values() {
+                spanned(field, source, nodes);
+            }
+        }
+        Value::Array(ref items) => {
+            for item in items {
+                spanned(item, source, nodes);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[test]
+fn hir_json_spans() {
+    let path = std::env::temp_dir().join(format!("hir-json-{}.lang", std::process::id()));
+    std::fs::write(&path, SOURCE).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(&["emit", "--stage=hir-json"])
+        .arg(&path)
+        .output()
+        .expect("could not run main");
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("the dump is invalid JSON");
+    let mut nodes = Vec::new();
+    spanned(&json, SOURCE, &mut nodes);
+
+    // the fields of an object aren't kept in order, so the nodes are put in source order,
+    // with the nodes that contain others first
+    nodes.sort_by_key(|&(start, end, _, _)| (start, std::cmp::Reverse(end)));
+
+    let nodes = nodes
+        .iter()
+        .map(|&(_, _, ref kind, text)| (kind.as_str(), text))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        nodes,
+        [
+            ("let", "let sum = 1 + 2;"),
+            ("ident", "sum"),
+            ("binary", "1 + 2"),
+            ("int", "1"),
+            ("int", "2"),
+            (
+                "if",
+                "if sum == 3 {\n    print sum;\n} else {\n    panic \"wrong\";\n}"
+            ),
+            ("branch", "sum == 3 {\n    print sum;\n}"),
+            ("binary", "sum == 3"),
+            ("name", "sum"),
+            ("int", "3"),
+            ("scope", "{\n    print sum;\n}"),
+            ("print", "print sum;"),
+            ("name", "sum"),
+            ("scope", "{\n    panic \"wrong\";\n}"),
+            ("panic", "panic \"wrong\";"),
+            ("let", "let twice = x -> x * 2;"),
+            ("ident", "twice"),
+            ("func", "x -> x * 2"),
+            ("param", "x"),
+            ("binary", "x * 2"),
+            ("name", "x"),
+            ("int", "2"),
+        ]
+    );
+}
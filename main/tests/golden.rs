@@ -0,0 +1,722 @@
+//! Runs every program in `tests/golden` through the whole pipeline and compares
+//! the results against the expectation files next to it.
+//!
+//! For a program `name.lang` the expectations are
+//! * `name.stdout` - the output of the program
+//! * `name.stderr` - diagnostics and panic messages
+//! * `name.tokens`, `name.hir`, `name.mir`, `name.types` - dumps of each stage
+//! * `name.fmt` - the program after formatting, which must not change when it is formatted again
+//! * `name.stats` - what each MIR optimisation changed
+//! * `name.ssa` - the MIR after each step of the SSA pipeline
+//!
+//! The HIR of every program is also printed back as source, which must parse to the same HIR,
+//! and the MIR is printed as text, which must parse to the same MIR.
+//!
+//! The programs in `tests/golden/mir` are written directly in the textual MIR, they skip the
+//! front end and are type checked and run in the interpreter with the same expectation files.
+//!
+//! A missing `stdout` or `stderr` file means the output must be empty, dumps are
+//! only checked if their file exists. Run with `BLESS=1` to update the expectations,
+//! a `// dump: tokens hir mir types fmt stats ssa` line in a program creates the dumps it names.
+//! Programs are run with overflow checks, unless they have a `// overflow: wrapping` line,
+//! and their small functions are inlined, unless they have an `// inline: no` line, like
+//! `--no-inline`.
+//!
+//! The MIR of every program is also converted to SSA form, has its small functions inlined,
+//! its constants propagated, its redundant values removed, its loop-invariant code hoisted, its
+//! dead code and needless jumps removed, and is converted back, and it is run again after each
+//! step, which must not change its output. MIR programs may not be in SSA form, so only their
+//! dead code and needless jumps are removed, before they are converted back.
+//!
+//! Programs are run in the interpreter, and if a C compiler is available (`CC`, or `cc`)
+//! they are also compiled with the C backend which must produce the same output. Programs
+//! that use something the C backend can't emit yet are skipped, which is noted in the output.
+
+use impl_session::{Diagnostic, HirNode, Session};
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+const DUMPS: [&str; 7] = ["tokens", "hir", "mir", "types", "fmt", "stats", "ssa"];
+
+#[derive(Default)]
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    /// the panic message of the program, the only part of `stderr` the compiled C program prints
+    panic: String,
+    tokens: String,
+    hir: String,
+    mir: String,
+    types: String,
+    fmt: String,
+    stats: String,
+    ssa: String,
+    /// the HIR printed back as source
+    printed: String,
+    /// the stdout and panic message of the program after each SSA step
+    steps: Vec<(&'static str, String, String)>,
+}
+
+impl Outcome {
+    fn get(&self, kind: &str) -> &str {
+        match kind {
+            "stdout" => &self.stdout,
+            "stderr" => &self.stderr,
+            "tokens" => &self.tokens,
+            "hir" => &self.hir,
+            "mir" => &self.mir,
+            "types" => &self.types,
+            "fmt" => &self.fmt,
+            "stats" => &self.stats,
+            "ssa" => &self.ssa,
+            _ => unreachable!("unknown expectation {}", kind),
+        }
+    }
+}
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let bless = std::env::var_os("BLESS").map_or(false, |bless| bless != "0");
+    let cc = c_compiler();
+
+    let mut programs = Vec::new();
+
+    for (dir, extension) in [(dir.clone(), "lang"), (dir.join("mir"), "mir")].iter() {
+        programs.extend(
+            std::fs::read_dir(dir)
+                .expect("could not read the golden test directory")
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == *extension)),
+        );
+    }
+
+    programs.sort();
+
+    if cc.is_none() {
+        println!("no C compiler found, only the interpreter will be tested");
+    }
+
+    let mut failed = Vec::new();
+
+    for program in programs.iter() {
+        let name = program.strip_prefix(&dir).unwrap().to_str().unwrap();
+
+        match run_test(program, cc.as_deref(), bless) {
+            Ok(None) => println!("test golden/{} ... ok", name),
+            Ok(Some(note)) => println!("test golden/{} ... ok ({})", name, note),
+            Err(report) => {
+                println!("test golden/{} ... FAILED\n{}", name, report);
+                failed.push(name);
+            }
+        }
+    }
+
+    println!(
+        "\ngolden test result: {} passed; {} failed",
+        programs.len() - failed.len(),
+        failed.len()
+    );
+
+    if !failed.is_empty() {
+        println!("\nfailures:");
+
+        for name in failed {
+            println!("    golden/{}", name);
+        }
+
+        println!("\nrun with `BLESS=1` to update the expectations");
+
+        std::process::exit(1);
+    }
+}
+
+/// run the test for `program`, and return a note about what was skipped, if anything was
+fn run_test(program: &Path, cc: Option<&str>, bless: bool) -> Result<Option<String>, String> {
+    let source = std::fs::read_to_string(program).map_err(|err| err.to_string())?;
+    let file_name = program.file_name().unwrap().to_str().unwrap();
+
+    let is_mir = program.extension().map_or(false, |ext| ext == "mir");
+    let outcome = run_pipeline(&source, file_name, is_mir);
+
+    if bless {
+        return bless_outcome(program, &source, &outcome)
+            .map(|()| None)
+            .map_err(|err| err.to_string());
+    }
+
+    let mut report = String::new();
+
+    for &kind in ["stdout", "stderr"].iter().chain(DUMPS.iter()) {
+        let path = program.with_extension(kind);
+
+        // the MIR programs are their own MIR dump
+        if path == program {
+            continue;
+        }
+
+        let expected = match std::fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(_) if DUMPS.contains(&kind) => continue,
+            Err(_) => String::new(),
+        };
+
+        diff(&mut report, kind, &expected, outcome.get(kind));
+    }
+
+    // formatting must be idempotent
+    if !outcome.fmt.is_empty() {
+        let session = Session::new();
+
+        match session.format(&outcome.fmt) {
+            Some(formatted) => diff(
+                &mut report,
+                "fmt (formatted twice)",
+                &outcome.fmt,
+                &formatted,
+            ),
+            None => report.push_str("the formatted program does not parse\n"),
+        }
+    }
+
+    // printing the HIR must round-trip
+    if !outcome.printed.is_empty() {
+        let session = Session::new();
+
+        match session.parse_hir(&outcome.printed) {
+            Some(hir) => diff(
+                &mut report,
+                "printed HIR (parsed and printed again)",
+                &outcome.printed,
+                &print_hir(&hir),
+            ),
+            None => report.push_str("the printed HIR does not parse\n"),
+        }
+    }
+
+    // printing the MIR must round-trip
+    if !outcome.mir.is_empty() {
+        match impl_pass_mir::parse::parse(&outcome.mir) {
+            Ok(frame) => diff(
+                &mut report,
+                "MIR (parsed and printed again)",
+                &outcome.mir,
+                &frame.to_string(),
+            ),
+            Err(error) => {
+                let (line, column) = error.span.line_col(&outcome.mir);
+                let _ = writeln!(
+                    report,
+                    "the printed MIR does not parse: {} at {}:{}",
+                    error, line, column
+                );
+            }
+        }
+    }
+
+    for (step, stdout, panic) in outcome.steps.iter() {
+        diff(
+            &mut report,
+            &format!("stdout ({})", step),
+            &outcome.stdout,
+            stdout,
+        );
+        diff(
+            &mut report,
+            &format!("stderr ({})", step),
+            &outcome.panic,
+            panic,
+        );
+    }
+
+    let mut note = None;
+
+    // the C backend can only run programs that type check
+    if let (Some(cc), false, false) = (cc, is_mir, outcome.mir.is_empty()) {
+        match run_c(program, &source, cc) {
+            Ok(CBackend::Ran(stdout, stderr)) => {
+                diff(&mut report, "stdout (C backend)", &outcome.stdout, &stdout);
+                diff(&mut report, "stderr (C backend)", &outcome.panic, &stderr);
+            }
+            Ok(CBackend::Unsupported(reason)) => {
+                note = Some(format!("skipped the C backend, {}", reason));
+            }
+            Err(err) => {
+                let _ = writeln!(report, "C backend: {}", err);
+            }
+        }
+    }
+
+    if report.is_empty() {
+        Ok(note)
+    } else {
+        Err(report)
+    }
+}
+
+/// lex, parse, encode, type check and interpret `source`, recording every stage,
+/// or only type check and interpret it if it is MIR
+fn run_pipeline(source: &str, file_name: &str, is_mir: bool) -> Outcome {
+    let mut outcome = Outcome::default();
+    let session = Session::new();
+
+    if is_mir {
+        if let Some(digest) = session.parse_mir(source) {
+            run_mir(&session, digest.clone(), source, file_name, &mut outcome);
+            run_ssa(&session, digest, source, file_name, true, &mut outcome);
+        }
+    } else {
+        run_stages(&session, source, file_name, &mut outcome);
+    }
+
+    outcome.stderr = outcome.panic.clone();
+
+    for diagnostic in session.take_diagnostics() {
+        let _ = writeln!(outcome.stderr, "{}", diagnostic.render(file_name, source));
+    }
+
+    outcome
+}
+
+fn run_stages<'a>(session: &'a Session<'a>, source: &str, file_name: &str, outcome: &mut Outcome) {
+    for token in session.lex(source) {
+        let _ = writeln!(
+            outcome.tokens,
+            "{}..{} {:?}",
+            token.span.start(),
+            token.span.end(),
+            token.ty
+        );
+    }
+
+    let hir = match session.parse_hir(source) {
+        Some(hir) => hir,
+        None => return,
+    };
+
+    outcome.hir = core_hir::dump::to_sexpr(&hir);
+    outcome.printed = print_hir(&hir);
+
+    if let Some(formatted) = session.format(source) {
+        outcome.fmt = formatted;
+    }
+
+    let resolution = session.resolve(&hir);
+
+    if resolution.has_errors() {
+        return;
+    }
+
+    if let Some(digest) = session.lower_to_mir(hir, &resolution) {
+        run_mir(session, digest.clone(), source, file_name, outcome);
+        run_ssa(session, digest, source, file_name, false, outcome);
+    }
+}
+
+/// type check and interpret `digest`
+fn run_mir<'a>(
+    session: &'a Session<'a>,
+    mut digest: impl_pass_mir::StackFrame,
+    source: &str,
+    file_name: &str,
+    outcome: &mut Outcome,
+) {
+    let types = match session.infer_types(&mut digest) {
+        Some(types) => types,
+        None => return,
+    };
+
+    let _ = write!(outcome.mir, "{}", digest);
+
+    for (i, ty) in types.iter().enumerate() {
+        let _ = writeln!(outcome.types, "type_of {}: {:?}", i, ty);
+    }
+
+    let (stdout, result) = interpret(session, &digest, &types, overflow(source));
+    outcome.stdout = stdout;
+
+    if let Err(error) = result {
+        match panic_message(&error, source, file_name) {
+            Some(message) => outcome.panic = message,
+            None => session.report(Diagnostic::error(error.to_string(), None)),
+        }
+    }
+}
+
+/// an optimisation run on the SSA form of a program, it is given the types inferred for the
+/// frame and the source of the program, which holds its directives, and returns what it
+/// changed, or `None` if the frame is invalid afterwards
+type SsaStep = for<'a> fn(
+    &'a Session<'a>,
+    &mut impl_pass_mir::StackFrame,
+    &[core_types::Ty<'a, 'a>],
+    &str,
+) -> Option<String>;
+
+/// the optimisations in the order they run, each is named by the step the program is run after
+const SSA_STEPS: [(&str, SsaStep); 6] = [
+    ("after inlining", |session, digest, _, source| {
+        if !inlines(source) {
+            return Some(impl_pass_mir::inline::Stats::default().to_string());
+        }
+
+        session.inline(digest).map(|stats| stats.to_string())
+    }),
+    ("after constant propagation", |session, digest, types, _| {
+        let stats = session.propagate_constants(digest, types)?;
+        Some(stats.to_string())
+    }),
+    ("after value numbering", |session, digest, _, _| {
+        session.number_values(digest).map(|stats| stats.to_string())
+    }),
+    // strength reduction moves where arithmetic overflows, so it only runs if it wraps
+    (
+        "after loop optimisation",
+        |session, digest, types, source| {
+            let wrapping = overflow(source) == interp_mir::Overflow::Wrapping;
+            let stats = session.optimise_loops(digest, types, wrapping)?;
+            Some(stats.to_string())
+        },
+    ),
+    ("after dead code elimination", |session, digest, _, _| {
+        session
+            .eliminate_dead_code(digest)
+            .map(|stats| stats.to_string())
+    }),
+    ("after CFG simplification", |session, digest, _, _| {
+        session.simplify_cfg(digest).map(|stats| stats.to_string())
+    }),
+];
+
+/// the steps from this one on work on frames both in and out of SSA form
+const ANY_FORM: usize = 4;
+
+/// convert `digest` to SSA form, optimise it and convert it back, and run it after each
+/// step, MIR programs may already be in SSA form, so they only go through the steps that
+/// work on any frame before they are converted back
+fn run_ssa<'a>(
+    session: &'a Session<'a>,
+    mut digest: impl_pass_mir::StackFrame,
+    source: &str,
+    file_name: &str,
+    is_mir: bool,
+    outcome: &mut Outcome,
+) {
+    // only programs that type check are run
+    if outcome.mir.is_empty() {
+        return;
+    }
+
+    let (steps, types) = if is_mir {
+        (&SSA_STEPS[ANY_FORM..], session.infer_types(&mut digest))
+    } else {
+        if !session.construct_ssa(&mut digest) {
+            return;
+        }
+
+        let types = run_ssa_step(
+            session,
+            &mut digest,
+            "in SSA form",
+            source,
+            file_name,
+            outcome,
+        );
+
+        (&SSA_STEPS[..], types)
+    };
+
+    let mut types = match types {
+        Some(types) => types,
+        None => return,
+    };
+
+    for &(step, optimise) in steps {
+        let stats = match optimise(session, &mut digest, &types, source) {
+            Some(stats) => stats,
+            None => return,
+        };
+
+        let _ = writeln!(outcome.stats, "{}: {}", step, stats);
+
+        types = match run_ssa_step(session, &mut digest, step, source, file_name, outcome) {
+            Some(types) => types,
+            None => return,
+        };
+    }
+
+    if session.destruct_ssa(&mut digest) {
+        run_ssa_step(
+            session,
+            &mut digest,
+            "after SSA destruction",
+            source,
+            file_name,
+            outcome,
+        );
+    }
+}
+
+fn run_ssa_step<'a>(
+    session: &'a Session<'a>,
+    digest: &mut impl_pass_mir::StackFrame,
+    step: &'static str,
+    source: &str,
+    file_name: &str,
+    outcome: &mut Outcome,
+) -> Option<Vec<core_types::Ty<'a, 'a>>> {
+    let types = session.infer_types(digest)?;
+
+    let _ = write!(outcome.ssa, "// {}\n{}", step, digest);
+
+    let (stdout, result) = interpret(session, digest, &types, overflow(source));
+    let panic = match result {
+        Ok(()) => String::new(),
+        Err(error) => panic_message(&error, source, file_name)
+            .unwrap_or_else(|| format!("error: {}\n", error)),
+    };
+
+    outcome.steps.push((step, stdout, panic));
+
+    Some(types)
+}
+
+fn interpret<'a>(
+    session: &'a Session<'a>,
+    digest: &impl_pass_mir::StackFrame,
+    types: &[core_types::Ty<'a, 'a>],
+    overflow: interp_mir::Overflow,
+) -> (String, Result<(), interp_mir::Error>) {
+    let mut stdout = Vec::new();
+
+    let result = session.interpret(digest, types, overflow, &mut stdout);
+    let stdout = String::from_utf8(stdout).expect("the program printed invalid utf-8");
+
+    (stdout, result)
+}
+
+/// the message a panic prints, `None` if `error` isn't a panic
+fn panic_message(error: &interp_mir::Error, source: &str, file_name: &str) -> Option<String> {
+    match *error {
+        interp_mir::Error::Panic { span, .. } | interp_mir::Error::DoublePanic { span, .. } => {
+            let (line, column) = span.line_col(source);
+            Some(format!("{}, {}:{}:{}\n", error, file_name, line, column))
+        }
+        _ => None,
+    }
+}
+
+fn print_hir(hir: &[HirNode<'_>]) -> String {
+    hir.iter().map(|node| format!("{}\n", node)).collect()
+}
+
+fn c_compiler() -> Option<String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    Command::new(&cc)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|_| cc)
+}
+
+/// what happened when a program was built with the C backend
+enum CBackend {
+    /// the program was built and run, with its stdout and stderr
+    Ran(String, String),
+    /// the C backend can't emit the program yet, for the given reason
+    Unsupported(String),
+}
+
+/// build `program` with the C backend, then run it
+fn run_c(program: &Path, source: &str, cc: &str) -> Result<CBackend, String> {
+    let file_name = program.file_name().unwrap().to_str().unwrap();
+    let out_dir = std::env::temp_dir()
+        .join("lang-golden")
+        .join(program.file_stem().unwrap());
+
+    std::fs::create_dir_all(&out_dir).map_err(|err| err.to_string())?;
+
+    let session = Session::new();
+    let digest = session
+        .parse_hir(source)
+        .and_then(|hir| {
+            let resolution = session.resolve(&hir);
+            session.lower_to_mir(hir, &resolution)
+        })
+        .ok_or("could not lower the program to MIR")?;
+
+    // the warnings of the front end are already checked against `name.stderr`
+    session.take_diagnostics();
+
+    let c_path = out_dir.join("program.c");
+    let c_file = std::fs::File::create(&c_path).map_err(|err| err.to_string())?;
+
+    let emitted = session
+        .emit_c(
+            digest,
+            &c_file,
+            &interp_mir::Options {
+                overflow: overflow(source),
+                file_name,
+                source,
+            },
+        )
+        .map_err(|err| err.to_string())?;
+
+    if !emitted {
+        let reason = session
+            .take_diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        return Ok(CBackend::Unsupported(reason));
+    }
+
+    interp_mir::runtime::write_runtime(&out_dir).map_err(|err| err.to_string())?;
+
+    let exe = out_dir.join("program.exe");
+
+    compile(
+        Command::new(cc)
+            .arg("-I")
+            .arg(&out_dir)
+            .arg(&c_path)
+            .arg(out_dir.join(interp_mir::runtime::SOURCE_NAME))
+            .arg("-o")
+            .arg(&exe),
+    )?;
+
+    let output = Command::new(&exe)
+        .output()
+        .map_err(|err| format!("could not run {}: {}", exe.display(), err))?;
+
+    Ok(CBackend::Ran(
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    ))
+}
+
+fn compile(command: &mut Command) -> Result<(), String> {
+    let output = command
+        .output()
+        .map_err(|err| format!("could not run the C compiler: {}", err))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the C compiler failed\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+/// how `source` handles integer overflow, a `// overflow: wrapping` line makes it wrap,
+/// otherwise it panics
+fn overflow(source: &str) -> interp_mir::Overflow {
+    let wrapping = source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// overflow:"))
+        .any(|mode| mode.trim() == "wrapping");
+
+    if wrapping {
+        interp_mir::Overflow::Wrapping
+    } else {
+        interp_mir::Overflow::Checked
+    }
+}
+
+/// whether the small functions of `source` are inlined, an `// inline: no` line keeps
+/// them from being inlined
+fn inlines(source: &str) -> bool {
+    !source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// inline:"))
+        .any(|inline| inline.trim() == "no")
+}
+
+/// the dumps requested by a `// dump: ...` line in `source`
+fn requested_dumps(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// dump:"))
+        .flat_map(str::split_whitespace)
+        .collect()
+}
+
+fn bless_outcome(program: &Path, source: &str, outcome: &Outcome) -> std::io::Result<()> {
+    let requested = requested_dumps(source);
+
+    for &kind in ["stdout", "stderr"].iter() {
+        let path = program.with_extension(kind);
+        let output = outcome.get(kind);
+
+        if output.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        } else {
+            std::fs::write(&path, output)?;
+        }
+    }
+
+    for &kind in DUMPS.iter() {
+        let path = program.with_extension(kind);
+
+        if path == *program {
+            continue;
+        }
+
+        if path.exists() || requested.contains(&kind) {
+            std::fs::write(&path, outcome.get(kind))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// append a line diff between `expected` and `actual` to `report`, if they differ
+fn diff(report: &mut String, kind: &str, expected: &str, actual: &str) {
+    if expected == actual {
+        return;
+    }
+
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // longest common subsequence of the lines, from the back
+    let mut lcs = vec![vec![0_usize; actual.len() + 1]; expected.len() + 1];
+
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let _ = writeln!(report, "--- expected {}\n+++ actual {}", kind, kind);
+
+    let (mut i, mut j) = (0, 0);
+
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            let _ = writeln!(report, "  {}", expected[i]);
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            let _ = writeln!(report, "+ {}", actual[j]);
+            j += 1;
+        } else {
+            let _ = writeln!(report, "- {}", expected[i]);
+            i += 1;
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Tests for the traversals of the HIR in `core_hir::visit_mut` and `core_hir::fold`,
+//! which no pass uses to rewrite the HIR yet
+
+use core_hir::fold::Fold;
+use core_hir::visit_mut::{self, VisitMut};
+use core_hir::{Expr, Hir, Node, Pattern, SimpleExpr};
+use core_tokens::{Ident, Span};
+use impl_session::{HirNode, Session};
+use lib_arena::local::LocalUniqueArena;
+
+fn print(hir: &[HirNode<'_>]) -> String {
+    hir.iter().map(|node| format!("{}\n", node)).collect()
+}
+
+/// the name bound by the first `let` of `source`
+fn ident<'a>(session: &'a Session<'a>, source: &str) -> Ident<'a> {
+    match session.parse_hir(source).unwrap()[0].val {
+        Hir::Let {
+            pat:
+                Node {
+                    val: Pattern::Ident(ident, _),
+                    ..
+                },
+            ..
+        } => ident,
+        ref hir => panic!("expected a `let`, found {:?}", hir),
+    }
+}
+
+/// renames every use and binding of `from` to `to`
+struct Rename<'idt> {
+    from: &'static str,
+    to: Ident<'idt>,
+    renamed: usize,
+}
+
+impl<'idt> Rename<'idt> {
+    fn rename(&mut self, ident: &mut Ident<'idt>) {
+        if *ident == *self.from {
+            *ident = self.to;
+            self.renamed += 1;
+        }
+    }
+}
+
+impl<'str, 'idt, 'hir> VisitMut<'str, 'idt, 'hir> for Rename<'idt> {
+    fn visit_hir_mut(&mut self, node: &mut Node<Hir<'str, 'idt, 'hir>>) {
+        if let Hir::Print(ref mut ident) = node.val {
+            self.rename(&mut ident.val);
+        }
+
+        visit_mut::walk_hir_mut(self, node)
+    }
+
+    fn visit_simple_expr_mut(&mut self, expr: &mut Node<SimpleExpr<'str, 'idt>>) {
+        if let SimpleExpr::Ident(ref mut ident) = expr.val {
+            self.rename(ident);
+        }
+    }
+
+    fn visit_pattern_mut(&mut self, pat: &mut Pattern<'str, 'idt>, span: Span) {
+        if let Pattern::Ident(ref mut ident, _) = *pat {
+            self.rename(ident);
+        }
+
+        visit_mut::walk_pattern_mut(self, pat, span)
+    }
+}
+
+#[test]
+fn visit_mut_renames_identifiers() {
+    let session = Session::new();
+    let mut hir = session
+        .parse_hir(
+            "let x = 1;
+            let y = x + 2;
+            if y == 3 {
+                let x = y * x;
+                print x;
+            }
+            let f = a -> a * x;",
+        )
+        .unwrap();
+
+    let mut rename = Rename {
+        from: "x",
+        to: ident(&session, "let count = 0;"),
+        renamed: 0,
+    };
+
+    rename.visit_scope_mut(&mut hir);
+
+    let expected = session
+        .parse_hir(
+            "let count = 1;
+            let y = count + 2;
+            if y == 3 {
+                let count = y * count;
+                print count;
+            }
+            let f = a -> a * count;",
+        )
+        .unwrap();
+
+    assert_eq!(rename.renamed, 6);
+    assert_eq!(print(&hir), print(&expected));
+}
+
+/// rebuilds the HIR in its own arena without changing it
+struct Rebuild<'str, 'idt, 'out> {
+    exprs: &'out LocalUniqueArena<Node<Expr<'str, 'idt, 'out>>, 16>,
+}
+
+impl<'str, 'idt, 'hir, 'out> Fold<'str, 'idt, 'hir, 'out> for Rebuild<'str, 'idt, 'out> {
+    fn alloc(
+        &mut self,
+        expr: Node<Expr<'str, 'idt, 'out>>,
+    ) -> &'out mut Node<Expr<'str, 'idt, 'out>> {
+        self.exprs.alloc(expr)
+    }
+}
+
+#[test]
+fn fold_into_a_new_arena_round_trips() {
+    let session = Session::new();
+    let source = "let add = x -> y -> x + y;
+        let three = add 1 2 as u32;
+        let four = 1 + three * 2;
+        loop {
+            if four == 3 {
+                break
+            } else if four > 0 {
+                print four;
+            } else {
+                print three;
+            }
+        }
+        panic \"done\";";
+
+    let hir = session.parse_hir(source).unwrap();
+    let exprs = LocalUniqueArena::new();
+    let folded = Rebuild { exprs: &exprs }.fold_scope(&hir);
+
+    assert_eq!(folded, hir);
+    assert_eq!(print(&folded), print(&hir));
+}
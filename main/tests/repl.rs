@@ -0,0 +1,127 @@
+//! Tests for `main repl`, which are scripted through its stdin
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// everything the repl prints for `input`, including its prompts
+fn repl(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("could not start the repl");
+
+    // the repl exits when its stdin is closed
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes()).unwrap();
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn keeps_bindings_between_inputs() {
+    let output = repl(
+        "let a = 1;
+let b = a + 2;
+b
+let f = x -> x * 2;
+f b
+",
+    );
+
+    assert_eq!(
+        output,
+        ">> a : i32
+>> b : i32
+>> 3 : i32
+>> f : $fn(0)
+>> 6 : i32
+>> \n"
+    );
+}
+
+#[test]
+fn reads_until_every_brace_is_closed() {
+    let output = repl(
+        "let a = 1;
+if a == 1 {
+    print a;
+}
+let b = 2; // {
+let c = \"{\";
+",
+    );
+
+    // braces in comments and strings don't need to be closed
+    assert_eq!(
+        output,
+        ">> a : i32
+>> .. .. 1
+>> b : i32
+>> error: string literals can't be lowered to MIR yet
+ --> <repl>:1:9
+>> \n"
+    );
+}
+
+#[test]
+fn commands() {
+    let output = repl(
+        "let a = 1;
+:type a == 1
+:mir a + 1
+:reset
+a
+:frobnicate
+",
+    );
+
+    assert_eq!(
+        output,
+        ">> a : i32
+>> a == 1 : bool
+>> FRAME regs(4)
+BLOCK(0)
+  0: load(reg) reg(1) reg(0)
+  1: load(u8) reg(2) 1
+  2: bin(Add) reg(3), reg(1), reg(2) @ 11..16
+     return
+ENDBLOCK(0)
+>> >> error: cannot find `a` in this scope
+ --> <repl>:1:1
+>> unknown command `:frobnicate`, try `:help`
+>> \n"
+    );
+}
+
+#[test]
+fn restores_bindings_after_a_panic() {
+    let output = repl(
+        "let x = 1;
+x = 2; panic \"no\";
+x
+let d = x / 0;
+d
+x = 3;
+x
+",
+    );
+
+    // nothing an input did is kept if it panics, not even the assignments before the panic
+    assert_eq!(
+        output,
+        ">> x : i32
+>> panicked at 'no', <repl>:1:8
+>> 1 : i32
+>> panicked at 'attempt to divide by zero', <repl>:1:9
+>> error: cannot find `d` in this scope
+ --> <repl>:1:1
+>> >> 3 : i32
+>> \n"
+    );
+}